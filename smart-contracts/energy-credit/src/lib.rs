@@ -1,5 +1,6 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use anchor_spl::token::spl_token::instruction::AuthorityType;
+use anchor_spl::token::{self, Mint, SetAuthority, Token, TokenAccount, Transfer};
 
 declare_id!("EnergyCredit11111111111111111111111111111111");
 
@@ -8,15 +9,32 @@ pub mod energy_credit {
     use super::*;
 
     /// Initialize the energy credit token
+    ///
+    /// Mint authority is handed to the program's `mint_authority` PDA, so minting
+    /// is only possible through `mint_credits` signed by `TokenInfo.authority`.
     pub fn initialize(
         ctx: Context<Initialize>,
         decimals: u8,
         name: String,
         symbol: String,
     ) -> Result<()> {
+        let cpi_accounts = SetAuthority {
+            current_authority: ctx.accounts.authority.to_account_info(),
+            account_or_mint: ctx.accounts.mint.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::set_authority(
+            cpi_ctx,
+            AuthorityType::MintTokens,
+            Some(ctx.accounts.mint_authority.key()),
+        )?;
+
         let token_info = &mut ctx.accounts.token_info;
         token_info.authority = ctx.accounts.authority.key();
+        token_info.pending_authority = Pubkey::default();
         token_info.mint = ctx.accounts.mint.key();
+        token_info.mint_authority_bump = *ctx.bumps.get("mint_authority").unwrap();
         token_info.decimals = decimals;
         token_info.name = name;
         token_info.symbol = symbol;
@@ -42,13 +60,20 @@ pub mod energy_credit {
             .ok_or(ErrorCode::Overflow)?;
 
         // Mint tokens to user account
+        let token_info_key = token_info.key();
+        let seeds = &[
+            b"mint_authority".as_ref(),
+            token_info_key.as_ref(),
+            &[token_info.mint_authority_bump],
+        ];
+        let signer = &[&seeds[..]];
         let cpi_accounts = token::MintTo {
             mint: ctx.accounts.mint.to_account_info(),
             to: ctx.accounts.user_token_account.to_account_info(),
-            authority: ctx.accounts.authority.to_account_info(),
+            authority: ctx.accounts.mint_authority.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
         token::mint_to(cpi_ctx, amount)?;
 
         // Record the energy production event
@@ -127,9 +152,57 @@ pub mod energy_credit {
         msg!("Burned {} energy credits for {} kWh consumed", amount, energy_consumed);
         Ok(())
     }
+
+    /// Propose a new token authority
+    ///
+    /// The transfer completes once the proposed authority calls `accept_authority`.
+    /// Proposing the default pubkey withdraws a pending proposal.
+    pub fn propose_authority(
+        ctx: Context<UpdateAuthority>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        let token_info = &mut ctx.accounts.token_info;
+        token_info.pending_authority = new_authority;
+
+        emit!(AuthorityProposedEvent {
+            token_info: token_info.key(),
+            authority: token_info.authority,
+            pending_authority: new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Token authority proposed: {}", new_authority);
+        Ok(())
+    }
+
+    /// Accept a proposed token authority transfer
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let token_info = &mut ctx.accounts.token_info;
+        let new_authority = ctx.accounts.new_authority.key();
+        require!(
+            token_info.pending_authority != Pubkey::default()
+                && token_info.pending_authority == new_authority,
+            ErrorCode::Unauthorized
+        );
+
+        let previous_authority = token_info.authority;
+        token_info.authority = new_authority;
+        token_info.pending_authority = Pubkey::default();
+
+        emit!(AuthorityTransferredEvent {
+            token_info: token_info.key(),
+            previous_authority,
+            new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Token authority transferred to {}", new_authority);
+        Ok(())
+    }
 }
 
 #[derive(Accounts)]
+#[instruction(decimals: u8)]
 pub struct Initialize<'info> {
     #[account(
         init,
@@ -138,24 +211,49 @@ pub struct Initialize<'info> {
     )]
     pub token_info: Account<'info, TokenInfo>,
     
-    #[account(mut)]
+    #[account(
+        mut,
+        mint::decimals = decimals,
+        mint::authority = authority
+    )]
     pub mint: Account<'info, Mint>,
     
+    /// CHECK: PDA that becomes the mint authority
+    #[account(
+        seeds = [b"mint_authority", token_info.key().as_ref()],
+        bump
+    )]
+    pub mint_authority: UncheckedAccount<'info>,
+    
+    /// Current mint authority of `mint`
     #[account(mut)]
     pub authority: Signer<'info>,
     
+    pub token_program: Program<'info, Token>,
+    
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
 pub struct MintCredits<'info> {
-    #[account(mut)]
+    #[account(mut, has_one = authority, has_one = mint)]
     pub token_info: Account<'info, TokenInfo>,
     
     #[account(mut)]
     pub mint: Account<'info, Mint>,
     
-    #[account(mut)]
+    /// CHECK: PDA holding the mint authority
+    #[account(
+        seeds = [b"mint_authority", token_info.key().as_ref()],
+        bump = token_info.mint_authority_bump
+    )]
+    pub mint_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = user
+    )]
     pub user_token_account: Account<'info, TokenAccount>,
     
     /// CHECK: User account receiving credits
@@ -169,10 +267,20 @@ pub struct MintCredits<'info> {
 
 #[derive(Accounts)]
 pub struct TransferCredits<'info> {
-    #[account(mut)]
+    pub token_info: Account<'info, TokenInfo>,
+    
+    #[account(
+        mut,
+        token::mint = token_info.mint,
+        token::authority = from_authority
+    )]
     pub from_token_account: Account<'info, TokenAccount>,
     
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = token_info.mint,
+        token::authority = to_authority
+    )]
     pub to_token_account: Account<'info, TokenAccount>,
     
     /// CHECK: Destination authority
@@ -185,13 +293,17 @@ pub struct TransferCredits<'info> {
 
 #[derive(Accounts)]
 pub struct BurnCredits<'info> {
-    #[account(mut)]
+    #[account(mut, has_one = mint)]
     pub token_info: Account<'info, TokenInfo>,
     
     #[account(mut)]
     pub mint: Account<'info, Mint>,
     
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = user
+    )]
     pub user_token_account: Account<'info, TokenAccount>,
     
     pub user: Signer<'info>,
@@ -199,11 +311,30 @@ pub struct BurnCredits<'info> {
     pub token_program: Program<'info, Token>,
 }
 
+#[derive(Accounts)]
+pub struct UpdateAuthority<'info> {
+    #[account(mut, has_one = authority)]
+    pub token_info: Account<'info, TokenInfo>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(mut)]
+    pub token_info: Account<'info, TokenInfo>,
+    
+    pub new_authority: Signer<'info>,
+}
+
 #[account]
 #[derive(InitSpace)]
 pub struct TokenInfo {
     pub authority: Pubkey,
+    /// Authority proposed by `propose_authority`, awaiting acceptance
+    pub pending_authority: Pubkey,
     pub mint: Pubkey,
+    pub mint_authority_bump: u8,
     pub decimals: u8,
     #[max_len(32)]
     pub name: String,
@@ -240,6 +371,22 @@ pub struct CreditTransferEvent {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct AuthorityProposedEvent {
+    pub token_info: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityTransferredEvent {
+    pub token_info: Pubkey,
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub timestamp: i64,
+}
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("Invalid amount specified")]
@@ -250,4 +397,6 @@ pub enum ErrorCode {
     Overflow,
     #[msg("Insufficient token supply")]
     InsufficientSupply,
+    #[msg("Unauthorized access")]
+    Unauthorized,
 }
@@ -0,0 +1,296 @@
+//! Account validation of the credit instructions when an account is substituted
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::system_program;
+use anchor_spl::token::spl_token;
+use anchor_spl::token::spl_token::state::AccountState;
+use energy_credit::{BurnCredits, MintCredits, TokenInfo, TransferCredits};
+
+struct TestAccount {
+    key: Pubkey,
+    owner: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+    is_signer: bool,
+    executable: bool,
+}
+
+impl TestAccount {
+    fn new(owner: Pubkey, data: Vec<u8>) -> Self {
+        Self {
+            key: Pubkey::new_unique(),
+            owner,
+            lamports: 1_000_000_000,
+            data,
+            is_signer: false,
+            executable: false,
+        }
+    }
+
+    fn wallet() -> Self {
+        Self::new(system_program::ID, Vec::new())
+    }
+
+    fn signer() -> Self {
+        Self { is_signer: true, ..Self::wallet() }
+    }
+
+    fn program(id: Pubkey) -> Self {
+        Self { key: id, executable: true, ..Self::new(Pubkey::default(), Vec::new()) }
+    }
+
+    fn anchor<T: AccountSerialize + Owner>(value: &T) -> Self {
+        let mut data = Vec::new();
+        value.try_serialize(&mut data).unwrap();
+        Self::new(T::owner(), data)
+    }
+
+    fn mint(authority: Pubkey) -> Self {
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        let mint = spl_token::state::Mint {
+            mint_authority: COption::Some(authority),
+            supply: 0,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        };
+        spl_token::state::Mint::pack(mint, &mut data).unwrap();
+        Self::new(spl_token::ID, data)
+    }
+
+    fn token_account(mint: Pubkey, owner: Pubkey) -> Self {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        let account = spl_token::state::Account {
+            mint,
+            owner,
+            state: AccountState::Initialized,
+            ..Default::default()
+        };
+        spl_token::state::Account::pack(account, &mut data).unwrap();
+        Self::new(spl_token::ID, data)
+    }
+
+    fn update<T: AccountSerialize + AccountDeserialize>(&mut self, change: impl FnOnce(&mut T)) {
+        let mut value = T::try_deserialize(&mut &self.data[..]).unwrap();
+        change(&mut value);
+        self.data.clear();
+        value.try_serialize(&mut self.data).unwrap();
+    }
+
+    fn info(&mut self) -> AccountInfo<'_> {
+        AccountInfo::new(
+            &self.key,
+            self.is_signer,
+            true,
+            &mut self.lamports,
+            &mut self.data,
+            &self.owner,
+            self.executable,
+            0,
+        )
+    }
+}
+
+fn try_accounts<'info, T: Accounts<'info>>(accounts: &'info mut [TestAccount]) -> Result<()> {
+    let infos: Vec<AccountInfo<'info>> = accounts.iter_mut().map(TestAccount::info).collect();
+    T::try_accounts(
+        &energy_credit::ID,
+        &mut &infos[..],
+        &[],
+        &mut BTreeMap::new(),
+        &mut BTreeSet::new(),
+    )
+    .map(|_| ())
+}
+
+fn assert_rejected(result: Result<()>, expected: impl Into<u32>) {
+    match result {
+        Ok(()) => panic!("substituted account was accepted"),
+        Err(err) => assert_eq!(ProgramError::from(err), ProgramError::Custom(expected.into())),
+    }
+}
+
+/// Credit token with a holder, in the order the instructions take them
+struct Credits {
+    token_info: TestAccount,
+    mint: TestAccount,
+    mint_authority: TestAccount,
+    user_token_account: TestAccount,
+    user: TestAccount,
+    authority: TestAccount,
+}
+
+impl Credits {
+    fn new() -> Self {
+        let authority = TestAccount::signer();
+        let user = TestAccount::signer();
+        let mut token_info = TestAccount::wallet();
+        let (mint_authority, mint_authority_bump) = Pubkey::find_program_address(
+            &[b"mint_authority", token_info.key.as_ref()],
+            &energy_credit::ID,
+        );
+        let mint = TestAccount::mint(mint_authority);
+        let info = TokenInfo {
+            authority: authority.key,
+            pending_authority: Pubkey::default(),
+            mint: mint.key,
+            mint_authority_bump,
+            decimals: 6,
+            name: "Energy Credit".to_string(),
+            symbol: "EC".to_string(),
+            total_supply: 0,
+        };
+        token_info = TestAccount { key: token_info.key, ..TestAccount::anchor(&info) };
+        Self {
+            user_token_account: TestAccount::token_account(mint.key, user.key),
+            mint_authority: TestAccount { key: mint_authority, ..TestAccount::wallet() },
+            token_info,
+            mint,
+            user,
+            authority,
+        }
+    }
+
+    fn mint_credits(self) -> Result<()> {
+        let mut accounts = [
+            self.token_info,
+            self.mint,
+            self.mint_authority,
+            self.user_token_account,
+            self.user,
+            self.authority,
+            TestAccount::program(spl_token::ID),
+        ];
+        try_accounts::<MintCredits>(&mut accounts)
+    }
+
+    fn burn_credits(self) -> Result<()> {
+        let mut accounts = [
+            self.token_info,
+            self.mint,
+            self.user_token_account,
+            self.user,
+            TestAccount::program(spl_token::ID),
+        ];
+        try_accounts::<BurnCredits>(&mut accounts)
+    }
+
+    fn transfer_credits(
+        self,
+        to_token_account: TestAccount,
+        to_authority: TestAccount,
+    ) -> Result<()> {
+        let mut accounts = [
+            self.token_info,
+            self.user_token_account,
+            to_token_account,
+            to_authority,
+            self.user,
+            TestAccount::program(spl_token::ID),
+        ];
+        try_accounts::<TransferCredits>(&mut accounts)
+    }
+}
+
+#[test]
+fn mint_credits_accepts_matching_accounts() {
+    Credits::new().mint_credits().unwrap();
+}
+
+#[test]
+fn mint_credits_rejects_signer_other_than_token_authority() {
+    let credits = Credits { authority: TestAccount::signer(), ..Credits::new() };
+    assert_rejected(credits.mint_credits(), ErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn mint_credits_rejects_token_info_of_another_mint() {
+    let mut credits = Credits::new();
+    credits.token_info.update(|info: &mut TokenInfo| info.mint = Pubkey::new_unique());
+    assert_rejected(credits.mint_credits(), ErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn mint_credits_rejects_mint_authority_off_its_address() {
+    let credits = Credits { mint_authority: TestAccount::wallet(), ..Credits::new() };
+    assert_rejected(credits.mint_credits(), ErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn mint_credits_rejects_user_account_of_another_mint() {
+    let credits = Credits::new();
+    let user_token_account = TestAccount::token_account(Pubkey::new_unique(), credits.user.key);
+    let credits = Credits { user_token_account, ..credits };
+    assert_rejected(credits.mint_credits(), ErrorCode::ConstraintTokenMint);
+}
+
+#[test]
+fn mint_credits_rejects_user_account_of_another_owner() {
+    let credits = Credits::new();
+    let user_token_account = TestAccount::token_account(credits.mint.key, Pubkey::new_unique());
+    let credits = Credits { user_token_account, ..credits };
+    assert_rejected(credits.mint_credits(), ErrorCode::ConstraintTokenOwner);
+}
+
+#[test]
+fn burn_credits_accepts_matching_accounts() {
+    Credits::new().burn_credits().unwrap();
+}
+
+#[test]
+fn burn_credits_rejects_another_mint() {
+    let credits = Credits { mint: TestAccount::mint(Pubkey::new_unique()), ..Credits::new() };
+    assert_rejected(credits.burn_credits(), ErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn burn_credits_rejects_user_account_of_another_mint() {
+    let credits = Credits::new();
+    let user_token_account = TestAccount::token_account(Pubkey::new_unique(), credits.user.key);
+    let credits = Credits { user_token_account, ..credits };
+    assert_rejected(credits.burn_credits(), ErrorCode::ConstraintTokenMint);
+}
+
+#[test]
+fn burn_credits_rejects_user_account_of_another_owner() {
+    let credits = Credits::new();
+    let user_token_account = TestAccount::token_account(credits.mint.key, Pubkey::new_unique());
+    let credits = Credits { user_token_account, ..credits };
+    assert_rejected(credits.burn_credits(), ErrorCode::ConstraintTokenOwner);
+}
+
+#[test]
+fn burn_credits_rejects_unsigned_user() {
+    let credits = Credits::new();
+    let user = TestAccount { is_signer: false, ..credits.user };
+    let credits = Credits { user, ..credits };
+    assert_rejected(credits.burn_credits(), ErrorCode::AccountNotSigner);
+}
+
+#[test]
+fn transfer_credits_accepts_matching_accounts() {
+    let credits = Credits::new();
+    let to_authority = TestAccount::wallet();
+    let to_token_account = TestAccount::token_account(credits.mint.key, to_authority.key);
+    credits.transfer_credits(to_token_account, to_authority).unwrap();
+}
+
+#[test]
+fn transfer_credits_rejects_destination_of_another_mint() {
+    let to_authority = TestAccount::wallet();
+    let to_token_account = TestAccount::token_account(Pubkey::new_unique(), to_authority.key);
+    let result = Credits::new().transfer_credits(to_token_account, to_authority);
+    assert_rejected(result, ErrorCode::ConstraintTokenMint);
+}
+
+#[test]
+fn transfer_credits_rejects_destination_of_another_owner() {
+    let credits = Credits::new();
+    let to_token_account = TestAccount::token_account(credits.mint.key, Pubkey::new_unique());
+    let result = credits.transfer_credits(to_token_account, TestAccount::wallet());
+    assert_rejected(result, ErrorCode::ConstraintTokenOwner);
+}
@@ -0,0 +1,407 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program::invoke_signed;
+
+declare_id!("EnergyGovernance111111111111111111111111111");
+
+/// Maximum number of owners of a multisig
+pub const MAX_OWNERS: usize = 10;
+/// Maximum number of accounts a proposed instruction can reference
+pub const MAX_PROPOSAL_ACCOUNTS: usize = 32;
+/// Maximum size of a proposed instruction's data
+pub const MAX_PROPOSAL_DATA: usize = 1_024;
+
+/// M-of-N multisig governance
+///
+/// Each multisig controls a signer PDA, `[multisig]`, which can be set as the
+/// authority of a market, the oracle or the energy credit token. Owners propose
+/// an instruction, approve it, and once `threshold` owners have approved anyone
+/// can execute it with the signer PDA signing.
+#[program]
+pub mod energy_governance {
+    use super::*;
+
+    /// Create a multisig with the given owners and approval threshold
+    pub fn create_multisig(
+        ctx: Context<CreateMultisig>,
+        owners: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        validate_owners(&owners, threshold)?;
+
+        let multisig = &mut ctx.accounts.multisig;
+        let (_, signer_bump) =
+            Pubkey::find_program_address(&[multisig.key().as_ref()], ctx.program_id);
+        multisig.owners = owners;
+        multisig.threshold = threshold;
+        multisig.signer_bump = signer_bump;
+        multisig.owner_set_seqno = 0;
+        multisig.proposal_count = 0;
+
+        emit!(MultisigCreatedEvent {
+            multisig: multisig.key(),
+            signer: multisig.signer(multisig.key())?,
+            owners: multisig.owners.clone(),
+            threshold,
+        });
+
+        msg!("Multisig created: {} of {}", threshold, multisig.owners.len());
+        Ok(())
+    }
+
+    /// Propose an instruction for the multisig signer to execute
+    ///
+    /// The proposer must be an owner and counts as the first approval.
+    pub fn create_proposal(
+        ctx: Context<CreateProposal>,
+        program_id: Pubkey,
+        accounts: Vec<ProposalAccount>,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        require!(accounts.len() <= MAX_PROPOSAL_ACCOUNTS, ErrorCode::ProposalTooLarge);
+        require!(data.len() <= MAX_PROPOSAL_DATA, ErrorCode::ProposalTooLarge);
+
+        let multisig = &mut ctx.accounts.multisig;
+        let owner_index = multisig.owner_index(&ctx.accounts.proposer.key())?;
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.multisig = multisig.key();
+        proposal.proposer = ctx.accounts.proposer.key();
+        proposal.program_id = program_id;
+        proposal.accounts = accounts;
+        proposal.data = data;
+        proposal.approvals = vec![false; multisig.owners.len()];
+        proposal.approvals[owner_index] = true;
+        proposal.executed = false;
+        proposal.owner_set_seqno = multisig.owner_set_seqno;
+        proposal.index = multisig.proposal_count;
+        proposal.bump = *ctx.bumps.get("proposal").unwrap();
+
+        multisig.proposal_count += 1;
+
+        emit!(ProposalCreatedEvent {
+            multisig: multisig.key(),
+            proposal: proposal.key(),
+            proposer: proposal.proposer,
+            program_id,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Proposal {} created", proposal.index);
+        Ok(())
+    }
+
+    /// Approve a pending proposal
+    pub fn approve_proposal(ctx: Context<ApproveProposal>) -> Result<()> {
+        let multisig = &ctx.accounts.multisig;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(!proposal.executed, ErrorCode::AlreadyExecuted);
+        require!(
+            proposal.owner_set_seqno == multisig.owner_set_seqno,
+            ErrorCode::OwnersChanged
+        );
+
+        let owner_index = multisig.owner_index(&ctx.accounts.owner.key())?;
+        proposal.approvals[owner_index] = true;
+
+        emit!(ProposalApprovedEvent {
+            proposal: proposal.key(),
+            owner: ctx.accounts.owner.key(),
+            approvals: proposal.approval_count() as u8,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Proposal {} approved: {} of {}", proposal.index, proposal.approval_count(), multisig.threshold);
+        Ok(())
+    }
+
+    /// Execute a proposal that reached the approval threshold
+    ///
+    /// Remaining accounts are the proposal's accounts followed by the target program.
+    pub fn execute_proposal<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteProposal<'info>>,
+    ) -> Result<()> {
+        let multisig = &ctx.accounts.multisig;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(!proposal.executed, ErrorCode::AlreadyExecuted);
+        require!(
+            proposal.owner_set_seqno == multisig.owner_set_seqno,
+            ErrorCode::OwnersChanged
+        );
+        require!(
+            proposal.approval_count() >= multisig.threshold as usize,
+            ErrorCode::NotEnoughApprovals
+        );
+
+        // Mark the proposal executed before running it so it cannot be replayed
+        proposal.executed = true;
+        proposal.exit(&crate::ID)?;
+
+        let multisig_key = multisig.key();
+        let signer_key = multisig.signer(multisig_key)?;
+        let instruction = Instruction {
+            program_id: proposal.program_id,
+            accounts: proposal
+                .accounts
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: account.pubkey,
+                    is_signer: account.is_signer || account.pubkey == signer_key,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: proposal.data.clone(),
+        };
+        let seeds = &[multisig_key.as_ref(), &[multisig.signer_bump]];
+        let signer = &[&seeds[..]];
+        invoke_signed(&instruction, ctx.remaining_accounts, signer)?;
+
+        emit!(ProposalExecutedEvent {
+            multisig: multisig_key,
+            proposal: proposal.key(),
+            executor: ctx.accounts.executor.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Proposal {} executed", proposal.index);
+        Ok(())
+    }
+
+    /// Replace the owners and threshold (only through an executed proposal)
+    ///
+    /// Pending proposals made under the previous owner set can no longer be approved
+    /// or executed.
+    pub fn change_owners(
+        ctx: Context<ChangeOwners>,
+        owners: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        validate_owners(&owners, threshold)?;
+
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.owners = owners;
+        multisig.threshold = threshold;
+        multisig.owner_set_seqno += 1;
+
+        emit!(OwnersChangedEvent {
+            multisig: multisig.key(),
+            owners: multisig.owners.clone(),
+            threshold,
+            owner_set_seqno: multisig.owner_set_seqno,
+        });
+
+        msg!("Multisig owners changed: {} of {}", threshold, multisig.owners.len());
+        Ok(())
+    }
+}
+
+/// Check an owner set has no duplicates and a reachable, non-zero threshold
+pub fn validate_owners(owners: &[Pubkey], threshold: u8) -> Result<()> {
+    require!(
+        !owners.is_empty() && owners.len() <= MAX_OWNERS,
+        ErrorCode::InvalidOwners
+    );
+    for (i, owner) in owners.iter().enumerate() {
+        require!(!owners[..i].contains(owner), ErrorCode::InvalidOwners);
+    }
+    require!(
+        threshold > 0 && threshold as usize <= owners.len(),
+        ErrorCode::InvalidThreshold
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateMultisig<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Multisig::INIT_SPACE
+    )]
+    pub multisig: Account<'info, Multisig>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(program_id: Pubkey, accounts: Vec<ProposalAccount>, data: Vec<u8>)]
+pub struct CreateProposal<'info> {
+    #[account(mut)]
+    pub multisig: Account<'info, Multisig>,
+    
+    #[account(
+        init,
+        payer = proposer,
+        space = Proposal::space(multisig.owners.len(), accounts.len(), data.len()),
+        seeds = [b"proposal", multisig.key().as_ref(), &multisig.proposal_count.to_le_bytes()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveProposal<'info> {
+    pub multisig: Account<'info, Multisig>,
+    
+    #[account(mut, has_one = multisig)]
+    pub proposal: Account<'info, Proposal>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteProposal<'info> {
+    pub multisig: Account<'info, Multisig>,
+    
+    #[account(mut, has_one = multisig)]
+    pub proposal: Account<'info, Proposal>,
+    
+    pub executor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ChangeOwners<'info> {
+    #[account(mut)]
+    pub multisig: Account<'info, Multisig>,
+    
+    #[account(
+        seeds = [multisig.key().as_ref()],
+        bump = multisig.signer_bump
+    )]
+    pub multisig_signer: Signer<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Multisig {
+    #[max_len(10)]
+    pub owners: Vec<Pubkey>,
+    pub threshold: u8,
+    /// Bump of the signer PDA, `[multisig]`
+    pub signer_bump: u8,
+    /// Incremented whenever the owners change, invalidating pending proposals
+    pub owner_set_seqno: u32,
+    pub proposal_count: u64,
+}
+
+impl Multisig {
+    /// Address of the PDA that signs for this multisig
+    pub fn signer(&self, multisig_key: Pubkey) -> Result<Pubkey> {
+        Pubkey::create_program_address(&[multisig_key.as_ref(), &[self.signer_bump]], &crate::ID)
+            .map_err(|_| error!(ErrorCode::InvalidOwners))
+    }
+
+    /// Position of `owner` in the owner set
+    pub fn owner_index(&self, owner: &Pubkey) -> Result<usize> {
+        self.owners
+            .iter()
+            .position(|o| o == owner)
+            .ok_or_else(|| error!(ErrorCode::NotAnOwner))
+    }
+}
+
+/// An instruction awaiting multisig approval
+#[account]
+pub struct Proposal {
+    pub multisig: Pubkey,
+    pub proposer: Pubkey,
+    pub program_id: Pubkey,
+    pub accounts: Vec<ProposalAccount>,
+    pub data: Vec<u8>,
+    /// Approval flag per owner, in owner order
+    pub approvals: Vec<bool>,
+    pub executed: bool,
+    /// Owner set the proposal was made under
+    pub owner_set_seqno: u32,
+    pub index: u64,
+    pub bump: u8,
+}
+
+impl Proposal {
+    /// Account size for a proposal with the given number of owners, accounts and data bytes
+    pub fn space(owners: usize, accounts: usize, data: usize) -> usize {
+        8 + 32 * 3
+            + 4 + accounts * ProposalAccount::INIT_SPACE
+            + 4 + data
+            + 4 + owners
+            + 1 + 4 + 8 + 1
+    }
+
+    pub fn approval_count(&self) -> usize {
+        self.approvals.iter().filter(|approved| **approved).count()
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct ProposalAccount {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+#[event]
+pub struct MultisigCreatedEvent {
+    pub multisig: Pubkey,
+    pub signer: Pubkey,
+    pub owners: Vec<Pubkey>,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct ProposalCreatedEvent {
+    pub multisig: Pubkey,
+    pub proposal: Pubkey,
+    pub proposer: Pubkey,
+    pub program_id: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalApprovedEvent {
+    pub proposal: Pubkey,
+    pub owner: Pubkey,
+    pub approvals: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalExecutedEvent {
+    pub multisig: Pubkey,
+    pub proposal: Pubkey,
+    pub executor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OwnersChangedEvent {
+    pub multisig: Pubkey,
+    pub owners: Vec<Pubkey>,
+    pub threshold: u8,
+    pub owner_set_seqno: u32,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Owners must be unique and at most MAX_OWNERS")]
+    InvalidOwners,
+    #[msg("Threshold must be between 1 and the number of owners")]
+    InvalidThreshold,
+    #[msg("Signer is not an owner of this multisig")]
+    NotAnOwner,
+    #[msg("Proposal exceeds the maximum instruction size")]
+    ProposalTooLarge,
+    #[msg("Proposal has already been executed")]
+    AlreadyExecuted,
+    #[msg("Multisig owners changed since the proposal was made")]
+    OwnersChanged,
+    #[msg("Not enough owners approved the proposal")]
+    NotEnoughApprovals,
+}
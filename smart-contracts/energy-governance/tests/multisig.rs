@@ -0,0 +1,393 @@
+//! Approval, execution and owner changes of a multisig, with proposals executed
+//! through a stand-in runtime
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Once;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::{ProgramResult, SUCCESS};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use anchor_lang::system_program;
+use anchor_lang::InstructionData;
+use energy_governance::{
+    ApproveProposal, ChangeOwners, ErrorCode, ExecuteProposal, Multisig, Proposal,
+    ProposalAccount,
+};
+
+const NOW: i64 = 1_700_000_000;
+
+thread_local! {
+    /// Instructions proposals invoked on programs other than governance
+    static INVOKED: RefCell<Vec<Instruction>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Stands in for the runtime pieces the instructions use: the clock sysvar (reading
+/// `NOW`) and cross-program invocation, which checks signers, runs governance
+/// instructions and records any other instruction in `INVOKED`
+struct Runtime;
+
+impl SyscallStubs for Runtime {
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Clock) = Clock { unix_timestamp: NOW, ..Clock::default() } };
+        SUCCESS
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let signers: Vec<Pubkey> = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &energy_governance::ID).unwrap())
+            .collect();
+        let mut infos = Vec::new();
+        for meta in &instruction.accounts {
+            let mut info = account_infos
+                .iter()
+                .find(|info| *info.key == meta.pubkey)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?
+                .clone();
+            if meta.is_signer && !info.is_signer && !signers.contains(&meta.pubkey) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            info.is_signer = meta.is_signer;
+            infos.push(info);
+        }
+
+        if instruction.program_id == energy_governance::ID {
+            energy_governance::entry(&instruction.program_id, &infos, &instruction.data)
+        } else {
+            INVOKED.with(|invoked| invoked.borrow_mut().push(instruction.clone()));
+            Ok(())
+        }
+    }
+}
+
+struct TestAccount {
+    key: Pubkey,
+    owner: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+    is_signer: bool,
+    executable: bool,
+}
+
+impl TestAccount {
+    fn new(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> Self {
+        Self { key, owner, lamports: 1_000_000_000, data, is_signer: false, executable: false }
+    }
+
+    fn wallet(key: Pubkey) -> Self {
+        Self::new(key, system_program::ID, Vec::new())
+    }
+
+    fn signer(key: Pubkey) -> Self {
+        Self { is_signer: true, ..Self::wallet(key) }
+    }
+
+    fn program(key: Pubkey) -> Self {
+        Self { executable: true, ..Self::new(key, Pubkey::default(), Vec::new()) }
+    }
+
+    /// Account holding `value` in an allocation of `space` bytes
+    fn anchor<T: AccountSerialize + Owner>(key: Pubkey, value: &T, space: usize) -> Self {
+        let mut data = Vec::new();
+        value.try_serialize(&mut data).unwrap();
+        data.resize(space, 0);
+        Self::new(key, T::owner(), data)
+    }
+
+    fn read<T: AccountDeserialize>(&self) -> T {
+        T::try_deserialize(&mut &self.data[..]).unwrap()
+    }
+
+    fn info(&mut self) -> AccountInfo<'_> {
+        AccountInfo::new(
+            &self.key,
+            self.is_signer,
+            true,
+            &mut self.lamports,
+            &mut self.data,
+            &self.owner,
+            self.executable,
+            0,
+        )
+    }
+}
+
+fn install_runtime() {
+    static RUNTIME: Once = Once::new();
+    RUNTIME.call_once(|| {
+        set_syscall_stubs(Box::new(Runtime));
+    });
+}
+
+fn assert_rejected(result: Result<()>, expected: impl Into<u32>) {
+    match result {
+        Ok(()) => panic!("instruction succeeded"),
+        Err(err) => assert_eq!(ProgramError::from(err), ProgramError::Custom(expected.into())),
+    }
+}
+
+fn invoked() -> Vec<Instruction> {
+    INVOKED.with(|invoked| invoked.borrow().clone())
+}
+
+/// A 2-of-3 multisig and the program its proposals call
+struct Governance {
+    owners: [Pubkey; 3],
+    multisig: TestAccount,
+    signer: Pubkey,
+    target: Pubkey,
+}
+
+impl Governance {
+    fn new() -> Self {
+        let owners = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let key = Pubkey::new_unique();
+        let (signer, signer_bump) =
+            Pubkey::find_program_address(&[key.as_ref()], &energy_governance::ID);
+        let multisig = Multisig {
+            owners: owners.to_vec(),
+            threshold: 2,
+            signer_bump,
+            owner_set_seqno: 0,
+            proposal_count: 0,
+        };
+        Self {
+            owners,
+            multisig: TestAccount::anchor(key, &multisig, 8 + Multisig::INIT_SPACE),
+            signer,
+            target: Pubkey::new_unique(),
+        }
+    }
+
+    /// Proposal by the first owner to run `data` on `program_id` over `accounts`
+    fn propose(
+        &self,
+        program_id: Pubkey,
+        accounts: Vec<AccountMeta>,
+        data: Vec<u8>,
+    ) -> TestAccount {
+        let multisig: Multisig = self.multisig.read();
+        let accounts: Vec<ProposalAccount> = accounts
+            .into_iter()
+            .map(|meta| ProposalAccount {
+                pubkey: meta.pubkey,
+                is_signer: meta.is_signer,
+                is_writable: meta.is_writable,
+            })
+            .collect();
+        let mut approvals = vec![false; multisig.owners.len()];
+        approvals[0] = true;
+        let space = Proposal::space(approvals.len(), accounts.len(), data.len());
+        let proposal = Proposal {
+            multisig: self.multisig.key,
+            proposer: multisig.owners[0],
+            program_id,
+            accounts,
+            data,
+            approvals,
+            executed: false,
+            owner_set_seqno: multisig.owner_set_seqno,
+            index: multisig.proposal_count,
+            bump: 255,
+        };
+        TestAccount::anchor(Pubkey::new_unique(), &proposal, space)
+    }
+
+    /// Proposal calling the target program with the multisig signer
+    fn propose_call(&self) -> TestAccount {
+        let accounts = vec![AccountMeta::new_readonly(self.signer, false)];
+        self.propose(self.target, accounts, vec![1, 2, 3])
+    }
+
+    /// Proposal replacing the owners with `owners`, requiring `threshold` approvals
+    fn propose_owners(&self, owners: Vec<Pubkey>, threshold: u8) -> TestAccount {
+        let accounts = energy_governance::accounts::ChangeOwners {
+            multisig: self.multisig.key,
+            multisig_signer: self.signer,
+        };
+        let data = energy_governance::instruction::ChangeOwners { owners, threshold }.data();
+        self.propose(energy_governance::ID, accounts.to_account_metas(None), data)
+    }
+
+    fn approve(&mut self, proposal: &mut TestAccount, owner: Pubkey) -> Result<()> {
+        install_runtime();
+        let mut owner = TestAccount::signer(owner);
+        let infos = [self.multisig.info(), proposal.info(), owner.info()];
+        let mut approve = ApproveProposal::try_accounts(
+            &energy_governance::ID,
+            &mut &infos[..],
+            &[],
+            &mut BTreeMap::new(),
+            &mut BTreeSet::new(),
+        )?;
+        energy_governance::energy_governance::approve_proposal(Context::new(
+            &energy_governance::ID,
+            &mut approve,
+            &[],
+            BTreeMap::new(),
+        ))?;
+        approve.exit(&energy_governance::ID)
+    }
+
+    /// Execute `proposal`, passing the multisig, its signer and the target program
+    /// as remaining accounts
+    fn execute(&mut self, proposal: &mut TestAccount) -> Result<()> {
+        install_runtime();
+        let mut executor = TestAccount::signer(Pubkey::new_unique());
+        let mut signer = TestAccount::wallet(self.signer);
+        let mut target = TestAccount::program(self.target);
+        let mut governance = TestAccount::program(energy_governance::ID);
+        let infos = [
+            self.multisig.info(),
+            proposal.info(),
+            executor.info(),
+            signer.info(),
+            target.info(),
+            governance.info(),
+        ];
+        let mut execute = ExecuteProposal::try_accounts(
+            &energy_governance::ID,
+            &mut &infos[..3],
+            &[],
+            &mut BTreeMap::new(),
+            &mut BTreeSet::new(),
+        )?;
+        let remaining = [infos[0].clone(), infos[3].clone(), infos[4].clone(), infos[5].clone()];
+        energy_governance::energy_governance::execute_proposal(Context::new(
+            &energy_governance::ID,
+            &mut execute,
+            &remaining,
+            BTreeMap::new(),
+        ))?;
+        execute.exit(&energy_governance::ID)
+    }
+
+    /// Validate `ChangeOwners` with `signer` standing in for the multisig signer
+    fn change_owners_accounts(&mut self, signer: &mut TestAccount) -> Result<()> {
+        let infos = [self.multisig.info(), signer.info()];
+        ChangeOwners::try_accounts(
+            &energy_governance::ID,
+            &mut &infos[..],
+            &[],
+            &mut BTreeMap::new(),
+            &mut BTreeSet::new(),
+        )
+        .map(|_| ())
+    }
+}
+
+#[test]
+fn execute_proposal_waits_for_threshold_approvals() {
+    let mut governance = Governance::new();
+    let mut proposal = governance.propose_call();
+
+    assert_rejected(governance.execute(&mut proposal), ErrorCode::NotEnoughApprovals);
+    // The proposer approving again still counts once
+    let proposer = governance.owners[0];
+    governance.approve(&mut proposal, proposer).unwrap();
+    assert_rejected(governance.execute(&mut proposal), ErrorCode::NotEnoughApprovals);
+    assert!(invoked().is_empty());
+
+    let second = governance.owners[2];
+    governance.approve(&mut proposal, second).unwrap();
+    governance.execute(&mut proposal).unwrap();
+
+    let invoked = invoked();
+    assert_eq!(invoked.len(), 1);
+    assert_eq!(invoked[0].program_id, governance.target);
+    assert_eq!(invoked[0].data, [1, 2, 3]);
+    assert_eq!(invoked[0].accounts, [AccountMeta::new_readonly(governance.signer, true)]);
+}
+
+#[test]
+fn approve_proposal_rejects_non_owner() {
+    let mut governance = Governance::new();
+    let mut proposal = governance.propose_call();
+    assert_rejected(
+        governance.approve(&mut proposal, Pubkey::new_unique()),
+        ErrorCode::NotAnOwner,
+    );
+}
+
+#[test]
+fn executed_proposal_cannot_be_replayed() {
+    let mut governance = Governance::new();
+    let mut proposal = governance.propose_call();
+    let second = governance.owners[1];
+    governance.approve(&mut proposal, second).unwrap();
+    governance.execute(&mut proposal).unwrap();
+    assert!(proposal.read::<Proposal>().executed);
+
+    assert_rejected(governance.execute(&mut proposal), ErrorCode::AlreadyExecuted);
+    let third = governance.owners[2];
+    assert_rejected(governance.approve(&mut proposal, third), ErrorCode::AlreadyExecuted);
+    assert_eq!(invoked().len(), 1);
+}
+
+#[test]
+fn change_owners_invalidates_pending_proposals() {
+    let mut governance = Governance::new();
+    let mut pending = governance.propose_call();
+    let second = governance.owners[1];
+    governance.approve(&mut pending, second).unwrap();
+
+    let new_owners = vec![governance.owners[0], Pubkey::new_unique()];
+    let mut change = governance.propose_owners(new_owners.clone(), 1);
+    governance.approve(&mut change, second).unwrap();
+    governance.execute(&mut change).unwrap();
+
+    let multisig: Multisig = governance.multisig.read();
+    assert_eq!(multisig.owners, new_owners);
+    assert_eq!(multisig.threshold, 1);
+    assert_eq!(multisig.owner_set_seqno, 1);
+
+    // The pending proposal had enough approvals under the previous owners
+    assert_rejected(governance.execute(&mut pending), ErrorCode::OwnersChanged);
+    let owner = new_owners[1];
+    assert_rejected(governance.approve(&mut pending, owner), ErrorCode::OwnersChanged);
+    assert!(invoked().is_empty());
+}
+
+#[test]
+fn change_owners_accepts_only_the_multisig_signer() {
+    let mut governance = Governance::new();
+    let mut signer = TestAccount::signer(governance.signer);
+    governance.change_owners_accounts(&mut signer).unwrap();
+
+    let mut owner = TestAccount::signer(governance.owners[0]);
+    assert_rejected(
+        governance.change_owners_accounts(&mut owner),
+        anchor_lang::error::ErrorCode::ConstraintSeeds,
+    );
+
+    let mut unsigned = TestAccount::wallet(governance.signer);
+    assert_rejected(
+        governance.change_owners_accounts(&mut unsigned),
+        anchor_lang::error::ErrorCode::AccountNotSigner,
+    );
+
+    let other = Governance::new();
+    let mut other_signer = TestAccount::signer(other.signer);
+    assert_rejected(
+        governance.change_owners_accounts(&mut other_signer),
+        anchor_lang::error::ErrorCode::ConstraintSeeds,
+    );
+}
+
+#[test]
+fn change_owners_proposal_needs_threshold_approvals() {
+    let mut governance = Governance::new();
+    let mut change = governance.propose_owners(vec![governance.owners[0]], 1);
+    assert_rejected(governance.execute(&mut change), ErrorCode::NotEnoughApprovals);
+
+    let multisig: Multisig = governance.multisig.read();
+    assert_eq!(multisig.owners, governance.owners);
+    assert_eq!(multisig.owner_set_seqno, 0);
+}
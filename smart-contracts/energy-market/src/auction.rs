@@ -0,0 +1,634 @@
+//! Uniform-price call auctions
+
+use super::*;
+
+pub(crate) fn open_auction(
+    ctx: Context<OpenAuction>,
+    delivery_start: i64,
+    delivery_end: i64,
+    gate_closure: i64,
+) -> Result<()> {
+    let market = &ctx.accounts.market;
+    require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+    require!(
+        gate_closure > Clock::get()?.unix_timestamp
+            && gate_closure <= delivery_start
+            && delivery_start < delivery_end,
+        ErrorCode::InvalidAuctionWindow
+    );
+
+    let mut auction = ctx.accounts.auction.load_init()?;
+    auction.market = market.key();
+    auction.delivery_start = delivery_start;
+    auction.delivery_end = delivery_end;
+    auction.gate_closure = gate_closure;
+    auction.bump = *ctx.bumps.get("auction").unwrap();
+
+    emit!(AuctionOpenedEvent {
+        auction: ctx.accounts.auction.key(),
+        delivery_start,
+        delivery_end,
+        gate_closure,
+    });
+
+    msg!("Auction opened for interval {} - {}", delivery_start, delivery_end);
+    Ok(())
+}
+
+pub(crate) fn submit_auction_order(
+    ctx: Context<SubmitAuctionOrder>,
+    side: Side,
+    price_per_kwh: u64,
+    energy_amount: u64,
+) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_TRADING)?;
+
+    require!(energy_amount > 0, ErrorCode::InvalidAmount);
+    require!(price_per_kwh > 0, ErrorCode::InvalidPrice);
+    // The book and auctions have no notion of grid zones
+    require!(ctx.accounts.market.zone_count == 0, ErrorCode::ZonedTradingUnsupported);
+
+    let mut auction = ctx.accounts.auction.load_mut()?;
+    require!(auction.market == ctx.accounts.market.key(), ErrorCode::Unauthorized);
+    require!(
+        Clock::get()?.unix_timestamp < auction.gate_closure,
+        ErrorCode::AuctionClosed
+    );
+    require!(
+        (auction.order_count as usize) < MAX_AUCTION_ORDERS,
+        ErrorCode::AuctionFull
+    );
+
+    // Auction participants are all charged the taker fee
+    let fee_bps = ctx.accounts.market.taker_fee_bps;
+    let (escrow_amount, vault) = match side {
+        Side::Bid => {
+            let notional = energy_amount
+                .checked_mul(price_per_kwh)
+                .ok_or(ErrorCode::Overflow)?;
+            (
+                notional + calculate_fee(notional, fee_bps)?,
+                ctx.accounts.auction_quote_vault.to_account_info(),
+            )
+        }
+        Side::Ask => (
+            energy_amount,
+            ctx.accounts.auction_credit_vault.to_account_info(),
+        ),
+    };
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.owner_token_account.to_account_info(),
+        to: vault,
+        authority: ctx.accounts.owner.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    token::transfer(cpi_ctx, escrow_amount)?;
+
+    let order_index = auction.order_count;
+    auction.orders[order_index as usize] = AuctionOrder {
+        owner: ctx.accounts.owner.key(),
+        beneficial_owner: beneficial_owner(ctx.accounts.owner.key(), &ctx.accounts.owner_link)?,
+        price: price_per_kwh,
+        quantity: energy_amount,
+        filled: 0,
+        escrowed: escrow_amount,
+        side: side as u8,
+        settled: 0,
+        fee_bps,
+        padding: [0; 4],
+    };
+    auction.order_count += 1;
+
+    emit!(AuctionOrderSubmittedEvent {
+        auction: ctx.accounts.auction.key(),
+        order_index,
+        owner: ctx.accounts.owner.key(),
+        side,
+        price_per_kwh,
+        energy_amount,
+    });
+
+    msg!("Auction order {} submitted: {} kWh at {} credits/kWh", order_index, energy_amount, price_per_kwh);
+    Ok(())
+}
+
+pub(crate) fn clear_auction(ctx: Context<ClearAuction>) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_TRADING)?;
+
+    let auction_key = ctx.accounts.auction.key();
+    let mut auction = ctx.accounts.auction.load_mut()?;
+    let market = &mut ctx.accounts.market;
+
+    require!(auction.market == market.key(), ErrorCode::Unauthorized);
+    require!(auction.cleared == 0, ErrorCode::AuctionAlreadyCleared);
+    let now = Clock::get()?.unix_timestamp;
+    require!(now >= auction.gate_closure, ErrorCode::AuctionNotClosed);
+
+    let (clearing_price, cleared_volume) = auction.clearing_point();
+
+    let mut linked_volume: u64 = 0;
+    if cleared_volume > 0 {
+        auction.allocate_fills(clearing_price, cleared_volume);
+        let order_count = auction.order_count as usize;
+
+        // Whatever a meter owner both bought and sold through linked wallets is linked volume
+        let mut positions: Vec<(Pubkey, u64, u64)> = Vec::new();
+        for order in auction.orders[..order_count].iter().filter(|order| order.filled > 0) {
+            let position = match positions.iter().position(|p| p.0 == order.beneficial_owner) {
+                Some(position) => position,
+                None => {
+                    positions.push((order.beneficial_owner, 0, 0));
+                    positions.len() - 1
+                }
+            };
+            if order.side == Side::Bid as u8 {
+                positions[position].1 += order.filled;
+            } else {
+                positions[position].2 += order.filled;
+            }
+        }
+        linked_volume = positions.iter().map(|&(_, bought, sold)| bought.min(sold)).sum();
+    }
+
+    auction.clearing_price = clearing_price;
+    auction.cleared_volume = cleared_volume;
+    auction.cleared = 1;
+    market.record_volume(cleared_volume - linked_volume, false);
+    market.record_volume(linked_volume, true);
+
+    emit!(AuctionClearedEvent {
+        auction: auction_key,
+        delivery_start: auction.delivery_start,
+        delivery_end: auction.delivery_end,
+        clearing_price,
+        cleared_volume,
+        linked_volume,
+        timestamp: now,
+    });
+
+    msg!("Auction cleared: {} kWh at {} credits/kWh", cleared_volume, clearing_price);
+    Ok(())
+}
+
+pub(crate) fn settle_auction_order(
+    ctx: Context<SettleAuctionOrder>,
+    order_index: u32,
+) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_WITHDRAWALS)?;
+
+    let auction_key = ctx.accounts.auction.key();
+    let mut auction = ctx.accounts.auction.load_mut()?;
+
+    require!(auction.cleared == 1, ErrorCode::AuctionNotCleared);
+    require!(order_index < auction.order_count, ErrorCode::InvalidOrderIndex);
+
+    let clearing_price = auction.clearing_price;
+    let order = &mut auction.orders[order_index as usize];
+    require!(order.settled == 0, ErrorCode::OrderAlreadySettled);
+    require!(
+        ctx.accounts.owner_credit_account.owner == order.owner
+            && ctx.accounts.owner_token_account.owner == order.owner,
+        ErrorCode::Unauthorized
+    );
+
+    let notional = order.filled * clearing_price;
+    let fee = calculate_fee(notional, order.fee_bps)?;
+    let (credit_amount, quote_amount) = if order.side == Side::Bid as u8 {
+        (order.filled, order.escrowed - notional - fee)
+    } else {
+        (order.quantity - order.filled, notional - fee)
+    };
+    order.settled = 1;
+    let owner = order.owner;
+
+    let market = &mut ctx.accounts.market;
+    let market_key = market.key();
+
+    if credit_amount > 0 {
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.auction_credit_vault,
+            &ctx.accounts.owner_credit_account,
+            &ctx.accounts.token_program,
+            credit_amount,
+        )?;
+    }
+
+    if quote_amount > 0 {
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.auction_quote_vault,
+            &ctx.accounts.owner_token_account,
+            &ctx.accounts.token_program,
+            quote_amount,
+        )?;
+    }
+
+    if fee > 0 {
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.auction_quote_vault,
+            &ctx.accounts.fee_vault,
+            &ctx.accounts.token_program,
+            fee,
+        )?;
+
+        market.total_fees_collected += fee;
+        emit!(FeesCollectedEvent {
+            market: market_key,
+            mint: market.quote_mint,
+            maker_fee: 0,
+            taker_fee: fee,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+    }
+
+    emit!(AuctionOrderSettledEvent {
+        auction: auction_key,
+        order_index,
+        owner,
+        credit_amount,
+        quote_amount,
+    });
+
+    msg!("Settled auction order {}", order_index);
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(delivery_start: i64)]
+pub struct OpenAuction<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + std::mem::size_of::<Auction>(),
+        seeds = [b"auction", market.key().as_ref(), &delivery_start.to_le_bytes()],
+        bump
+    )]
+    pub auction: AccountLoader<'info, Auction>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(address = market.credit_mint)]
+    pub credit_mint: Account<'info, Mint>,
+    
+    #[account(address = market.quote_mint)]
+    pub quote_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"auction_credit_vault", auction.key().as_ref()],
+        bump,
+        token::mint = credit_mint,
+        token::authority = vault_authority
+    )]
+    pub auction_credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"auction_quote_vault", auction.key().as_ref()],
+        bump,
+        token::mint = quote_mint,
+        token::authority = vault_authority
+    )]
+    pub auction_quote_vault: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+    
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitAuctionOrder<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market)]
+    pub auction: AccountLoader<'info, Auction>,
+    
+    #[account(
+        mut,
+        seeds = [b"auction_credit_vault", auction.key().as_ref()],
+        bump
+    )]
+    pub auction_credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"auction_quote_vault", auction.key().as_ref()],
+        bump
+    )]
+    pub auction_quote_vault: Account<'info, TokenAccount>,
+    
+    /// Funding account: quote tokens for bids, energy credits for asks
+    #[account(
+        mut,
+        token::authority = owner
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    
+    /// CHECK: owner's oracle wallet link address, which may hold no account
+    #[account(
+        seeds = [b"wallet_link", owner.key().as_ref()],
+        bump,
+        seeds::program = energy_oracle::ID
+    )]
+    pub owner_link: UncheckedAccount<'info>,
+    
+    pub owner: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClearAuction<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market)]
+    pub auction: AccountLoader<'info, Auction>,
+}
+
+#[derive(Accounts)]
+pub struct SettleAuctionOrder<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market)]
+    pub auction: AccountLoader<'info, Auction>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"auction_credit_vault", auction.key().as_ref()],
+        bump
+    )]
+    pub auction_credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"auction_quote_vault", auction.key().as_ref()],
+        bump
+    )]
+    pub auction_quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint
+    )]
+    pub owner_credit_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"fee_vault", market.key().as_ref()],
+        bump = market.fee_vault_bump
+    )]
+    pub fee_vault: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+/// Sealed-bid double auction for one delivery interval
+#[account(zero_copy)]
+pub struct Auction {
+    pub market: Pubkey,
+    pub delivery_start: i64,
+    pub delivery_end: i64,
+    pub gate_closure: i64,
+    pub clearing_price: u64,
+    pub cleared_volume: u64,
+    pub order_count: u32,
+    pub cleared: u8,
+    pub bump: u8,
+    pub padding: [u8; 2],
+    pub orders: [AuctionOrder; MAX_AUCTION_ORDERS],
+}
+
+#[zero_copy]
+pub struct AuctionOrder {
+    pub owner: Pubkey,
+    /// Meter owner the order's wallet was linked to when submitted, or the wallet itself
+    pub beneficial_owner: Pubkey,
+    pub price: u64,
+    pub quantity: u64,
+    pub filled: u64,
+    pub escrowed: u64,
+    pub side: u8,
+    pub settled: u8,
+    pub fee_bps: u16,
+    pub padding: [u8; 4],
+}
+
+impl Auction {
+    /// Find the price where supply meets demand, returning `(price, volume)`.
+    ///
+    /// Candidate prices are the submitted limit prices. The price with the largest
+    /// matched volume wins; ties go to the smallest supply/demand imbalance, then
+    /// to the lower price.
+    pub fn clearing_point(&self) -> (u64, u64) {
+        let orders = &self.orders[..self.order_count as usize];
+        let mut best: Option<(u64, u64, u64)> = None;
+
+        for candidate in orders.iter().map(|order| order.price) {
+            let demand: u64 = orders
+                .iter()
+                .filter(|order| order.side == Side::Bid as u8 && order.price >= candidate)
+                .map(|order| order.quantity)
+                .sum();
+            let supply: u64 = orders
+                .iter()
+                .filter(|order| order.side == Side::Ask as u8 && order.price <= candidate)
+                .map(|order| order.quantity)
+                .sum();
+            let volume = demand.min(supply);
+            if volume == 0 {
+                continue;
+            }
+            let imbalance = demand.abs_diff(supply);
+
+            let better = match best {
+                None => true,
+                Some((price, best_volume, best_imbalance)) => {
+                    volume > best_volume
+                        || (volume == best_volume && imbalance < best_imbalance)
+                        || (volume == best_volume && imbalance == best_imbalance && candidate < price)
+                }
+            };
+            if better {
+                best = Some((candidate, volume, imbalance));
+            }
+        }
+
+        best.map_or((0, 0), |(price, volume, _)| (price, volume))
+    }
+
+    /// Fill `cleared_volume` on each side from the orders crossing `clearing_price`
+    ///
+    /// Marginal orders are rationed by price, then submission order.
+    pub fn allocate_fills(&mut self, clearing_price: u64, cleared_volume: u64) {
+        let orders = &mut self.orders[..self.order_count as usize];
+        let mut bids: Vec<usize> = (0..orders.len())
+            .filter(|&i| orders[i].side == Side::Bid as u8 && orders[i].price >= clearing_price)
+            .collect();
+        bids.sort_by(|&a, &b| orders[b].price.cmp(&orders[a].price).then(a.cmp(&b)));
+
+        let mut asks: Vec<usize> = (0..orders.len())
+            .filter(|&i| orders[i].side == Side::Ask as u8 && orders[i].price <= clearing_price)
+            .collect();
+        asks.sort_by(|&a, &b| orders[a].price.cmp(&orders[b].price).then(a.cmp(&b)));
+
+        for indices in [bids, asks] {
+            let mut remaining = cleared_volume;
+            for i in indices {
+                if remaining == 0 {
+                    break;
+                }
+                let fill = remaining.min(orders[i].quantity);
+                orders[i].filled = fill;
+                remaining -= fill;
+            }
+        }
+    }
+}
+
+#[event]
+pub struct AuctionOpenedEvent {
+    pub auction: Pubkey,
+    pub delivery_start: i64,
+    pub delivery_end: i64,
+    pub gate_closure: i64,
+}
+
+#[event]
+pub struct AuctionOrderSubmittedEvent {
+    pub auction: Pubkey,
+    pub order_index: u32,
+    pub owner: Pubkey,
+    pub side: Side,
+    pub price_per_kwh: u64,
+    pub energy_amount: u64,
+}
+
+#[event]
+pub struct AuctionClearedEvent {
+    pub auction: Pubkey,
+    pub delivery_start: i64,
+    pub delivery_end: i64,
+    pub clearing_price: u64,
+    pub cleared_volume: u64,
+    /// Part of the cleared volume bought and sold by wallets of the same meter owner
+    pub linked_volume: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionOrderSettledEvent {
+    pub auction: Pubkey,
+    pub order_index: u32,
+    pub owner: Pubkey,
+    pub credit_amount: u64,
+    pub quote_amount: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytemuck::Zeroable;
+
+    fn auction(orders: &[(Side, u64, u64)]) -> Box<Auction> {
+        let mut auction = Box::new(Auction::zeroed());
+        for (order, &(side, price, quantity)) in auction.orders.iter_mut().zip(orders) {
+            order.side = side as u8;
+            order.price = price;
+            order.quantity = quantity;
+        }
+        auction.order_count = orders.len() as u32;
+        auction
+    }
+
+    fn fills(auction: &Auction) -> Vec<u64> {
+        auction.orders[..auction.order_count as usize].iter().map(|order| order.filled).collect()
+    }
+
+    #[test]
+    fn clearing_point_maximises_matched_volume() {
+        let auction = auction(&[
+            (Side::Bid, 120, 50),
+            (Side::Bid, 100, 50),
+            (Side::Ask, 90, 40),
+            (Side::Ask, 110, 40),
+        ]);
+        // Below 110 only the cheap ask crosses, so 40 kWh at most would trade
+        assert_eq!(auction.clearing_point(), (110, 50));
+    }
+
+    #[test]
+    fn clearing_point_breaks_ties_on_imbalance_then_lower_price() {
+        let imbalanced = auction(&[
+            (Side::Bid, 100, 50),
+            (Side::Bid, 90, 30),
+            (Side::Ask, 80, 50),
+            (Side::Ask, 95, 5),
+        ]);
+        // Every price matches 50 kWh, but from 95 up only 5 kWh is left over
+        assert_eq!(imbalanced.clearing_point(), (95, 50));
+
+        let balanced = auction(&[(Side::Bid, 100, 30), (Side::Ask, 80, 30)]);
+        assert_eq!(balanced.clearing_point(), (80, 30));
+    }
+
+    #[test]
+    fn clearing_point_is_empty_when_no_orders_cross() {
+        let auction = auction(&[(Side::Bid, 80, 30), (Side::Ask, 100, 30)]);
+        assert_eq!(auction.clearing_point(), (0, 0));
+        assert_eq!(super::tests::auction(&[]).clearing_point(), (0, 0));
+    }
+
+    #[test]
+    fn allocate_fills_rations_marginal_orders_by_price_then_submission() {
+        let mut auction = auction(&[
+            (Side::Bid, 100, 30),
+            (Side::Bid, 120, 30),
+            (Side::Bid, 100, 30),
+            (Side::Ask, 90, 50),
+            (Side::Ask, 80, 20),
+            (Side::Ask, 110, 50),
+        ]);
+        let (clearing_price, cleared_volume) = auction.clearing_point();
+        assert_eq!((clearing_price, cleared_volume), (90, 70));
+
+        auction.allocate_fills(clearing_price, cleared_volume);
+        // The best bid fills first, then the earlier of the marginal bids; the ask
+        // above the clearing price trades nothing
+        assert_eq!(fills(&auction), [30, 30, 10, 50, 20, 0]);
+    }
+}
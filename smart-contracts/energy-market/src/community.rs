@@ -0,0 +1,932 @@
+//! Community shared-solar assets with share-based revenue splits
+
+use super::*;
+
+pub(crate) fn create_community_asset(ctx: Context<CreateCommunityAsset>) -> Result<()> {
+    let meter = &ctx.accounts.meter;
+    require!(
+        meter.is_authorized && meter.meter_type == MeterType::Solar,
+        ErrorCode::InvalidMeter
+    );
+
+    let asset = &mut ctx.accounts.asset;
+    asset.market = ctx.accounts.market.key();
+    asset.meter = meter.key();
+    asset.authority = ctx.accounts.authority.key();
+    asset.payout = CommunityPayout::Credits;
+    asset.total_shares = 0;
+    asset.acc_credits_per_share = 0;
+    asset.credits_balance = 0;
+    asset.total_credits = 0;
+    asset.acc_proceeds_per_share = 0;
+    asset.proceeds_balance = 0;
+    asset.total_proceeds = 0;
+    asset.offer_nonce = 0;
+    asset.bump = *ctx.bumps.get("asset").unwrap();
+    asset.credit_vault_bump = *ctx.bumps.get("asset_credit_vault").unwrap();
+    asset.quote_vault_bump = *ctx.bumps.get("asset_quote_vault").unwrap();
+
+    emit!(CommunityAssetCreatedEvent {
+        asset: asset.key(),
+        market: asset.market,
+        meter: asset.meter,
+        authority: asset.authority,
+    });
+
+    msg!("Created community asset for meter {}", meter.meter_id);
+    Ok(())
+}
+
+pub(crate) fn open_share_holding(ctx: Context<OpenShareHolding>) -> Result<()> {
+    let holding = &mut ctx.accounts.holding;
+    holding.asset = ctx.accounts.asset.key();
+    holding.owner = ctx.accounts.owner.key();
+    holding.shares = 0;
+    holding.credits_debt = 0;
+    holding.proceeds_debt = 0;
+    holding.unclaimed_credits = 0;
+    holding.unclaimed_proceeds = 0;
+    holding.bump = *ctx.bumps.get("holding").unwrap();
+    Ok(())
+}
+
+pub(crate) fn issue_shares(ctx: Context<IssueShares>, shares: u64) -> Result<()> {
+    require!(shares > 0, ErrorCode::InvalidAmount);
+
+    let asset = &mut ctx.accounts.asset;
+    let holding = &mut ctx.accounts.holding;
+    asset.accrue(
+        ctx.accounts.asset_credit_vault.amount,
+        ctx.accounts.asset_quote_vault.amount,
+    )?;
+    asset.settle_holding(holding)?;
+
+    holding.shares = holding.shares.checked_add(shares).ok_or(ErrorCode::Overflow)?;
+    asset.total_shares = asset.total_shares.checked_add(shares).ok_or(ErrorCode::Overflow)?;
+    asset.reset_debts(holding);
+
+    emit!(SharesIssuedEvent {
+        asset: asset.key(),
+        owner: holding.owner,
+        shares,
+        total_shares: asset.total_shares,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Issued {} community shares", shares);
+    Ok(())
+}
+
+pub(crate) fn transfer_shares(ctx: Context<TransferShares>, shares: u64) -> Result<()> {
+    require!(shares > 0, ErrorCode::InvalidAmount);
+
+    let asset = &mut ctx.accounts.asset;
+    let from_holding = &mut ctx.accounts.from_holding;
+    let to_holding = &mut ctx.accounts.to_holding;
+    require!(shares <= from_holding.shares, ErrorCode::InsufficientShares);
+
+    asset.accrue(
+        ctx.accounts.asset_credit_vault.amount,
+        ctx.accounts.asset_quote_vault.amount,
+    )?;
+    asset.settle_holding(from_holding)?;
+    asset.settle_holding(to_holding)?;
+
+    from_holding.shares -= shares;
+    to_holding.shares = to_holding.shares.checked_add(shares).ok_or(ErrorCode::Overflow)?;
+    asset.reset_debts(from_holding);
+    asset.reset_debts(to_holding);
+
+    emit!(SharesTransferredEvent {
+        asset: asset.key(),
+        from: from_holding.owner,
+        to: to_holding.owner,
+        shares,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Transferred {} community shares", shares);
+    Ok(())
+}
+
+pub(crate) fn set_community_payout(
+    ctx: Context<UpdateCommunityAsset>,
+    payout: CommunityPayout,
+) -> Result<()> {
+    let asset = &mut ctx.accounts.asset;
+    asset.accrue(
+        ctx.accounts.asset_credit_vault.amount,
+        ctx.accounts.asset_quote_vault.amount,
+    )?;
+    asset.payout = payout;
+
+    emit!(CommunityPayoutChangedEvent {
+        asset: asset.key(),
+        payout,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Community payout updated");
+    Ok(())
+}
+
+pub(crate) fn claim_community_revenue(ctx: Context<ClaimCommunityRevenue>) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_WITHDRAWALS)?;
+
+    let asset = &mut ctx.accounts.asset;
+    let holding = &mut ctx.accounts.holding;
+    asset.accrue(
+        ctx.accounts.asset_credit_vault.amount,
+        ctx.accounts.asset_quote_vault.amount,
+    )?;
+    asset.settle_holding(holding)?;
+
+    let credits = holding.unclaimed_credits;
+    let proceeds = holding.unclaimed_proceeds;
+    require!(credits > 0 || proceeds > 0, ErrorCode::NothingToClaim);
+
+    holding.unclaimed_credits = 0;
+    holding.unclaimed_proceeds = 0;
+    asset.credits_balance -= credits;
+    asset.proceeds_balance -= proceeds;
+
+    if credits > 0 {
+        community_transfer(
+            asset,
+            &ctx.accounts.asset_credit_vault,
+            &ctx.accounts.owner_credit_account,
+            &ctx.accounts.token_program,
+            credits,
+        )?;
+    }
+    if proceeds > 0 {
+        community_transfer(
+            asset,
+            &ctx.accounts.asset_quote_vault,
+            &ctx.accounts.owner_token_account,
+            &ctx.accounts.token_program,
+            proceeds,
+        )?;
+    }
+
+    emit!(CommunityRevenueClaimedEvent {
+        asset: asset.key(),
+        owner: holding.owner,
+        credits,
+        proceeds,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Claimed {} credits and {} in proceeds", credits, proceeds);
+    Ok(())
+}
+
+pub(crate) fn create_community_offer(
+    ctx: Context<CreateCommunityOffer>,
+    energy_amount: u64,
+    price_per_kwh: u64,
+    duration_hours: u32,
+    min_fill_amount: u64,
+) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_NEW_OFFERS)?;
+
+    require!(energy_amount > 0, ErrorCode::InvalidAmount);
+    require!(price_per_kwh > 0, ErrorCode::InvalidPrice);
+    require!(duration_hours > 0 && duration_hours <= 168, ErrorCode::InvalidDuration); // Max 1 week
+    require!(min_fill_amount <= energy_amount, ErrorCode::InvalidMinFill);
+
+    let asset = &mut ctx.accounts.asset;
+    require!(asset.payout == CommunityPayout::Proceeds, ErrorCode::CommunityPaysCredits);
+
+    // Credits already split between holders are not for sale
+    let available_amount = ctx.accounts.asset_credit_vault.amount - asset.credits_balance;
+    require!(energy_amount <= available_amount, ErrorCode::InsufficientEnergy);
+
+    // Lock the offered credits in the offer vault until traded or cancelled
+    community_transfer(
+        asset,
+        &ctx.accounts.asset_credit_vault,
+        &ctx.accounts.offer_vault,
+        &ctx.accounts.token_program,
+        energy_amount,
+    )?;
+
+    let zone = seller_zone(&ctx.accounts.market, &ctx.accounts.meter_zone_member)?;
+    let now = Clock::get()?.unix_timestamp;
+
+    let offer = &mut ctx.accounts.offer;
+    let market = &mut ctx.accounts.market;
+    offer.seller = asset.key();
+    offer.rent_payer = ctx.accounts.authority.key();
+    offer.proceeds_account = ctx.accounts.asset_quote_vault.key();
+    offer.market = market.key();
+    offer.nonce = asset.offer_nonce;
+    offer.bump = *ctx.bumps.get("offer").unwrap();
+    asset.offer_nonce += 1;
+    offer.energy_amount = energy_amount;
+    offer.price_per_kwh = price_per_kwh;
+    offer.price_reference = Pubkey::default();
+    offer.price_offset_bps = 0;
+    offer.offer_type = OfferType::Immediate;
+    offer.status = OfferStatus::Active;
+    offer.created_at = now;
+    offer.opens_at = now;
+    offer.expires_at = now + (duration_hours as i64 * 3600);
+    offer.filled_amount = 0;
+    offer.min_fill_amount = min_fill_amount;
+    offer.delivery_start = 0;
+    offer.delivery_end = 0;
+    offer.meter = asset.meter;
+    offer.zone = zone;
+    offer.delivered_amount = 0;
+    offer.collateral_locked = 0;
+    offer.penalty_bps = market.penalty_bps;
+    offer.trade_count = 0;
+    offer.open_trades = 0;
+    offer.recurrence_interval = None;
+    offer.occurrences_remaining = 0;
+    offer.occurrence_index = 0;
+    offer.vault_bump = *ctx.bumps.get("offer_vault").unwrap();
+
+    market.total_offers += 1;
+
+    emit!(OfferCreatedEvent {
+        offer_id: offer.key(),
+        seller: offer.seller,
+        energy_amount,
+        price_per_kwh,
+        offer_type: OfferType::Immediate,
+        expires_at: offer.expires_at,
+    });
+
+    msg!("Community offered {} kWh at {} per kWh", energy_amount, price_per_kwh);
+    Ok(())
+}
+
+pub(crate) fn cancel_community_offer(ctx: Context<CancelCommunityOffer>) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_CANCELLATIONS)?;
+
+    let offer = &mut ctx.accounts.offer;
+    let market = &ctx.accounts.market;
+    require!(offer.status == OfferStatus::Active, ErrorCode::OfferNotActive);
+
+    let refund_amount = offer.unsold_amount();
+    if refund_amount > 0 {
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.offer_vault,
+            &ctx.accounts.asset_credit_vault,
+            &ctx.accounts.token_program,
+            refund_amount,
+        )?;
+    }
+
+    offer.status = OfferStatus::Cancelled;
+    offer.occurrences_remaining = 0;
+
+    emit!(OfferCancelledEvent {
+        offer_id: offer.key(),
+        seller: offer.seller,
+        refunded_amount: refund_amount,
+        collateral_released: 0,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Community offer cancelled");
+    Ok(())
+}
+
+pub(crate) fn close_community_offer(ctx: Context<CloseCommunityOffer>) -> Result<()> {
+    close_operator_offer(
+        &ctx.accounts.market,
+        &ctx.accounts.vault_authority,
+        &mut ctx.accounts.offer,
+        &ctx.accounts.offer_vault,
+        &ctx.accounts.asset_credit_vault,
+        &ctx.accounts.authority,
+        &ctx.accounts.token_program,
+    )?;
+
+    msg!("Community offer closed");
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateCommunityAsset<'info> {
+    pub market: Account<'info, Market>,
+    
+    /// Shared solar meter, registered to the asset authority
+    #[account(
+        constraint = meter.oracle == market.oracle @ ErrorCode::OracleMismatch,
+        constraint = meter.owner == authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub meter: Account<'info, EnergyMeter>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + CommunityAsset::INIT_SPACE,
+        seeds = [b"community", market.key().as_ref(), meter.key().as_ref()],
+        bump
+    )]
+    pub asset: Account<'info, CommunityAsset>,
+    
+    #[account(address = market.credit_mint)]
+    pub credit_mint: Account<'info, Mint>,
+    
+    #[account(address = market.quote_mint)]
+    pub quote_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"community_credit_vault", asset.key().as_ref()],
+        bump,
+        token::mint = credit_mint,
+        token::authority = asset
+    )]
+    pub asset_credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"community_quote_vault", asset.key().as_ref()],
+        bump,
+        token::mint = quote_mint,
+        token::authority = asset
+    )]
+    pub asset_quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+    
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct OpenShareHolding<'info> {
+    pub asset: Account<'info, CommunityAsset>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + ShareHolding::INIT_SPACE,
+        seeds = [b"share_holding", asset.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub holding: Account<'info, ShareHolding>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct IssueShares<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
+    pub asset: Account<'info, CommunityAsset>,
+    
+    #[account(mut, has_one = asset)]
+    pub holding: Account<'info, ShareHolding>,
+    
+    #[account(
+        seeds = [b"community_credit_vault", asset.key().as_ref()],
+        bump = asset.credit_vault_bump
+    )]
+    pub asset_credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        seeds = [b"community_quote_vault", asset.key().as_ref()],
+        bump = asset.quote_vault_bump
+    )]
+    pub asset_quote_vault: Account<'info, TokenAccount>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct TransferShares<'info> {
+    #[account(mut)]
+    pub asset: Account<'info, CommunityAsset>,
+    
+    #[account(
+        mut,
+        seeds = [b"share_holding", asset.key().as_ref(), owner.key().as_ref()],
+        bump = from_holding.bump
+    )]
+    pub from_holding: Account<'info, ShareHolding>,
+    
+    #[account(
+        mut,
+        has_one = asset,
+        constraint = to_holding.key() != from_holding.key() @ ErrorCode::InvalidShareTransfer
+    )]
+    pub to_holding: Account<'info, ShareHolding>,
+    
+    #[account(
+        seeds = [b"community_credit_vault", asset.key().as_ref()],
+        bump = asset.credit_vault_bump
+    )]
+    pub asset_credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        seeds = [b"community_quote_vault", asset.key().as_ref()],
+        bump = asset.quote_vault_bump
+    )]
+    pub asset_quote_vault: Account<'info, TokenAccount>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateCommunityAsset<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
+    pub asset: Account<'info, CommunityAsset>,
+    
+    #[account(
+        seeds = [b"community_credit_vault", asset.key().as_ref()],
+        bump = asset.credit_vault_bump
+    )]
+    pub asset_credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        seeds = [b"community_quote_vault", asset.key().as_ref()],
+        bump = asset.quote_vault_bump
+    )]
+    pub asset_quote_vault: Account<'info, TokenAccount>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimCommunityRevenue<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market)]
+    pub asset: Account<'info, CommunityAsset>,
+    
+    #[account(
+        mut,
+        seeds = [b"share_holding", asset.key().as_ref(), owner.key().as_ref()],
+        bump = holding.bump
+    )]
+    pub holding: Account<'info, ShareHolding>,
+    
+    #[account(
+        mut,
+        seeds = [b"community_credit_vault", asset.key().as_ref()],
+        bump = asset.credit_vault_bump
+    )]
+    pub asset_credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"community_quote_vault", asset.key().as_ref()],
+        bump = asset.quote_vault_bump
+    )]
+    pub asset_quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint,
+        token::authority = owner
+    )]
+    pub owner_credit_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    
+    pub owner: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CreateCommunityOffer<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market, has_one = authority @ ErrorCode::Unauthorized)]
+    pub asset: Account<'info, CommunityAsset>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + EnergyOffer::INIT_SPACE,
+        seeds = [
+            b"offer",
+            market.key().as_ref(),
+            asset.key().as_ref(),
+            &asset.offer_nonce.to_le_bytes()
+        ],
+        bump
+    )]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(address = market.credit_mint)]
+    pub credit_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump,
+        token::mint = credit_mint,
+        token::authority = vault_authority
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"community_credit_vault", asset.key().as_ref()],
+        bump = asset.credit_vault_bump
+    )]
+    pub asset_credit_vault: Account<'info, TokenAccount>,
+    
+    /// Vault the offer's trade proceeds are paid into
+    #[account(
+        seeds = [b"community_quote_vault", asset.key().as_ref()],
+        bump = asset.quote_vault_bump
+    )]
+    pub asset_quote_vault: Account<'info, TokenAccount>,
+    
+    /// CHECK: zone assignment address of the asset's meter, which may hold no account
+    #[account(
+        seeds = [b"zone_member", market.key().as_ref(), asset.meter.as_ref()],
+        bump
+    )]
+    pub meter_zone_member: UncheckedAccount<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+    
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CancelCommunityOffer<'info> {
+    #[account(
+        mut,
+        has_one = market,
+        constraint = offer.seller == asset.key() @ ErrorCode::Unauthorized
+    )]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    pub market: Account<'info, Market>,
+    
+    #[account(has_one = market, has_one = authority @ ErrorCode::Unauthorized)]
+    pub asset: Account<'info, CommunityAsset>,
+    
+    pub authority: Signer<'info>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump = offer.vault_bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"community_credit_vault", asset.key().as_ref()],
+        bump = asset.credit_vault_bump
+    )]
+    pub asset_credit_vault: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseCommunityOffer<'info> {
+    #[account(
+        mut,
+        has_one = market,
+        constraint = offer.seller == asset.key() @ ErrorCode::Unauthorized,
+        constraint = offer.rent_payer == authority.key() @ ErrorCode::Unauthorized,
+        close = authority
+    )]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    pub market: Account<'info, Market>,
+    
+    #[account(has_one = market, has_one = authority @ ErrorCode::Unauthorized)]
+    pub asset: Account<'info, CommunityAsset>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump = offer.vault_bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"community_credit_vault", asset.key().as_ref()],
+        bump = asset.credit_vault_bump
+    )]
+    pub asset_credit_vault: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+/// Shared solar meter whose output is split between residents by share units
+#[account]
+#[derive(InitSpace)]
+pub struct CommunityAsset {
+    pub market: Pubkey,
+    pub meter: Pubkey,
+    /// Meter owner managing shares, payout and offers
+    pub authority: Pubkey,
+    pub payout: CommunityPayout,
+    pub total_shares: u64,
+    /// Credits earned per share, scaled by `ACC_PRECISION`
+    pub acc_credits_per_share: u128,
+    /// Credit vault balance owed to holders; the rest is for sale
+    pub credits_balance: u64,
+    pub total_credits: u64,
+    /// Proceeds earned per share, scaled by `ACC_PRECISION`
+    pub acc_proceeds_per_share: u128,
+    /// Quote vault balance already split between holders
+    pub proceeds_balance: u64,
+    pub total_proceeds: u64,
+    /// Nonce of the asset's next offer
+    pub offer_nonce: u64,
+    pub bump: u8,
+    pub credit_vault_bump: u8,
+    pub quote_vault_bump: u8,
+}
+
+impl CommunityAsset {
+    /// Split credits and proceeds that reached the vaults since the last call
+    ///
+    /// Revenue arriving while no shares are issued waits for the first holders.
+    pub fn accrue(&mut self, credit_vault_balance: u64, quote_vault_balance: u64) -> Result<()> {
+        if self.total_shares == 0 {
+            return Ok(());
+        }
+
+        if self.payout == CommunityPayout::Credits {
+            let credits = credit_vault_balance.saturating_sub(self.credits_balance);
+            if credits > 0 {
+                self.acc_credits_per_share += (credits as u128) * ACC_PRECISION
+                    / (self.total_shares as u128);
+                self.total_credits = self.total_credits.checked_add(credits).ok_or(ErrorCode::Overflow)?;
+                self.credits_balance = credit_vault_balance;
+            }
+        }
+
+        let proceeds = quote_vault_balance.saturating_sub(self.proceeds_balance);
+        if proceeds > 0 {
+            self.acc_proceeds_per_share += (proceeds as u128) * ACC_PRECISION
+                / (self.total_shares as u128);
+            self.total_proceeds = self.total_proceeds.checked_add(proceeds).ok_or(ErrorCode::Overflow)?;
+            self.proceeds_balance = quote_vault_balance;
+        }
+        Ok(())
+    }
+
+    /// Move a holding's accrued credits and proceeds into its unclaimed balances
+    pub fn settle_holding(&self, holding: &mut ShareHolding) -> Result<()> {
+        let credits = ((holding.shares as u128) * self.acc_credits_per_share - holding.credits_debt)
+            / ACC_PRECISION;
+        holding.credits_debt += credits * ACC_PRECISION;
+        holding.unclaimed_credits = holding
+            .unclaimed_credits
+            .checked_add(u64::try_from(credits).map_err(|_| error!(ErrorCode::Overflow))?)
+            .ok_or(ErrorCode::Overflow)?;
+
+        let proceeds = ((holding.shares as u128) * self.acc_proceeds_per_share - holding.proceeds_debt)
+            / ACC_PRECISION;
+        holding.proceeds_debt += proceeds * ACC_PRECISION;
+        holding.unclaimed_proceeds = holding
+            .unclaimed_proceeds
+            .checked_add(u64::try_from(proceeds).map_err(|_| error!(ErrorCode::Overflow))?)
+            .ok_or(ErrorCode::Overflow)?;
+        Ok(())
+    }
+
+    /// Start a settled holding earning from the current indices after its shares change
+    pub fn reset_debts(&self, holding: &mut ShareHolding) {
+        holding.credits_debt = (holding.shares as u128) * self.acc_credits_per_share;
+        holding.proceeds_debt = (holding.shares as u128) * self.acc_proceeds_per_share;
+    }
+}
+
+/// Transfer tokens out of one of a community asset's vaults
+pub fn community_transfer<'info>(
+    asset: &Account<'info, CommunityAsset>,
+    from: &impl ToAccountInfo<'info>,
+    to: &impl ToAccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let seeds = &[
+        b"community".as_ref(),
+        asset.market.as_ref(),
+        asset.meter.as_ref(),
+        &[asset.bump],
+    ];
+    signed_transfer(from, to, asset, token_program, &[&seeds[..]], amount)
+}
+
+/// A resident's share units in a community asset
+#[account]
+#[derive(InitSpace)]
+pub struct ShareHolding {
+    pub asset: Pubkey,
+    pub owner: Pubkey,
+    pub shares: u64,
+    /// Credits per share already accounted for, scaled by `ACC_PRECISION`
+    pub credits_debt: u128,
+    /// Proceeds per share already accounted for, scaled by `ACC_PRECISION`
+    pub proceeds_debt: u128,
+    pub unclaimed_credits: u64,
+    pub unclaimed_proceeds: u64,
+    pub bump: u8,
+}
+
+/// How a community asset pays out the credits minted for its meter
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum CommunityPayout {
+    /// Split the credits between holders in kind
+    Credits,
+    /// Keep the credits for community offers and split the proceeds
+    Proceeds,
+}
+
+#[event]
+pub struct CommunityAssetCreatedEvent {
+    pub asset: Pubkey,
+    pub market: Pubkey,
+    pub meter: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct SharesIssuedEvent {
+    pub asset: Pubkey,
+    pub owner: Pubkey,
+    pub shares: u64,
+    pub total_shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SharesTransferredEvent {
+    pub asset: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CommunityPayoutChangedEvent {
+    pub asset: Pubkey,
+    pub payout: CommunityPayout,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CommunityRevenueClaimedEvent {
+    pub asset: Pubkey,
+    pub owner: Pubkey,
+    pub credits: u64,
+    pub proceeds: u64,
+    pub timestamp: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(payout: CommunityPayout) -> CommunityAsset {
+        CommunityAsset {
+            market: Pubkey::new_unique(),
+            meter: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            payout,
+            total_shares: 0,
+            acc_credits_per_share: 0,
+            credits_balance: 0,
+            total_credits: 0,
+            acc_proceeds_per_share: 0,
+            proceeds_balance: 0,
+            total_proceeds: 0,
+            offer_nonce: 0,
+            bump: 255,
+            credit_vault_bump: 255,
+            quote_vault_bump: 255,
+        }
+    }
+
+    fn holding(asset: &mut CommunityAsset, shares: u64) -> ShareHolding {
+        let mut holding = ShareHolding {
+            asset: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            shares,
+            credits_debt: 0,
+            proceeds_debt: 0,
+            unclaimed_credits: 0,
+            unclaimed_proceeds: 0,
+            bump: 255,
+        };
+        asset.total_shares += shares;
+        asset.reset_debts(&mut holding);
+        holding
+    }
+
+    #[test]
+    fn accrue_splits_credits_and_proceeds_pro_rata() {
+        let mut asset = asset(CommunityPayout::Credits);
+        let mut small = holding(&mut asset, 25);
+        let mut large = holding(&mut asset, 75);
+
+        asset.accrue(400, 200).unwrap();
+        asset.settle_holding(&mut small).unwrap();
+        asset.settle_holding(&mut large).unwrap();
+        assert_eq!((small.unclaimed_credits, small.unclaimed_proceeds), (100, 50));
+        assert_eq!((large.unclaimed_credits, large.unclaimed_proceeds), (300, 150));
+
+        // Only revenue above the recorded balances is new
+        asset.accrue(400, 600).unwrap();
+        asset.settle_holding(&mut small).unwrap();
+        assert_eq!((small.unclaimed_credits, small.unclaimed_proceeds), (100, 150));
+        assert_eq!((asset.total_credits, asset.total_proceeds), (400, 600));
+    }
+
+    #[test]
+    fn accrue_keeps_credits_for_sale_under_proceeds_payout() {
+        let mut asset = asset(CommunityPayout::Proceeds);
+        let mut holding = holding(&mut asset, 10);
+
+        asset.accrue(400, 200).unwrap();
+        assert_eq!((asset.credits_balance, asset.total_credits), (0, 0));
+        asset.settle_holding(&mut holding).unwrap();
+        assert_eq!((holding.unclaimed_credits, holding.unclaimed_proceeds), (0, 200));
+    }
+
+    #[test]
+    fn accrue_holds_revenue_until_shares_are_issued() {
+        let mut asset = asset(CommunityPayout::Credits);
+        asset.accrue(400, 200).unwrap();
+        assert_eq!((asset.credits_balance, asset.proceeds_balance), (0, 0));
+
+        let mut first = holding(&mut asset, 4);
+        asset.accrue(400, 200).unwrap();
+        asset.settle_holding(&mut first).unwrap();
+        assert_eq!((first.unclaimed_credits, first.unclaimed_proceeds), (400, 200));
+    }
+
+    #[test]
+    fn reset_debts_excludes_revenue_from_before_the_shares() {
+        let mut asset = asset(CommunityPayout::Credits);
+        let mut early = holding(&mut asset, 50);
+        asset.accrue(100, 0).unwrap();
+
+        let mut late = holding(&mut asset, 50);
+        asset.accrue(300, 0).unwrap();
+        asset.settle_holding(&mut early).unwrap();
+        asset.settle_holding(&mut late).unwrap();
+        assert_eq!(early.unclaimed_credits, 200);
+        assert_eq!(late.unclaimed_credits, 100);
+    }
+}
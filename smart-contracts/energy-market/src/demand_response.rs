@@ -0,0 +1,622 @@
+//! Demand-response events with curtailment bids and baseline settlement
+
+use super::*;
+
+pub(crate) fn open_demand_response(
+    ctx: Context<OpenDemandResponse>,
+    event_id: u16,
+    window_start: i64,
+    window_end: i64,
+    target_reduction: u64,
+    max_price_per_kwh: u64,
+) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_NEW_OFFERS)?;
+
+    require!(target_reduction > 0, ErrorCode::InvalidAmount);
+    require!(max_price_per_kwh > 0, ErrorCode::InvalidPrice);
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        window_start - GATE_CLOSURE_SECS > now
+            && window_end > window_start
+            && window_end - window_start <= DR_MAX_WINDOW_SECS,
+        ErrorCode::InvalidDeliveryWindow
+    );
+
+    let budget = target_reduction
+        .checked_mul(max_price_per_kwh)
+        .ok_or(ErrorCode::Overflow)?;
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.operator_token_account.to_account_info(),
+        to: ctx.accounts.dr_vault.to_account_info(),
+        authority: ctx.accounts.operator.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    token::transfer(cpi_ctx, budget)?;
+
+    let event = &mut ctx.accounts.event;
+    event.market = ctx.accounts.market.key();
+    event.operator = ctx.accounts.operator.key();
+    event.event_id = event_id;
+    event.zone = ctx.accounts.zone.key();
+    event.window_start = window_start;
+    event.window_end = window_end;
+    event.target_reduction = target_reduction;
+    event.max_price_per_kwh = max_price_per_kwh;
+    event.clearing_price = 0;
+    event.accepted_reduction = 0;
+    event.delivered_reduction = 0;
+    event.total_paid = 0;
+    event.bid_count = 0;
+    event.status = DemandResponseStatus::Open;
+    event.bump = *ctx.bumps.get("event").unwrap();
+    event.vault_bump = *ctx.bumps.get("dr_vault").unwrap();
+
+    emit!(DemandResponseOpenedEvent {
+        event: event.key(),
+        market: event.market,
+        operator: event.operator,
+        zone: event.zone,
+        window_start,
+        window_end,
+        target_reduction,
+        max_price_per_kwh,
+    });
+
+    msg!("Opened demand-response event {} for {} kWh", event_id, target_reduction);
+    Ok(())
+}
+
+pub(crate) fn submit_curtailment_bid<'info>(
+    ctx: Context<'_, '_, '_, 'info, SubmitCurtailmentBid<'info>>,
+    reduction_amount: u64,
+    price_per_kwh: u64,
+) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_NEW_OFFERS)?;
+
+    let event = &mut ctx.accounts.event;
+    let meter = &ctx.accounts.meter;
+    require!(event.status == DemandResponseStatus::Open, ErrorCode::BiddingClosed);
+    require!(
+        Clock::get()?.unix_timestamp < event.window_start - GATE_CLOSURE_SECS,
+        ErrorCode::BiddingClosed
+    );
+    require!(event.bid_count < DR_MAX_BIDS, ErrorCode::DemandResponseFull);
+    require!(reduction_amount > 0, ErrorCode::InvalidAmount);
+    require!(
+        price_per_kwh > 0 && price_per_kwh <= event.max_price_per_kwh,
+        ErrorCode::InvalidPrice
+    );
+    require!(
+        meter.is_authorized && meter.meter_type == MeterType::Consumption,
+        ErrorCode::InvalidMeter
+    );
+    require!(
+        assigned_zone(&ctx.accounts.meter_zone_member)? == event.zone,
+        ErrorCode::ZoneMismatch
+    );
+
+    // Baseline from the consumption register over the event hours on previous days
+    require!(
+        meter.last_reading_at >= event.window_end - 86_400,
+        ErrorCode::MissingReading
+    );
+    let mut readings = ctx.remaining_accounts.iter();
+    let mut baseline_total: u64 = 0;
+    for days_before in 1..=DR_BASELINE_DAYS {
+        let offset = days_before * 86_400;
+        let start_register = meter_register_at(
+            meter,
+            ReadingType::Consumption,
+            event.window_start - offset,
+            &mut readings,
+        )?;
+        let end_register = meter_register_at(
+            meter,
+            ReadingType::Consumption,
+            event.window_end - offset,
+            &mut readings,
+        )?;
+        baseline_total = baseline_total
+            .checked_add(end_register.saturating_sub(start_register))
+            .ok_or(ErrorCode::Overflow)?;
+    }
+    let baseline = baseline_total / DR_BASELINE_DAYS as u64;
+
+    let bid = &mut ctx.accounts.bid;
+    bid.event = event.key();
+    bid.consumer = ctx.accounts.consumer.key();
+    bid.meter = meter.key();
+    bid.reduction_amount = reduction_amount;
+    bid.price_per_kwh = price_per_kwh;
+    bid.accepted_amount = 0;
+    bid.baseline = baseline;
+    bid.delivered_amount = 0;
+    bid.payout = 0;
+    bid.is_settled = false;
+    bid.bump = *ctx.bumps.get("bid").unwrap();
+
+    event.bid_count += 1;
+
+    emit!(CurtailmentBidSubmittedEvent {
+        event: event.key(),
+        consumer: bid.consumer,
+        meter: bid.meter,
+        reduction_amount,
+        price_per_kwh,
+        baseline,
+    });
+
+    msg!("Curtailment bid of {} kWh at {} per kWh", reduction_amount, price_per_kwh);
+    Ok(())
+}
+
+pub(crate) fn clear_demand_response<'info>(
+    ctx: Context<'_, '_, '_, 'info, ClearDemandResponse<'info>>,
+) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_TRADING)?;
+
+    let event = &mut ctx.accounts.event;
+    let market = &ctx.accounts.market;
+    let now = Clock::get()?.unix_timestamp;
+    require!(event.status == DemandResponseStatus::Open, ErrorCode::EventAlreadyCleared);
+    require!(now >= event.window_start - GATE_CLOSURE_SECS, ErrorCode::BiddingOpen);
+    require!(
+        now < event.window_start || ctx.remaining_accounts.is_empty(),
+        ErrorCode::InvalidDeliveryWindow
+    );
+    // The whole merit order is shown, so no cheaper bid can be passed over
+    require!(
+        now >= event.window_start || ctx.remaining_accounts.len() == event.bid_count as usize,
+        ErrorCode::MissingCurtailmentBid
+    );
+
+    let event_key = event.key();
+    let mut accepted_reduction: u64 = 0;
+    let mut clearing_price: u64 = 0;
+    let mut accepted_bids: u32 = 0;
+    let mut last_price: u64 = 0;
+    let mut counted: Vec<Pubkey> = Vec::with_capacity(ctx.remaining_accounts.len());
+    for bid_info in ctx.remaining_accounts.iter() {
+        require!(!counted.contains(bid_info.key), ErrorCode::InvalidRemainingAccounts);
+        let mut bid: Account<CurtailmentBid> = Account::try_from(bid_info)?;
+        require!(bid.event == event_key, ErrorCode::InvalidRemainingAccounts);
+        require!(bid.price_per_kwh >= last_price, ErrorCode::BidsNotSorted);
+        last_price = bid.price_per_kwh;
+        counted.push(bid_info.key());
+
+        let remaining = event.target_reduction - accepted_reduction;
+        if remaining == 0 {
+            continue;
+        }
+        bid.accepted_amount = bid.reduction_amount.min(remaining);
+        accepted_reduction += bid.accepted_amount;
+        clearing_price = bid.price_per_kwh;
+        accepted_bids += 1;
+        bid.exit(&crate::ID)?;
+    }
+
+    // Only the accepted reduction at the clearing price stays in escrow
+    let reserved = accepted_reduction
+        .checked_mul(clearing_price)
+        .ok_or(ErrorCode::Overflow)?;
+    let refund_amount = ctx.accounts.dr_vault.amount - reserved;
+    if refund_amount > 0 {
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.dr_vault,
+            &ctx.accounts.operator_token_account,
+            &ctx.accounts.token_program,
+            refund_amount,
+        )?;
+    }
+
+    event.accepted_reduction = accepted_reduction;
+    event.clearing_price = clearing_price;
+    event.status = DemandResponseStatus::Cleared;
+
+    emit!(DemandResponseClearedEvent {
+        event: event_key,
+        accepted_bids,
+        accepted_reduction,
+        clearing_price,
+        refunded_amount: refund_amount,
+    });
+
+    msg!("Cleared {} kWh of curtailment at {} per kWh", accepted_reduction, clearing_price);
+    Ok(())
+}
+
+pub(crate) fn settle_curtailment<'info>(
+    ctx: Context<'_, '_, '_, 'info, SettleCurtailment<'info>>,
+) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_WITHDRAWALS)?;
+
+    let event = &mut ctx.accounts.event;
+    let bid = &mut ctx.accounts.bid;
+    let market = &ctx.accounts.market;
+    let meter = &ctx.accounts.meter;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(event.status == DemandResponseStatus::Cleared, ErrorCode::BiddingOpen);
+    require!(bid.accepted_amount > 0, ErrorCode::BidNotAccepted);
+    require!(!bid.is_settled, ErrorCode::TradeAlreadySettled);
+    let readings_close = event.window_end + DELIVERY_GRACE_SECS;
+    require!(now >= readings_close, ErrorCode::DeliveryWindowOpen);
+    if now < readings_close + DR_OPERATOR_SETTLEMENT_SECS {
+        require!(ctx.accounts.settler.key() == event.operator, ErrorCode::Unauthorized);
+    }
+
+    // Consumption over the event window, read off the meter's cumulative register.
+    // A meter that stops reporting would show none, so the window must be covered.
+    let baseline = bid.baseline;
+    let readings_archived =
+        now >= event.window_start + READING_ARCHIVE_SECS && ctx.remaining_accounts.is_empty();
+    let covered = meter.last_reading_at >= event.window_end && !readings_archived;
+    let (consumed, delivered_amount) = if covered {
+        let mut readings = ctx.remaining_accounts.iter();
+        let start_register = meter_register_at(
+            meter,
+            ReadingType::Consumption,
+            event.window_start,
+            &mut readings,
+        )?;
+        let end_register = meter_register_at(
+            meter,
+            ReadingType::Consumption,
+            event.window_end,
+            &mut readings,
+        )?;
+        let consumed = end_register.saturating_sub(start_register);
+        (consumed, baseline.saturating_sub(consumed).min(bid.accepted_amount))
+    } else {
+        require!(
+            now >= readings_close + DR_OPERATOR_SETTLEMENT_SECS,
+            ErrorCode::MissingReading
+        );
+        (0, 0)
+    };
+    let payout = delivered_amount
+        .checked_mul(event.clearing_price)
+        .ok_or(ErrorCode::Overflow)?;
+    let refund_amount = (bid.accepted_amount - delivered_amount)
+        .checked_mul(event.clearing_price)
+        .ok_or(ErrorCode::Overflow)?;
+
+    // Pay the consumer for the verified reduction, return the rest to the operator
+    if payout > 0 {
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.dr_vault,
+            &ctx.accounts.consumer_token_account,
+            &ctx.accounts.token_program,
+            payout,
+        )?;
+    }
+    if refund_amount > 0 {
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.dr_vault,
+            &ctx.accounts.operator_token_account,
+            &ctx.accounts.token_program,
+            refund_amount,
+        )?;
+    }
+
+    bid.delivered_amount = delivered_amount;
+    bid.payout = payout;
+    bid.is_settled = true;
+    event.delivered_reduction += delivered_amount;
+    event.total_paid += payout;
+
+    emit!(CurtailmentSettledEvent {
+        event: event.key(),
+        consumer: bid.consumer,
+        baseline,
+        consumed,
+        accepted_amount: bid.accepted_amount,
+        delivered_amount,
+        payout,
+        timestamp: now,
+    });
+
+    msg!("Settled curtailment: {} kWh below a {} kWh baseline", delivered_amount, baseline);
+    Ok(())
+}
+
+pub(crate) fn close_curtailment_bid(ctx: Context<CloseCurtailmentBid>) -> Result<()> {
+    let event = &ctx.accounts.event;
+    let bid = &ctx.accounts.bid;
+    require!(
+        event.status == DemandResponseStatus::Cleared
+            && (bid.is_settled || bid.accepted_amount == 0),
+        ErrorCode::BidNotClosable
+    );
+
+    msg!("Closed curtailment bid");
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(event_id: u16)]
+pub struct OpenDemandResponse<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init,
+        payer = operator,
+        space = 8 + DemandResponseEvent::INIT_SPACE,
+        seeds = [
+            b"dr_event",
+            market.key().as_ref(),
+            operator.key().as_ref(),
+            &event_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub event: Account<'info, DemandResponseEvent>,
+    
+    /// Grid zone whose load is to be reduced
+    #[account(has_one = market)]
+    pub zone: Account<'info, Zone>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(address = market.quote_mint)]
+    pub quote_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = operator,
+        seeds = [b"dr_vault", event.key().as_ref()],
+        bump,
+        token::mint = quote_mint,
+        token::authority = vault_authority
+    )]
+    pub dr_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = operator
+    )]
+    pub operator_token_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+    
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitCurtailmentBid<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market)]
+    pub event: Account<'info, DemandResponseEvent>,
+    
+    /// Consumer's consumption meter the curtailment is measured on
+    #[account(
+        constraint = meter.oracle == market.oracle @ ErrorCode::OracleMismatch,
+        constraint = meter.owner == consumer.key() @ ErrorCode::Unauthorized
+    )]
+    pub meter: Account<'info, EnergyMeter>,
+    
+    /// CHECK: meter's zone assignment address, which may hold no account
+    #[account(
+        seeds = [b"zone_member", market.key().as_ref(), meter.key().as_ref()],
+        bump
+    )]
+    pub meter_zone_member: UncheckedAccount<'info>,
+    
+    #[account(
+        init,
+        payer = consumer,
+        space = 8 + CurtailmentBid::INIT_SPACE,
+        seeds = [b"curtailment_bid", event.key().as_ref(), meter.key().as_ref()],
+        bump
+    )]
+    pub bid: Account<'info, CurtailmentBid>,
+    
+    #[account(mut)]
+    pub consumer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClearDemandResponse<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market, has_one = operator @ ErrorCode::Unauthorized)]
+    pub event: Account<'info, DemandResponseEvent>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"dr_vault", event.key().as_ref()],
+        bump = event.vault_bump
+    )]
+    pub dr_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = operator
+    )]
+    pub operator_token_account: Account<'info, TokenAccount>,
+    
+    pub operator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SettleCurtailment<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market)]
+    pub event: Account<'info, DemandResponseEvent>,
+    
+    #[account(mut, has_one = event, has_one = meter)]
+    pub bid: Account<'info, CurtailmentBid>,
+    
+    /// Consumption meter the bid is measured on
+    pub meter: Account<'info, EnergyMeter>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"dr_vault", event.key().as_ref()],
+        bump = event.vault_bump
+    )]
+    pub dr_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = bid.consumer
+    )]
+    pub consumer_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = event.operator
+    )]
+    pub operator_token_account: Account<'info, TokenAccount>,
+    
+    pub settler: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseCurtailmentBid<'info> {
+    pub event: Account<'info, DemandResponseEvent>,
+    
+    #[account(mut, has_one = event, has_one = consumer, close = consumer)]
+    pub bid: Account<'info, CurtailmentBid>,
+    
+    #[account(mut)]
+    pub consumer: Signer<'info>,
+}
+
+/// Grid operator's request to buy load reduction in a zone during a window
+#[account]
+#[derive(InitSpace)]
+pub struct DemandResponseEvent {
+    pub market: Pubkey,
+    pub operator: Pubkey,
+    pub event_id: u16,
+    pub zone: Pubkey,
+    pub window_start: i64,
+    pub window_end: i64,
+    /// Load reduction wanted over the window, in kWh
+    pub target_reduction: u64,
+    pub max_price_per_kwh: u64,
+    /// Uniform price paid for every accepted kWh, set when the event clears
+    pub clearing_price: u64,
+    pub accepted_reduction: u64,
+    /// Reduction verified against baselines so far
+    pub delivered_reduction: u64,
+    pub total_paid: u64,
+    pub bid_count: u32,
+    pub status: DemandResponseStatus,
+    pub bump: u8,
+    pub vault_bump: u8,
+}
+
+/// A consumer's offer to curtail load during a demand-response event
+#[account]
+#[derive(InitSpace)]
+pub struct CurtailmentBid {
+    pub event: Pubkey,
+    pub consumer: Pubkey,
+    pub meter: Pubkey,
+    pub reduction_amount: u64,
+    pub price_per_kwh: u64,
+    /// Reduction the operator bought, zero if the bid was not accepted
+    pub accepted_amount: u64,
+    /// Average consumption over the event hours on the baseline days, set when bidding
+    pub baseline: u64,
+    pub delivered_amount: u64,
+    pub payout: u64,
+    pub is_settled: bool,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum DemandResponseStatus {
+    /// Taking curtailment bids
+    Open,
+    /// Bids accepted and awaiting settlement
+    Cleared,
+}
+
+#[event]
+pub struct DemandResponseOpenedEvent {
+    pub event: Pubkey,
+    pub market: Pubkey,
+    pub operator: Pubkey,
+    pub zone: Pubkey,
+    pub window_start: i64,
+    pub window_end: i64,
+    pub target_reduction: u64,
+    pub max_price_per_kwh: u64,
+}
+
+#[event]
+pub struct CurtailmentBidSubmittedEvent {
+    pub event: Pubkey,
+    pub consumer: Pubkey,
+    pub meter: Pubkey,
+    pub reduction_amount: u64,
+    pub price_per_kwh: u64,
+    pub baseline: u64,
+}
+
+#[event]
+pub struct DemandResponseClearedEvent {
+    pub event: Pubkey,
+    pub accepted_bids: u32,
+    pub accepted_reduction: u64,
+    pub clearing_price: u64,
+    pub refunded_amount: u64,
+}
+
+#[event]
+pub struct CurtailmentSettledEvent {
+    pub event: Pubkey,
+    pub consumer: Pubkey,
+    pub baseline: u64,
+    pub consumed: u64,
+    pub accepted_amount: u64,
+    pub delivered_amount: u64,
+    pub payout: u64,
+    pub timestamp: i64,
+}
@@ -1,47 +1,325 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer};
+use energy_oracle::{
+    EnergyMeter, MeterReading, MeterType, Oracle, ReadingType, ReferencePrice, WalletLink,
+    READING_ARCHIVE_SECS,
+};
 
 declare_id!("EnergyMarket1111111111111111111111111111111");
 
+mod orderbook;
+pub use orderbook::*;
+mod auction;
+pub use auction::*;
+mod pool;
+pub use pool::*;
+mod community;
+pub use community::*;
+mod demand_response;
+pub use demand_response::*;
+mod zones;
+pub use zones::*;
+
+/// Maximum number of resting orders per side of the order book
+pub const MAX_BOOK_ORDERS: usize = 128;
+/// Maximum number of unsettled fills held in the event queue
+pub const EVENT_QUEUE_CAPACITY: usize = 256;
+/// Maximum number of orders collected by a single call auction
+pub const MAX_AUCTION_ORDERS: usize = 128;
+/// Lamports paid from each closed offer's rent to the account running the expiry crank
+pub const CRANK_BOUNTY_LAMPORTS: u64 = 10_000;
+/// Upper bound for maker and taker fees, in basis points
+pub const MAX_FEE_BPS: u16 = 1_000;
+/// Trading in a scheduled offer closes this many seconds before its delivery window opens
+pub const GATE_CLOSURE_SECS: i64 = 3_600;
+/// Meter readings submitted up to this long after a delivery window still count towards it
+pub const DELIVERY_GRACE_SECS: i64 = 900;
+/// Period after the grace window in which only the seller may settle a delivery
+pub const SELLER_SETTLEMENT_SECS: i64 = 86_400;
+/// Maximum number of occurrences of a recurring offer
+pub const MAX_OCCURRENCES: u16 = 365;
+/// Upper bound for collateral, penalty and insurance rates, in basis points
+pub const MAX_BPS: u16 = 10_000;
+/// Maximum number of neighbouring zones a grid zone can export to (matches `Zone::neighbours`)
+pub const MAX_ZONE_NEIGHBOURS: usize = 8;
+/// Length of the interval inter-zone transfer capacity is measured over
+pub const ZONE_INTERVAL_SECS: i64 = 3_600;
+/// Number of intervals whose inter-zone flows are tracked at once
+pub const ZONE_FLOW_SLOTS: usize = 48;
+/// Reference prices older than this cannot be used to price pegged offers
+pub const MAX_REFERENCE_PRICE_AGE_SECS: i64 = 86_400;
+/// Pause bit blocking new offers, bids and recurring occurrences
+pub const PAUSE_NEW_OFFERS: u8 = 1 << 0;
+/// Pause bit blocking trade execution and order matching
+pub const PAUSE_TRADING: u8 = 1 << 1;
+/// Pause bit blocking cancellations and the expiry crank
+pub const PAUSE_CANCELLATIONS: u8 = 1 << 2;
+/// Pause bit blocking settlement payouts and balance withdrawals
+pub const PAUSE_WITHDRAWALS: u8 = 1 << 3;
+/// Every pause bit
+pub const PAUSE_ALL: u8 = PAUSE_NEW_OFFERS | PAUSE_TRADING | PAUSE_CANCELLATIONS | PAUSE_WITHDRAWALS;
+/// Fixed-point denominator of an accepted mint's price scale
+pub const PRICE_SCALE: u64 = 1_000_000_000;
+/// Highest aggregator fee a pool may charge on its proceeds
+pub const MAX_POOL_FEE_BPS: u16 = 2_000;
+/// Fixed-point scale of the per-share revenue indices of pools and community assets
+pub const ACC_PRECISION: u128 = 1_000_000_000_000;
+/// Longest window a demand-response event may cover
+pub const DR_MAX_WINDOW_SECS: i64 = 6 * 3_600;
+/// Previous days whose consumption over the event hours forms a curtailment baseline
+pub const DR_BASELINE_DAYS: i64 = 5;
+/// Time after a demand-response window during which only the operator may settle,
+/// and after which a meter still missing readings for the window delivers nothing
+pub const DR_OPERATOR_SETTLEMENT_SECS: i64 = 86_400;
+/// Most curtailment bids a demand-response event takes, so it clears in one transaction
+pub const DR_MAX_BIDS: u32 = 24;
+
 #[program]
 pub mod energy_market {
     use super::*;
 
     /// Initialize the energy market
-    pub fn initialize_market(ctx: Context<InitializeMarket>) -> Result<()> {
+    ///
+    /// The market address is derived from the creating authority, quote mint and
+    /// `market_index`, so one authority can run several markets per quote mint.
+    /// The creator is kept on the market, so the address can still be re-derived
+    /// after the authority is transferred.
+    pub fn initialize_market(
+        ctx: Context<InitializeMarket>,
+        market_index: u16,
+        maker_fee_bps: u16,
+        taker_fee_bps: u16,
+        collateral_bps: u16,
+        penalty_bps: u16,
+        insurance_bps: u16,
+    ) -> Result<()> {
+        require!(
+            maker_fee_bps <= MAX_FEE_BPS && taker_fee_bps <= MAX_FEE_BPS,
+            ErrorCode::InvalidFee
+        );
+        require!(
+            collateral_bps <= MAX_BPS && penalty_bps <= MAX_BPS && insurance_bps <= MAX_BPS,
+            ErrorCode::InvalidCollateralSettings
+        );
+
         let market = &mut ctx.accounts.market;
         market.authority = ctx.accounts.authority.key();
+        market.pending_authority = Pubkey::default();
+        market.creator = ctx.accounts.authority.key();
+        market.credit_mint = ctx.accounts.credit_mint.key();
+        market.quote_mint = ctx.accounts.quote_mint.key();
+        market.oracle = ctx.accounts.oracle.key();
+        market.market_index = market_index;
+        market.bump = *ctx.bumps.get("market").unwrap();
+        market.vault_authority_bump = *ctx.bumps.get("vault_authority").unwrap();
+        market.fee_vault_bump = *ctx.bumps.get("fee_vault").unwrap();
+        market.maker_fee_bps = maker_fee_bps;
+        market.taker_fee_bps = taker_fee_bps;
+        market.collateral_vault_bump = *ctx.bumps.get("collateral_vault").unwrap();
+        market.insurance_vault_bump = *ctx.bumps.get("insurance_vault").unwrap();
+        market.collateral_bps = collateral_bps;
+        market.penalty_bps = penalty_bps;
+        market.insurance_bps = insurance_bps;
+        market.total_fees_collected = 0;
         market.total_offers = 0;
+        market.total_bids = 0;
         market.total_volume_traded = 0;
+        market.total_linked_volume = 0;
+        market.total_penalties = 0;
+        market.total_congestion_charges = 0;
         market.is_active = true;
+        market.paused_flags = 0;
+        market.auto_unpause_at = 0;
+        market.self_trade_mode = SelfTradeMode::Reject;
+        market.zone_count = 0;
         
-        msg!("Energy market initialized");
+        msg!("Energy market {} initialized", market_index);
+        Ok(())
+    }
+
+    /// Create the seller account that numbers a seller's offers on a market
+    pub fn initialize_seller_account(ctx: Context<InitializeSellerAccount>) -> Result<()> {
+        let seller_account = &mut ctx.accounts.seller_account;
+        seller_account.market = ctx.accounts.market.key();
+        seller_account.seller = ctx.accounts.seller.key();
+        seller_account.offer_nonce = 0;
+        seller_account.bump = *ctx.bumps.get("seller_account").unwrap();
+
+        msg!("Seller account initialized for {}", seller_account.seller);
         Ok(())
     }
 
-    /// Create an energy offer (sell order)
+    /// Create an energy offer (sell order), escrowing the seller's energy credits
+    ///
+    /// For recurring offers `energy_amount` is the quantity per occurrence and the
+    /// credits for every occurrence are escrowed up front. Scheduled offers also lock
+    /// `market.collateral_bps` of their notional from the seller's quote account.
+    /// The offer address is derived from the seller account's next offer nonce.
+    ///
+    /// With `params.price_offset_bps` the offer is pegged to the oracle reference
+    /// price passed in: it trades at that price adjusted by the offset, never below
+    /// `price_per_kwh`. Buyers must take at least `params.min_fill_amount` per trade,
+    /// unless they take everything that is left.
     pub fn create_offer(
         ctx: Context<CreateOffer>,
         energy_amount: u64,
         price_per_kwh: u64,
         offer_type: OfferType,
         duration_hours: u32,
+        params: OfferParams,
     ) -> Result<()> {
+        ctx.accounts.market.require_not_paused(PAUSE_NEW_OFFERS)?;
+        let OfferParams { delivery_window, recurrence, price_offset_bps, min_fill_amount } = params;
+
         require!(energy_amount > 0, ErrorCode::InvalidAmount);
         require!(price_per_kwh > 0, ErrorCode::InvalidPrice);
         require!(duration_hours > 0 && duration_hours <= 168, ErrorCode::InvalidDuration); // Max 1 week
+        require!(min_fill_amount <= energy_amount, ErrorCode::InvalidMinFill);
+
+        // Scheduled offers, and only those, sell delivery in a future window
+        let now = Clock::get()?.unix_timestamp;
+        let (delivery_start, delivery_end) = match (offer_type, delivery_window) {
+            (OfferType::Scheduled, Some(window)) => {
+                require!(
+                    window.start - GATE_CLOSURE_SECS > now && window.end > window.start,
+                    ErrorCode::InvalidDeliveryWindow
+                );
+                (window.start, window.end)
+            }
+            (OfferType::Scheduled, None) | (_, Some(_)) => {
+                return err!(ErrorCode::InvalidDeliveryWindow);
+            }
+            (_, None) => (0, 0),
+        };
+
+        // Recurring offers, and only those, repeat on a schedule
+        let (recurrence_interval, occurrences) = match (offer_type, recurrence) {
+            (OfferType::Recurring, Some(rule)) => {
+                require!(
+                    rule.occurrences > 0 && rule.occurrences <= MAX_OCCURRENCES,
+                    ErrorCode::InvalidRecurrence
+                );
+                // Occurrences must not overlap
+                require!(
+                    duration_hours as i64 * 3600 <= rule.interval.min_period(),
+                    ErrorCode::InvalidRecurrence
+                );
+                (Some(rule.interval), rule.occurrences)
+            }
+            (OfferType::Recurring, None) | (_, Some(_)) => {
+                return err!(ErrorCode::InvalidRecurrence);
+            }
+            (_, None) => (None, 1),
+        };
+        let escrow_amount = energy_amount
+            .checked_mul(occurrences as u64)
+            .ok_or(ErrorCode::Overflow)?;
+
+        // Scheduled deliveries are verified against the seller's production meter
+        let meter = match &ctx.accounts.meter {
+            Some(meter) => {
+                require!(meter.owner == ctx.accounts.seller.key(), ErrorCode::Unauthorized);
+                require!(
+                    meter.is_authorized && meter.meter_type != MeterType::Consumption,
+                    ErrorCode::InvalidMeter
+                );
+                meter.key()
+            }
+            None => {
+                require!(offer_type != OfferType::Scheduled, ErrorCode::InvalidMeter);
+                Pubkey::default()
+            }
+        };
+
+        // Lock the offered energy credits in the offer vault until traded or cancelled
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.seller_credit_account.to_account_info(),
+            to: ctx.accounts.offer_vault.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, escrow_amount)?;
+
+        // Scheduled offers back their promised delivery with quote collateral
+        let collateral = if offer_type == OfferType::Scheduled {
+            let notional = energy_amount
+                .checked_mul(price_per_kwh)
+                .ok_or(ErrorCode::Overflow)?;
+            calculate_fee(notional, ctx.accounts.market.collateral_bps)?
+        } else {
+            0
+        };
+        if collateral > 0 {
+            let seller_token_account = ctx
+                .accounts
+                .seller_token_account
+                .as_ref()
+                .ok_or(ErrorCode::CollateralAccountRequired)?;
+            let cpi_accounts = Transfer {
+                from: seller_token_account.to_account_info(),
+                to: ctx.accounts.collateral_vault.to_account_info(),
+                authority: ctx.accounts.seller.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::transfer(cpi_ctx, collateral)?;
+        }
+
+        let zone = seller_zone(&ctx.accounts.market, &ctx.accounts.seller_zone_member)?;
+
+        // Pegged offers follow an oracle reference price
+        let price_reference = match price_offset_bps {
+            Some(offset_bps) => {
+                require!(
+                    offset_bps > -(MAX_BPS as i16) && offset_bps <= MAX_BPS as i16,
+                    ErrorCode::InvalidPriceOffset
+                );
+                let reference_price = ctx
+                    .accounts
+                    .reference_price
+                    .as_ref()
+                    .ok_or(ErrorCode::ReferencePriceRequired)?;
+                reference_price.key()
+            }
+            None => Pubkey::default(),
+        };
 
         let offer = &mut ctx.accounts.offer;
         let market = &mut ctx.accounts.market;
         
+        let seller_account = &mut ctx.accounts.seller_account;
         offer.seller = ctx.accounts.seller.key();
+        offer.rent_payer = ctx.accounts.seller.key();
+        offer.proceeds_account = Pubkey::default();
+        offer.market = market.key();
+        offer.nonce = seller_account.offer_nonce;
+        offer.bump = *ctx.bumps.get("offer").unwrap();
+        seller_account.offer_nonce += 1;
         offer.energy_amount = energy_amount;
         offer.price_per_kwh = price_per_kwh;
+        offer.price_reference = price_reference;
+        offer.price_offset_bps = price_offset_bps.unwrap_or(0);
         offer.offer_type = offer_type;
         offer.status = OfferStatus::Active;
-        offer.created_at = Clock::get()?.unix_timestamp;
+        offer.created_at = now;
+        offer.opens_at = now;
         offer.expires_at = offer.created_at + (duration_hours as i64 * 3600);
         offer.filled_amount = 0;
+        offer.min_fill_amount = min_fill_amount;
+        offer.delivery_start = delivery_start;
+        offer.delivery_end = delivery_end;
+        offer.meter = meter;
+        offer.zone = zone;
+        offer.delivered_amount = 0;
+        offer.collateral_locked = collateral;
+        offer.penalty_bps = market.penalty_bps;
+        offer.trade_count = 0;
+        offer.open_trades = 0;
+        offer.recurrence_interval = recurrence_interval;
+        offer.occurrences_remaining = occurrences - 1;
+        offer.occurrence_index = 0;
+        offer.vault_bump = *ctx.bumps.get("offer_vault").unwrap();
 
         market.total_offers += 1;
 
@@ -58,28 +336,116 @@ pub mod energy_market {
         Ok(())
     }
 
-    /// Execute a trade (buy energy)
+    /// Execute a trade (buy energy), swapping payment for escrowed credits
+    ///
+    /// Fails if the offer's current price is above `expected_price`, so a seller
+    /// re-pricing the offer cannot front-run the buyer. A seller taking their own
+    /// offer is handled by the market's self-trade mode instead of trading.
+    ///
+    /// With `accepted_mint` the trade settles in that mint instead of the market's
+    /// quote mint, at the offer's price converted by the mint's price scale. Offers
+    /// paying into a pool's or community asset's vault only settle in the quote mint.
     pub fn execute_trade(
         ctx: Context<ExecuteTrade>,
         energy_amount: u64,
+        expected_price: u64,
     ) -> Result<()> {
+        ctx.accounts.market.require_not_paused(PAUSE_TRADING)?;
+
         require!(energy_amount > 0, ErrorCode::InvalidAmount);
         
         let offer = &mut ctx.accounts.offer;
         let market = &mut ctx.accounts.market;
         
         // Check if offer is still valid
+        let now = Clock::get()?.unix_timestamp;
         require!(offer.status == OfferStatus::Active, ErrorCode::OfferNotActive);
-        require!(Clock::get()?.unix_timestamp < offer.expires_at, ErrorCode::OfferExpired);
+        require!(now >= offer.opens_at, ErrorCode::OfferNotOpen);
+        require!(now < offer.expires_at, ErrorCode::OfferExpired);
+        require!(offer.offer_type != OfferType::Scheduled, ErrorCode::ScheduledOfferRequiresEscrow);
         
         let available_amount = offer.energy_amount - offer.filled_amount;
         require!(energy_amount <= available_amount, ErrorCode::InsufficientEnergy);
+        require!(offer.meets_min_fill(energy_amount), ErrorCode::BelowMinimumFill);
+
+        if ctx.accounts.buyer.key() == offer.seller {
+            let withdrawn = offer.prevent_self_trade(market.self_trade_mode, energy_amount)?;
+            let market_key = market.key();
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.offer_vault,
+                &ctx.accounts.buyer_credit_account,
+                &ctx.accounts.token_program,
+                withdrawn,
+            )?;
+
+            emit!(SelfTradePreventedEvent {
+                market: market_key,
+                trader: offer.seller,
+                mode: market.self_trade_mode,
+                energy_amount: withdrawn,
+                timestamp: now,
+            });
+
+            msg!("Self-trade prevented: {} kWh withdrawn from offer", withdrawn);
+            return Ok(());
+        }
+        let linked_trade = is_linked_trade(
+            ctx.accounts.buyer.key(),
+            &ctx.accounts.buyer_link,
+            offer.seller,
+            &ctx.accounts.seller_link,
+        )?;
         
+        let price_per_kwh = offer.resolve_price(ctx.accounts.reference_price.as_ref(), now)?;
+        require!(price_per_kwh <= expected_price, ErrorCode::PriceSlippage);
         let total_cost = energy_amount
-            .checked_mul(offer.price_per_kwh)
+            .checked_mul(price_per_kwh)
             .ok_or(ErrorCode::Overflow)?;
+        let congestion_charge = apply_zone_transfer(
+            offer,
+            &ctx.accounts.buyer_zone_member,
+            ctx.accounts.buyer_zone.as_ref(),
+            ctx.accounts.zone_link.as_deref_mut(),
+            now,
+            energy_amount,
+        )?;
+
+        // Settling in an accepted mint converts the amounts and collects fees in its own vault
+        let (total_cost, congestion_charge, settlement_mint, fee_vault) =
+            match &ctx.accounts.accepted_mint {
+                Some(accepted_mint) => {
+                    require!(
+                        offer.proceeds_account == Pubkey::default(),
+                        ErrorCode::AcceptedMintUnsupported
+                    );
+                    let fee_vault = ctx
+                        .accounts
+                        .accepted_mint_fee_vault
+                        .as_ref()
+                        .ok_or(ErrorCode::InvalidVault)?;
+                    require!(fee_vault.key() == accepted_mint.fee_vault, ErrorCode::InvalidVault);
+                    (
+                        accepted_mint.convert(total_cost)?,
+                        accepted_mint.convert(congestion_charge)?,
+                        accepted_mint.mint,
+                        fee_vault.to_account_info(),
+                    )
+                }
+                None => (
+                    total_cost,
+                    congestion_charge,
+                    market.quote_mint,
+                    ctx.accounts.fee_vault.to_account_info(),
+                ),
+            };
+        require!(total_cost > 0, ErrorCode::InvalidAmount);
+        // The resting offer is the maker, the buyer the taker
+        let maker_fee = calculate_fee(total_cost, market.maker_fee_bps)?;
+        let taker_fee = calculate_fee(total_cost, market.taker_fee_bps)?;
 
-        // Transfer payment from buyer to seller
+        // Transfer payment, net of the seller's fee, from buyer to seller
         let cpi_accounts = Transfer {
             from: ctx.accounts.buyer_token_account.to_account_info(),
             to: ctx.accounts.seller_token_account.to_account_info(),
@@ -87,7 +453,58 @@ pub mod energy_market {
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, total_cost)?;
+        token::transfer(cpi_ctx, total_cost - maker_fee)?;
+
+        // Transfer both fees from buyer to the fee vault
+        if maker_fee + taker_fee > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: fee_vault.clone(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::transfer(cpi_ctx, maker_fee + taker_fee)?;
+
+            match ctx.accounts.accepted_mint.as_mut() {
+                Some(accepted_mint) => accepted_mint.total_fees_collected += maker_fee + taker_fee,
+                None => market.total_fees_collected += maker_fee + taker_fee,
+            }
+            emit!(FeesCollectedEvent {
+                market: market.key(),
+                mint: settlement_mint,
+                maker_fee,
+                taker_fee,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+        }
+
+        // Inter-zone imports pay the importing zone's price adder
+        if congestion_charge > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: fee_vault,
+                authority: ctx.accounts.buyer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::transfer(cpi_ctx, congestion_charge)?;
+
+            match ctx.accounts.accepted_mint.as_mut() {
+                Some(accepted_mint) => accepted_mint.total_congestion_charges += congestion_charge,
+                None => market.total_congestion_charges += congestion_charge,
+            }
+        }
+
+        // Release the purchased energy credits from escrow to the buyer
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.offer_vault,
+            &ctx.accounts.buyer_credit_account,
+            &ctx.accounts.token_program,
+            energy_amount,
+        )?;
 
         // Update offer
         offer.filled_amount += energy_amount;
@@ -96,14 +513,17 @@ pub mod energy_market {
         }
 
         // Update market stats
-        market.total_volume_traded += energy_amount;
+        market.record_volume(energy_amount, linked_trade);
 
         emit!(TradeExecutedEvent {
             offer_id: offer.key(),
             buyer: ctx.accounts.buyer.key(),
             seller: offer.seller,
             energy_amount,
+            quote_mint: settlement_mint,
             total_cost,
+            congestion_charge,
+            linked_trade,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
@@ -111,169 +531,3734 @@ pub mod energy_market {
         Ok(())
     }
 
-    /// Cancel an active offer
-    pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+    /// Buy `energy_amount` by sweeping several offers, cheapest first
+    ///
+    /// Remaining accounts are passed in groups of four per offer:
+    /// `[offer, offer_vault, seller_token_account, seller_link]`, the first three
+    /// writable, where `seller_link` is the seller's oracle wallet link address
+    /// (whether or not it exists) and `seller_token_account` is the offer's proceeds
+    /// account if it has one. Offers that are not open for immediate trading,
+    /// priced above `max_price_per_kwh`, in another zone than the buyer's or pegged
+    /// to a reference other than `reference_price` are skipped. The buyer's own
+    /// offers are handled by the market's self-trade mode, decremented amounts
+    /// counting towards the sweep. The sweep fills the whole amount or fails, and
+    /// fails if the total cost including the taker fee exceeds `max_total_cost`.
+    ///
+    /// The sweep never crosses zones: it books no inter-zone transfer capacity,
+    /// so offers from other zones must be bought one at a time with `execute_trade`.
+    pub fn buy_best<'info>(
+        ctx: Context<'_, '_, '_, 'info, BuyBest<'info>>,
+        energy_amount: u64,
+        max_price_per_kwh: u64,
+        max_total_cost: u64,
+    ) -> Result<()> {
+        ctx.accounts.market.require_not_paused(PAUSE_TRADING)?;
+
+        require!(energy_amount > 0, ErrorCode::InvalidAmount);
+        require!(
+            !ctx.remaining_accounts.is_empty() && ctx.remaining_accounts.len().is_multiple_of(4),
+            ErrorCode::InvalidRemainingAccounts
+        );
+
+        let market = &mut ctx.accounts.market;
+        let market_key = market.key();
+        let now = Clock::get()?.unix_timestamp;
+        let buyer_zone = assigned_zone(&ctx.accounts.buyer_zone_member)?;
+        let reference_key = ctx.accounts.reference_price.as_ref().map(|price| price.key());
+
+        // Collect the eligible offers with their current price
+        let mut candidates: Vec<(u64, usize)> = Vec::new();
+        for (index, accounts) in ctx.remaining_accounts.chunks_exact(4).enumerate() {
+            let offer: Account<EnergyOffer> = Account::try_from(&accounts[0])?;
+            require!(offer.market == market_key, ErrorCode::Unauthorized);
+            if offer.status != OfferStatus::Active
+                || offer.offer_type == OfferType::Scheduled
+                || now < offer.opens_at
+                || now >= offer.expires_at
+                || (offer.zone != Pubkey::default() && offer.zone != buyer_zone)
+                || (offer.price_reference != Pubkey::default()
+                    && Some(offer.price_reference) != reference_key)
+            {
+                continue;
+            }
+            let price = offer.resolve_price(ctx.accounts.reference_price.as_ref(), now)?;
+            if price <= max_price_per_kwh {
+                candidates.push((price, index));
+            }
+        }
+        candidates.sort();
+
+        let buyer = ctx.accounts.buyer.key();
+        let mut filled: u64 = 0;
+        let mut decremented: u64 = 0;
+        let mut total_cost: u64 = 0;
+        let mut maker_fees: u64 = 0;
+        let mut linked_volume: u64 = 0;
+        for (price, index) in candidates {
+            if filled + decremented == energy_amount {
+                break;
+            }
+            let (offer_info, vault_info, seller_token_info, seller_link_info) = (
+                &ctx.remaining_accounts[index * 4],
+                &ctx.remaining_accounts[index * 4 + 1],
+                &ctx.remaining_accounts[index * 4 + 2],
+                &ctx.remaining_accounts[index * 4 + 3],
+            );
+            let mut offer: Account<EnergyOffer> = Account::try_from(offer_info)?;
+
+            let vault_key = Pubkey::create_program_address(
+                &[b"offer_vault", offer_info.key.as_ref(), &[offer.vault_bump]],
+                &crate::ID,
+            )
+            .map_err(|_| ErrorCode::InvalidVault)?;
+            require!(vault_info.key() == vault_key, ErrorCode::InvalidVault);
+            let seller_token_account: Account<TokenAccount> = Account::try_from(seller_token_info)?;
+            require!(
+                seller_token_account.owner == offer.seller
+                    && seller_token_account.mint == market.quote_mint,
+                ErrorCode::Unauthorized
+            );
+            require!(
+                offer.accepts_proceeds(seller_token_account.key()),
+                ErrorCode::ProceedsAccountMismatch
+            );
+
+            // An offer listed twice is only filled once
+            let fill_amount = (offer.energy_amount - offer.filled_amount)
+                .min(energy_amount - filled - decremented);
+            if offer.status != OfferStatus::Active
+                || fill_amount == 0
+                || !offer.meets_min_fill(fill_amount)
+            {
+                continue;
+            }
+
+            if offer.seller == buyer {
+                let withdrawn = offer.prevent_self_trade(market.self_trade_mode, fill_amount)?;
+                vault_transfer(
+                    market,
+                    &ctx.accounts.vault_authority,
+                    vault_info,
+                    &ctx.accounts.buyer_credit_account,
+                    &ctx.accounts.token_program,
+                    withdrawn,
+                )?;
+                offer.exit(&crate::ID)?;
+
+                if market.self_trade_mode == SelfTradeMode::DecrementBoth {
+                    decremented += withdrawn;
+                }
+                emit!(SelfTradePreventedEvent {
+                    market: market_key,
+                    trader: buyer,
+                    mode: market.self_trade_mode,
+                    energy_amount: withdrawn,
+                    timestamp: now,
+                });
+                continue;
+            }
+
+            let (seller_link_key, _) = Pubkey::find_program_address(
+                &[b"wallet_link", offer.seller.as_ref()],
+                &energy_oracle::ID,
+            );
+            require!(seller_link_info.key() == seller_link_key, ErrorCode::InvalidWalletLink);
+            let linked_trade =
+                is_linked_trade(buyer, &ctx.accounts.buyer_link, offer.seller, seller_link_info)?;
+            let cost = fill_amount.checked_mul(price).ok_or(ErrorCode::Overflow)?;
+            let maker_fee = calculate_fee(cost, market.maker_fee_bps)?;
+
+            // Pay the seller, net of their fee
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: seller_token_info.clone(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::transfer(cpi_ctx, cost - maker_fee)?;
+
+            // Release the credits from the offer's escrow to the buyer
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                vault_info,
+                &ctx.accounts.buyer_credit_account,
+                &ctx.accounts.token_program,
+                fill_amount,
+            )?;
+
+            offer.filled_amount += fill_amount;
+            if offer.filled_amount >= offer.energy_amount {
+                offer.status = OfferStatus::Completed;
+            }
+            offer.exit(&crate::ID)?;
+
+            filled += fill_amount;
+            total_cost = total_cost.checked_add(cost).ok_or(ErrorCode::Overflow)?;
+            maker_fees += maker_fee;
+            if linked_trade {
+                linked_volume += fill_amount;
+            }
+
+            emit!(TradeExecutedEvent {
+                offer_id: offer_info.key(),
+                buyer,
+                seller: offer.seller,
+                energy_amount: fill_amount,
+                quote_mint: market.quote_mint,
+                total_cost: cost,
+                congestion_charge: 0,
+                linked_trade,
+                timestamp: now,
+            });
+        }
+
+        require!(filled + decremented == energy_amount, ErrorCode::InsufficientEnergy);
+        let taker_fee = calculate_fee(total_cost, market.taker_fee_bps)?;
+        require!(
+            total_cost.checked_add(taker_fee).ok_or(ErrorCode::Overflow)? <= max_total_cost,
+            ErrorCode::PriceSlippage
+        );
+
+        // Collect the sellers' fees and the buyer's taker fee in one transfer
+        if maker_fees + taker_fee > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: ctx.accounts.fee_vault.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::transfer(cpi_ctx, maker_fees + taker_fee)?;
+
+            market.total_fees_collected += maker_fees + taker_fee;
+            emit!(FeesCollectedEvent {
+                market: market_key,
+                mint: market.quote_mint,
+                maker_fee: maker_fees,
+                taker_fee,
+                timestamp: now,
+            });
+        }
+
+        market.record_volume(filled - linked_volume, false);
+        market.record_volume(linked_volume, true);
+
+        msg!("Swept {} kWh for {} credits", filled, total_cost);
+        Ok(())
+    }
+
+    /// Buy from a scheduled offer, holding the payment in escrow until the delivery window ends
+    pub fn execute_scheduled_trade(
+        ctx: Context<ExecuteScheduledTrade>,
+        energy_amount: u64,
+        expected_price: u64,
+    ) -> Result<()> {
+        ctx.accounts.market.require_not_paused(PAUSE_TRADING)?;
+
+        require!(energy_amount > 0, ErrorCode::InvalidAmount);
+
         let offer = &mut ctx.accounts.offer;
-        
+        let market = &mut ctx.accounts.market;
+        let now = Clock::get()?.unix_timestamp;
+
+        // Check if offer is still valid
         require!(offer.status == OfferStatus::Active, ErrorCode::OfferNotActive);
-        require!(offer.seller == ctx.accounts.seller.key(), ErrorCode::Unauthorized);
-        
-        offer.status = OfferStatus::Cancelled;
+        require!(now < offer.expires_at, ErrorCode::OfferExpired);
+        require!(offer.offer_type == OfferType::Scheduled, ErrorCode::OfferNotScheduled);
+        require!(now < offer.delivery_start - GATE_CLOSURE_SECS, ErrorCode::GateClosed);
+        // Scheduled offers back deliveries with collateral, so self-trades are always rejected
+        require!(ctx.accounts.buyer.key() != offer.seller, ErrorCode::SelfTrade);
+        let linked_trade = is_linked_trade(
+            ctx.accounts.buyer.key(),
+            &ctx.accounts.buyer_link,
+            offer.seller,
+            &ctx.accounts.seller_link,
+        )?;
 
-        emit!(OfferCancelledEvent {
+        let available_amount = offer.energy_amount - offer.filled_amount;
+        require!(energy_amount <= available_amount, ErrorCode::InsufficientEnergy);
+        require!(offer.meets_min_fill(energy_amount), ErrorCode::BelowMinimumFill);
+
+        let price_per_kwh = offer.resolve_price(ctx.accounts.reference_price.as_ref(), now)?;
+        require!(price_per_kwh <= expected_price, ErrorCode::PriceSlippage);
+        let total_cost = energy_amount
+            .checked_mul(price_per_kwh)
+            .ok_or(ErrorCode::Overflow)?;
+        let taker_fee = calculate_fee(total_cost, market.taker_fee_bps)?;
+        // Transfer capacity is reserved in the interval delivery starts in
+        let congestion_charge = apply_zone_transfer(
+            offer,
+            &ctx.accounts.buyer_zone_member,
+            ctx.accounts.buyer_zone.as_ref(),
+            ctx.accounts.zone_link.as_deref_mut(),
+            offer.delivery_start,
+            energy_amount,
+        )?;
+
+        // Hold the payment in the trade escrow until delivery
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.buyer_token_account.to_account_info(),
+            to: ctx.accounts.trade_escrow.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, total_cost)?;
+
+        // The buyer's taker fee is collected up front
+        if taker_fee > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: ctx.accounts.fee_vault.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::transfer(cpi_ctx, taker_fee)?;
+
+            market.total_fees_collected += taker_fee;
+            emit!(FeesCollectedEvent {
+                market: market.key(),
+                mint: market.quote_mint,
+                maker_fee: 0,
+                taker_fee,
+                timestamp: now,
+            });
+        }
+
+        // Inter-zone imports pay the importing zone's price adder up front
+        if congestion_charge > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: ctx.accounts.fee_vault.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::transfer(cpi_ctx, congestion_charge)?;
+
+            market.total_congestion_charges += congestion_charge;
+        }
+
+        let trade = &mut ctx.accounts.trade;
+        trade.offer = offer.key();
+        trade.market = market.key();
+        trade.buyer = ctx.accounts.buyer.key();
+        trade.seller = offer.seller;
+        trade.energy_amount = energy_amount;
+        trade.total_cost = total_cost;
+        trade.delivery_start = offer.delivery_start;
+        trade.delivery_end = offer.delivery_end;
+        trade.meter = offer.meter;
+        trade.delivered_amount = 0;
+        trade.executed_at = now;
+        trade.is_settled = false;
+        trade.bump = *ctx.bumps.get("trade").unwrap();
+        trade.escrow_bump = *ctx.bumps.get("trade_escrow").unwrap();
+
+        // Credits stay in the offer vault, reserved for this trade
+        offer.filled_amount += energy_amount;
+        offer.trade_count += 1;
+        offer.open_trades += 1;
+        if offer.filled_amount >= offer.energy_amount {
+            offer.status = OfferStatus::Completed;
+        }
+
+        market.record_volume(energy_amount, linked_trade);
+
+        emit!(TradeExecutedEvent {
             offer_id: offer.key(),
+            buyer: trade.buyer,
             seller: offer.seller,
-            timestamp: Clock::get()?.unix_timestamp,
+            energy_amount,
+            quote_mint: market.quote_mint,
+            total_cost,
+            congestion_charge,
+            linked_trade,
+            timestamp: now,
         });
 
-        msg!("Offer cancelled");
+        msg!("Scheduled trade executed: {} kWh for {} credits, delivery {} - {}",
+             energy_amount, total_cost, trade.delivery_start, trade.delivery_end);
         Ok(())
     }
 
-    /// Update market settings (admin only)
-    pub fn update_market_settings(
-        ctx: Context<UpdateMarketSettings>,
-        is_active: bool,
+    /// Settle a scheduled trade against the seller's verified meter production
+    ///
+    /// Production is the growth of the meter's cumulative register over the
+    /// delivery window. The `MeterReading` accounts covering the window's start
+    /// and end are passed as remaining accounts, in that order (the same account
+    /// twice if one reading spans both); a boundary before the meter's first or
+    /// after its last reading needs none. Payment is released in proportion to the
+    /// verified delivery; the buyer is refunded for any shortfall and the undelivered
+    /// credits go back to the seller. A shortfall also slashes the seller's
+    /// collateral by `penalty_bps` of the undelivered value, split between the
+    /// buyer and the market's insurance vault. Only the seller may settle during
+    /// the first `SELLER_SETTLEMENT_SECS`, after which anyone can. Once the oracle
+    /// may have archived the readings, `READING_ARCHIVE_SECS` after the window
+    /// opens, the trade can be settled without them as undelivered.
+    pub fn settle_delivery<'info>(
+        ctx: Context<'_, '_, '_, 'info, SettleDelivery<'info>>,
     ) -> Result<()> {
+        ctx.accounts.market.require_not_paused(PAUSE_WITHDRAWALS)?;
+
+        let trade = &mut ctx.accounts.trade;
+        let offer = &mut ctx.accounts.offer;
         let market = &mut ctx.accounts.market;
-        require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
-        
-        market.is_active = is_active;
-        
-        msg!("Market settings updated: active = {}", is_active);
-        Ok(())
-    }
-}
+        let meter = &ctx.accounts.meter;
+        let now = Clock::get()?.unix_timestamp;
 
-#[derive(Accounts)]
-pub struct InitializeMarket<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + Market::INIT_SPACE
-    )]
-    pub market: Account<'info, Market>,
-    
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    
-    pub system_program: Program<'info, System>,
-}
+        require!(!trade.is_settled, ErrorCode::TradeAlreadySettled);
+        let readings_close = trade.delivery_end + DELIVERY_GRACE_SECS;
+        require!(now >= readings_close, ErrorCode::DeliveryWindowOpen);
+        if now < readings_close + SELLER_SETTLEMENT_SECS {
+            require!(ctx.accounts.settler.key() == trade.seller, ErrorCode::Unauthorized);
+        }
 
-#[derive(Accounts)]
-pub struct CreateOffer<'info> {
-    #[account(
-        init,
-        payer = seller,
-        space = 8 + EnergyOffer::INIT_SPACE
-    )]
-    pub offer: Account<'info, EnergyOffer>,
-    
-    #[account(mut)]
-    pub market: Account<'info, Market>,
-    
-    #[account(mut)]
-    pub seller: Signer<'info>,
-    
-    pub system_program: Program<'info, System>,
-}
+        // Production over the delivery window, read off the meter's cumulative register
+        let readings_archived = now >= trade.delivery_start + READING_ARCHIVE_SECS
+            && ctx.remaining_accounts.is_empty()
+            && (register_needs_reading(meter, trade.delivery_start)
+                || register_needs_reading(meter, trade.delivery_end));
+        let produced = if readings_archived {
+            0
+        } else {
+            let mut readings = ctx.remaining_accounts.iter();
+            let start_register = meter_register_at(
+                meter,
+                ReadingType::Production,
+                trade.delivery_start,
+                &mut readings,
+            )?;
+            let end_register = meter_register_at(
+                meter,
+                ReadingType::Production,
+                trade.delivery_end,
+                &mut readings,
+            )?;
+            end_register.saturating_sub(start_register)
+        };
 
-#[derive(Accounts)]
-pub struct ExecuteTrade<'info> {
-    #[account(mut)]
-    pub offer: Account<'info, EnergyOffer>,
-    
-    #[account(mut)]
-    pub market: Account<'info, Market>,
-    
-    #[account(mut)]
-    pub buyer_token_account: Account<'info, TokenAccount>,
-    
-    #[account(mut)]
-    pub seller_token_account: Account<'info, TokenAccount>,
-    
-    pub buyer: Signer<'info>,
-    
-    pub token_program: Program<'info, Token>,
-}
+        // Production already credited to earlier trades of this offer is not reused
+        let delivered_amount = produced
+            .saturating_sub(offer.delivered_amount)
+            .min(trade.energy_amount);
+        let seller_payment = u64::try_from(
+            (trade.total_cost as u128) * (delivered_amount as u128) / (trade.energy_amount as u128),
+        )
+        .map_err(|_| ErrorCode::Overflow)?;
+        let buyer_refund = trade.total_cost - seller_payment;
+        let maker_fee = calculate_fee(seller_payment, market.maker_fee_bps)?;
 
-#[derive(Accounts)]
-pub struct CancelOffer<'info> {
-    #[account(mut)]
-    pub offer: Account<'info, EnergyOffer>,
-    
-    pub seller: Signer<'info>,
-}
+        let market_key = market.key();
 
-#[derive(Accounts)]
-pub struct UpdateMarketSettings<'info> {
-    #[account(mut)]
-    pub market: Account<'info, Market>,
-    
-    pub authority: Signer<'info>,
-}
+        // Deliver the verified credits to the buyer, return the rest to the seller
+        if delivered_amount > 0 {
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.offer_vault,
+                &ctx.accounts.buyer_credit_account,
+                &ctx.accounts.token_program,
+                delivered_amount,
+            )?;
+        }
+        if delivered_amount < trade.energy_amount {
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.offer_vault,
+                &ctx.accounts.seller_credit_account,
+                &ctx.accounts.token_program,
+                trade.energy_amount - delivered_amount,
+            )?;
+        }
 
-#[account]
-#[derive(InitSpace)]
-pub struct Market {
-    pub authority: Pubkey,
-    pub total_offers: u64,
-    pub total_volume_traded: u64,
-    pub is_active: bool,
-}
+        // Pay the seller for what was delivered, net of the maker fee
+        if seller_payment > maker_fee {
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.trade_escrow,
+                &ctx.accounts.seller_token_account,
+                &ctx.accounts.token_program,
+                seller_payment - maker_fee,
+            )?;
+        }
 
-#[account]
-#[derive(InitSpace)]
-pub struct EnergyOffer {
-    pub seller: Pubkey,
-    pub energy_amount: u64,
-    pub price_per_kwh: u64,
-    pub offer_type: OfferType,
-    pub status: OfferStatus,
-    pub created_at: i64,
-    pub expires_at: i64,
-    pub filled_amount: u64,
-}
+        if maker_fee > 0 {
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.trade_escrow,
+                &ctx.accounts.fee_vault,
+                &ctx.accounts.token_program,
+                maker_fee,
+            )?;
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
-pub enum OfferType {
-    Immediate,
-    Scheduled,
-    Recurring,
-}
+            market.total_fees_collected += maker_fee;
+            emit!(FeesCollectedEvent {
+                market: market_key,
+                mint: market.quote_mint,
+                maker_fee,
+                taker_fee: 0,
+                timestamp: now,
+            });
+        }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
-pub enum OfferStatus {
-    Active,
-    Completed,
-    Cancelled,
-    Expired,
-}
+        // Refund the buyer for the shortfall
+        if buyer_refund > 0 {
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.trade_escrow,
+                &ctx.accounts.buyer_token_account,
+                &ctx.accounts.token_program,
+                buyer_refund,
+            )?;
+        }
 
-#[event]
-pub struct OfferCreatedEvent {
-    pub offer_id: Pubkey,
-    pub seller: Pubkey,
-    pub energy_amount: u64,
-    pub price_per_kwh: u64,
-    pub offer_type: OfferType,
-    pub expires_at: i64,
-}
+        // Slash the seller's collateral for the undelivered energy
+        let penalty = calculate_fee(buyer_refund, offer.penalty_bps)?.min(offer.collateral_locked);
+        if penalty > 0 {
+            let insurance_amount = calculate_fee(penalty, market.insurance_bps)?;
+            let buyer_compensation = penalty - insurance_amount;
 
-#[event]
-pub struct TradeExecutedEvent {
-    pub offer_id: Pubkey,
-    pub buyer: Pubkey,
-    pub seller: Pubkey,
-    pub energy_amount: u64,
-    pub total_cost: u64,
-    pub timestamp: i64,
-}
+            if buyer_compensation > 0 {
+                vault_transfer(
+                    market,
+                    &ctx.accounts.vault_authority,
+                    &ctx.accounts.collateral_vault,
+                    &ctx.accounts.buyer_token_account,
+                    &ctx.accounts.token_program,
+                    buyer_compensation,
+                )?;
+            }
 
-#[event]
-pub struct OfferCancelledEvent {
-    pub offer_id: Pubkey,
-    pub seller: Pubkey,
+            if insurance_amount > 0 {
+                vault_transfer(
+                    market,
+                    &ctx.accounts.vault_authority,
+                    &ctx.accounts.collateral_vault,
+                    &ctx.accounts.insurance_vault,
+                    &ctx.accounts.token_program,
+                    insurance_amount,
+                )?;
+            }
+
+            offer.collateral_locked -= penalty;
+            market.total_penalties += penalty;
+
+            emit!(CollateralSlashedEvent {
+                offer_id: offer.key(),
+                trade: trade.key(),
+                seller: trade.seller,
+                buyer: trade.buyer,
+                buyer_compensation,
+                insurance_amount,
+                timestamp: now,
+            });
+        }
+
+        // Trading closed at gate closure, so the last settled trade frees the collateral
+        if offer.open_trades == 1 && offer.collateral_locked > 0 {
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.collateral_vault,
+                &ctx.accounts.seller_token_account,
+                &ctx.accounts.token_program,
+                offer.collateral_locked,
+            )?;
+            offer.collateral_locked = 0;
+        }
+
+        trade.is_settled = true;
+        trade.delivered_amount = delivered_amount;
+        offer.delivered_amount += delivered_amount;
+        offer.open_trades -= 1;
+
+        emit!(DeliverySettledEvent {
+            trade: trade.key(),
+            offer_id: offer.key(),
+            meter: meter.key(),
+            buyer: trade.buyer,
+            seller: trade.seller,
+            energy_amount: trade.energy_amount,
+            delivered_amount,
+            seller_payment,
+            buyer_refund,
+            timestamp: now,
+        });
+
+        msg!("Delivery settled: {} of {} kWh verified", delivered_amount, trade.energy_amount);
+        Ok(())
+    }
+
+    /// Amend an active offer in place, keeping its address and queue position
+    ///
+    /// `energy_amount` is the new total for the current occurrence and must stay above
+    /// what has already been filled; the escrow (and, for scheduled offers, the
+    /// collateral) is topped up or refunded to match, though collateral is not
+    /// reduced while sold deliveries are unsettled. `expires_at` can only be
+    /// extended, up to 168 hours after the occurrence opened.
+    pub fn amend_offer(
+        ctx: Context<AmendOffer>,
+        price_per_kwh: Option<u64>,
+        energy_amount: Option<u64>,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        ctx.accounts.market.require_not_paused(PAUSE_NEW_OFFERS)?;
+
+        let offer = &mut ctx.accounts.offer;
+        let market = &ctx.accounts.market;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(offer.status == OfferStatus::Active, ErrorCode::OfferNotActive);
+        require!(offer.seller == ctx.accounts.seller.key(), ErrorCode::Unauthorized);
+        require!(now < offer.expires_at, ErrorCode::OfferExpired);
+        if offer.offer_type == OfferType::Scheduled {
+            require!(now < offer.delivery_start - GATE_CLOSURE_SECS, ErrorCode::GateClosed);
+        }
+
+        let new_price = price_per_kwh.unwrap_or(offer.price_per_kwh);
+        let new_amount = energy_amount.unwrap_or(offer.energy_amount);
+        let new_expires_at = expires_at.unwrap_or(offer.expires_at);
+        require!(new_price > 0, ErrorCode::InvalidPrice);
+        require!(new_amount > offer.filled_amount, ErrorCode::InvalidAmount);
+        require!(
+            new_expires_at >= offer.expires_at && new_expires_at - offer.opens_at <= 168 * 3600,
+            ErrorCode::InvalidDuration
+        );
+        if let Some(interval) = offer.recurrence_interval {
+            // Occurrences must not overlap
+            require!(
+                new_expires_at - offer.opens_at <= interval.min_period(),
+                ErrorCode::InvalidDuration
+            );
+        }
+
+        // The amount applies to the current and every remaining occurrence
+        let occurrences = 1 + offer.occurrences_remaining as u64;
+        if new_amount > offer.energy_amount {
+            let top_up = (new_amount - offer.energy_amount)
+                .checked_mul(occurrences)
+                .ok_or(ErrorCode::Overflow)?;
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.seller_credit_account.to_account_info(),
+                to: ctx.accounts.offer_vault.to_account_info(),
+                authority: ctx.accounts.seller.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token::transfer(cpi_ctx, top_up)?;
+        } else if new_amount < offer.energy_amount {
+            let refund = (offer.energy_amount - new_amount)
+                .checked_mul(occurrences)
+                .ok_or(ErrorCode::Overflow)?;
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.offer_vault,
+                &ctx.accounts.seller_credit_account,
+                &ctx.accounts.token_program,
+                refund,
+            )?;
+        }
+
+        // Scheduled offers keep collateral in line with their new notional
+        if offer.offer_type == OfferType::Scheduled {
+            let notional = new_amount
+                .checked_mul(new_price)
+                .ok_or(ErrorCode::Overflow)?;
+            let mut collateral = calculate_fee(notional, market.collateral_bps)?;
+            // Sold deliveries were backed at their sale price, so none of it is released early
+            if offer.open_trades > 0 {
+                collateral = collateral.max(offer.collateral_locked);
+            }
+            if collateral != offer.collateral_locked {
+                let seller_token_account = ctx
+                    .accounts
+                    .seller_token_account
+                    .as_ref()
+                    .ok_or(ErrorCode::CollateralAccountRequired)?;
+                if collateral > offer.collateral_locked {
+                    let cpi_accounts = Transfer {
+                        from: seller_token_account.to_account_info(),
+                        to: ctx.accounts.collateral_vault.to_account_info(),
+                        authority: ctx.accounts.seller.to_account_info(),
+                    };
+                    let cpi_program = ctx.accounts.token_program.to_account_info();
+                    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+                    token::transfer(cpi_ctx, collateral - offer.collateral_locked)?;
+                } else {
+                    vault_transfer(
+                        market,
+                        &ctx.accounts.vault_authority,
+                        &ctx.accounts.collateral_vault,
+                        seller_token_account,
+                        &ctx.accounts.token_program,
+                        offer.collateral_locked - collateral,
+                    )?;
+                }
+                offer.collateral_locked = collateral;
+            }
+        }
+
+        offer.price_per_kwh = new_price;
+        offer.energy_amount = new_amount;
+        offer.expires_at = new_expires_at;
+
+        emit!(OfferAmendedEvent {
+            offer_id: offer.key(),
+            seller: offer.seller,
+            price_per_kwh: new_price,
+            energy_amount: new_amount,
+            expires_at: new_expires_at,
+            timestamp: now,
+        });
+
+        msg!("Offer amended: {} kWh at {} credits/kWh", new_amount, new_price);
+        Ok(())
+    }
+
+    /// Cancel an active offer and refund the unfilled credits
+    pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+        ctx.accounts.market.require_not_paused(PAUSE_CANCELLATIONS)?;
+
+        let offer = &mut ctx.accounts.offer;
+        let market = &ctx.accounts.market;
+        
+        // A recurring offer can also be cancelled between occurrences
+        require!(
+            offer.status == OfferStatus::Active
+                || (offer.status == OfferStatus::Completed && offer.occurrences_remaining > 0),
+            ErrorCode::OfferNotActive
+        );
+        require!(offer.seller == ctx.accounts.seller.key(), ErrorCode::Unauthorized);
+
+        let refund_amount = offer.unsold_amount();
+        if refund_amount > 0 {
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.offer_vault,
+                &ctx.accounts.seller_credit_account,
+                &ctx.accounts.token_program,
+                refund_amount,
+            )?;
+        }
+
+        // Collateral stays locked while sold deliveries are still unsettled
+        let collateral_released = if offer.open_trades == 0 { offer.collateral_locked } else { 0 };
+        if collateral_released > 0 {
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.collateral_vault,
+                &ctx.accounts.seller_token_account,
+                &ctx.accounts.token_program,
+                collateral_released,
+            )?;
+            offer.collateral_locked = 0;
+        }
+        
+        offer.status = OfferStatus::Cancelled;
+        offer.occurrences_remaining = 0;
+
+        emit!(OfferCancelledEvent {
+            offer_id: offer.key(),
+            seller: offer.seller,
+            refunded_amount: refund_amount,
+            collateral_released,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Offer cancelled");
+        Ok(())
+    }
+
+    /// Crank: open the next occurrence of a recurring offer
+    ///
+    /// Runs once the current occurrence has completed or expired. The unsold part of
+    /// the finished occurrence, and of any occurrences missed while the crank was
+    /// idle, is refunded to the seller; the rest of the escrow carries over.
+    pub fn roll_recurring_offer(ctx: Context<RollRecurringOffer>) -> Result<()> {
+        ctx.accounts.market.require_not_paused(PAUSE_NEW_OFFERS)?;
+
+        let offer = &mut ctx.accounts.offer;
+        let market = &ctx.accounts.market;
+        let now = Clock::get()?.unix_timestamp;
+
+        let interval = offer.recurrence_interval.ok_or(ErrorCode::OfferNotRecurring)?;
+        require!(offer.occurrences_remaining > 0, ErrorCode::NoOccurrencesRemaining);
+        require!(
+            offer.status == OfferStatus::Completed
+                || (offer.status == OfferStatus::Active && now >= offer.expires_at),
+            ErrorCode::OccurrenceStillOpen
+        );
+
+        let duration = offer.expires_at - offer.opens_at;
+        let mut refund_amount = offer.energy_amount - offer.filled_amount;
+        let mut opens_at = interval.next_start(offer.opens_at);
+        offer.occurrences_remaining -= 1;
+        offer.occurrence_index += 1;
+
+        // Skip occurrences whose window has already passed
+        while opens_at + duration <= now && offer.occurrences_remaining > 0 {
+            refund_amount += offer.energy_amount;
+            opens_at = interval.next_start(opens_at);
+            offer.occurrences_remaining -= 1;
+            offer.occurrence_index += 1;
+        }
+
+        if refund_amount > 0 {
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.offer_vault,
+                &ctx.accounts.seller_credit_account,
+                &ctx.accounts.token_program,
+                refund_amount,
+            )?;
+        }
+
+        offer.opens_at = opens_at;
+        offer.expires_at = opens_at + duration;
+        offer.filled_amount = 0;
+        offer.status = OfferStatus::Active;
+
+        emit!(OfferRolledEvent {
+            offer_id: offer.key(),
+            seller: offer.seller,
+            occurrence_index: offer.occurrence_index,
+            occurrences_remaining: offer.occurrences_remaining,
+            opens_at,
+            expires_at: offer.expires_at,
+            refunded_amount: refund_amount,
+        });
+
+        msg!("Recurring offer rolled to occurrence {}", offer.occurrence_index);
+        Ok(())
+    }
+
+    /// Close a completed or cancelled offer and its vault, returning the rent to the seller
+    ///
+    /// Scheduled offers can only be closed once every trade has settled and their
+    /// collateral has been released. Any credits left in the vault are refunded.
+    pub fn close_offer(ctx: Context<CloseOffer>) -> Result<()> {
+        ctx.accounts.market.require_not_paused(PAUSE_CANCELLATIONS)?;
+
+        let offer = &ctx.accounts.offer;
+        let market = &ctx.accounts.market;
+        require!(
+            (offer.status == OfferStatus::Completed && offer.occurrences_remaining == 0)
+                || offer.status == OfferStatus::Cancelled,
+            ErrorCode::OfferNotClosable
+        );
+        require!(
+            offer.open_trades == 0 && offer.collateral_locked == 0,
+            ErrorCode::OfferNotClosable
+        );
+
+        let market_key = market.key();
+
+        let refund_amount = ctx.accounts.offer_vault.amount;
+        if refund_amount > 0 {
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.offer_vault,
+                &ctx.accounts.seller_credit_account,
+                &ctx.accounts.token_program,
+                refund_amount,
+            )?;
+        }
+        vault_close_account(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.offer_vault,
+            &ctx.accounts.seller,
+            &ctx.accounts.token_program,
+        )?;
+
+        emit!(OfferClosedEvent {
+            offer_id: offer.key(),
+            market: market_key,
+            seller: offer.seller,
+            status: offer.status.clone(),
+            energy_amount: offer.energy_amount,
+            filled_amount: offer.filled_amount,
+            refunded_amount: refund_amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Offer closed");
+        Ok(())
+    }
+
+    /// Crank: expire offers past `expires_at`, refund their escrow and close them
+    ///
+    /// Remaining accounts are passed in groups of five per offer:
+    /// `[offer, offer_vault, seller_credit_account, seller_token_account, seller]`,
+    /// all writable. Offers that are no longer active or not yet expired are skipped,
+    /// as are pool and community offers (see `close_pool_offer`).
+    /// Any collateral still locked by an offer is returned to `seller_token_account`.
+    pub fn expire_offers<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExpireOffers<'info>>,
+    ) -> Result<()> {
+        ctx.accounts.market.require_not_paused(PAUSE_CANCELLATIONS)?;
+
+        require!(
+            !ctx.remaining_accounts.is_empty() && ctx.remaining_accounts.len().is_multiple_of(5),
+            ErrorCode::InvalidRemainingAccounts
+        );
+
+        let market = &ctx.accounts.market;
+        let now = Clock::get()?.unix_timestamp;
+        let market_key = market.key();
+
+        let mut expired_count: u32 = 0;
+        for accounts in ctx.remaining_accounts.chunks_exact(5) {
+            let (offer_info, vault_info, seller_credit_info, seller_token_info, seller_info) =
+                (&accounts[0], &accounts[1], &accounts[2], &accounts[3], &accounts[4]);
+
+            let mut offer: Account<EnergyOffer> = Account::try_from(offer_info)?;
+            require!(offer.market == market_key, ErrorCode::Unauthorized);
+            // Scheduled offers keep their vault until every trade has been released,
+            // recurring offers until their last occurrence. Pool and community offers
+            // are closed by their operators, who paid the rent.
+            if offer.status != OfferStatus::Active
+                || now < offer.expires_at
+                || offer.open_trades > 0
+                || offer.occurrences_remaining > 0
+                || offer.rent_payer != offer.seller
+            {
+                continue;
+            }
+
+            let vault_key = Pubkey::create_program_address(
+                &[b"offer_vault", offer_info.key.as_ref(), &[offer.vault_bump]],
+                &crate::ID,
+            )
+            .map_err(|_| ErrorCode::InvalidVault)?;
+            require!(vault_info.key() == vault_key, ErrorCode::InvalidVault);
+            require!(seller_info.key() == offer.seller, ErrorCode::Unauthorized);
+
+            let vault: Account<TokenAccount> = Account::try_from(vault_info)?;
+            let seller_credit_account: Account<TokenAccount> = Account::try_from(seller_credit_info)?;
+            require!(
+                seller_credit_account.owner == offer.seller
+                    && seller_credit_account.mint == market.credit_mint,
+                ErrorCode::Unauthorized
+            );
+
+            // Refund the unfilled credits and close the vault back to the seller
+            let refund_amount = vault.amount;
+            if refund_amount > 0 {
+                vault_transfer(
+                    market,
+                    &ctx.accounts.vault_authority,
+                    vault_info,
+                    seller_credit_info,
+                    &ctx.accounts.token_program,
+                    refund_amount,
+                )?;
+            }
+            vault_close_account(
+                market,
+                &ctx.accounts.vault_authority,
+                vault_info,
+                seller_info,
+                &ctx.accounts.token_program,
+            )?;
+
+            if offer.collateral_locked > 0 {
+                let seller_token_account: Account<TokenAccount> = Account::try_from(seller_token_info)?;
+                require!(
+                    seller_token_account.owner == offer.seller
+                        && seller_token_account.mint == market.quote_mint,
+                    ErrorCode::Unauthorized
+                );
+                vault_transfer(
+                    market,
+                    &ctx.accounts.vault_authority,
+                    &ctx.accounts.collateral_vault,
+                    seller_token_info,
+                    &ctx.accounts.token_program,
+                    offer.collateral_locked,
+                )?;
+                offer.collateral_locked = 0;
+            }
+
+            offer.status = OfferStatus::Expired;
+
+            // Pay the crank bounty out of the offer's rent, the rest goes to the seller
+            let bounty = CRANK_BOUNTY_LAMPORTS.min(offer_info.lamports());
+            **offer_info.try_borrow_mut_lamports()? -= bounty;
+            **ctx.accounts.cranker.try_borrow_mut_lamports()? += bounty;
+            offer.close(seller_info.clone())?;
+
+            emit!(OfferExpiredEvent {
+                offer_id: offer_info.key(),
+                seller: offer.seller,
+                refunded_amount: refund_amount,
+                crank_bounty: bounty,
+                timestamp: now,
+            });
+            expired_count += 1;
+        }
+
+        msg!("Expired {} offers", expired_count);
+        Ok(())
+    }
+
+    /// Create an energy bid (buy order), escrowing the buyer's payment at the maximum price
+    pub fn create_bid(
+        ctx: Context<CreateBid>,
+        energy_amount: u64,
+        max_price_per_kwh: u64,
+        duration_hours: u32,
+    ) -> Result<()> {
+        ctx.accounts.market.require_not_paused(PAUSE_NEW_OFFERS)?;
+
+        require!(energy_amount > 0, ErrorCode::InvalidAmount);
+        require!(max_price_per_kwh > 0, ErrorCode::InvalidPrice);
+        require!(duration_hours > 0 && duration_hours <= 168, ErrorCode::InvalidDuration); // Max 1 week
+
+        let max_total_cost = energy_amount
+            .checked_mul(max_price_per_kwh)
+            .ok_or(ErrorCode::Overflow)?;
+        let maker_fee_bps = ctx.accounts.market.maker_fee_bps;
+        let max_maker_fee = calculate_fee(max_total_cost, maker_fee_bps)?;
+
+        // Lock the maximum payment and fee in the bid vault until filled or cancelled
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.buyer_token_account.to_account_info(),
+            to: ctx.accounts.bid_vault.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, max_total_cost + max_maker_fee)?;
+
+        let bid = &mut ctx.accounts.bid;
+        let market = &mut ctx.accounts.market;
+
+        bid.buyer = ctx.accounts.buyer.key();
+        bid.market = market.key();
+        bid.energy_amount = energy_amount;
+        bid.max_price_per_kwh = max_price_per_kwh;
+        bid.status = OfferStatus::Active;
+        bid.created_at = Clock::get()?.unix_timestamp;
+        bid.expires_at = bid.created_at + (duration_hours as i64 * 3600);
+        bid.filled_amount = 0;
+        bid.maker_fee_bps = maker_fee_bps;
+        bid.vault_bump = *ctx.bumps.get("bid_vault").unwrap();
+
+        market.total_bids += 1;
+
+        emit!(BidCreatedEvent {
+            bid_id: bid.key(),
+            buyer: bid.buyer,
+            energy_amount,
+            max_price_per_kwh,
+            expires_at: bid.expires_at,
+        });
+
+        msg!("Created energy bid: {} kWh at up to {} credits/kWh", energy_amount, max_price_per_kwh);
+        Ok(())
+    }
+
+    /// Fill a bid (sell energy), delivering credits for the escrowed payment
+    ///
+    /// A bidder filling their own bid is handled by the market's self-trade mode:
+    /// the withdrawn part of the escrow is refunded to `seller_token_account`.
+    pub fn fill_bid(
+        ctx: Context<FillBid>,
+        energy_amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.market.require_not_paused(PAUSE_TRADING)?;
+
+        require!(energy_amount > 0, ErrorCode::InvalidAmount);
+
+        let bid = &mut ctx.accounts.bid;
+        let market = &mut ctx.accounts.market;
+
+        // Check if bid is still valid
+        require!(bid.status == OfferStatus::Active, ErrorCode::BidNotActive);
+        require!(Clock::get()?.unix_timestamp < bid.expires_at, ErrorCode::BidExpired);
+
+        let remaining_amount = bid.energy_amount - bid.filled_amount;
+        require!(energy_amount <= remaining_amount, ErrorCode::InsufficientEnergy);
+
+        if ctx.accounts.seller.key() == bid.buyer {
+            let withdrawn = market.self_trade_mode.withdrawn(remaining_amount, energy_amount)?;
+            bid.filled_amount += withdrawn;
+            // An exhausted bid refunds its whole escrow, fee rounding dust included
+            let refund_amount = if bid.filled_amount >= bid.energy_amount {
+                if market.self_trade_mode == SelfTradeMode::CancelResting {
+                    bid.status = OfferStatus::Cancelled;
+                } else {
+                    bid.status = OfferStatus::Completed;
+                }
+                ctx.accounts.bid_vault.amount
+            } else {
+                let notional = withdrawn
+                    .checked_mul(bid.max_price_per_kwh)
+                    .ok_or(ErrorCode::Overflow)?;
+                notional + calculate_fee(notional, bid.maker_fee_bps)?
+            };
+
+            let market_key = market.key();
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.bid_vault,
+                &ctx.accounts.seller_token_account,
+                &ctx.accounts.token_program,
+                refund_amount,
+            )?;
+
+            emit!(SelfTradePreventedEvent {
+                market: market_key,
+                trader: bid.buyer,
+                mode: market.self_trade_mode,
+                energy_amount: withdrawn,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+
+            msg!("Self-trade prevented: {} kWh withdrawn from bid", withdrawn);
+            return Ok(());
+        }
+
+        // Bids carry no transfer path, so they can only be filled from the buyer's own zone
+        require!(
+            seller_zone(market, &ctx.accounts.seller_zone_member)?
+                == assigned_zone(&ctx.accounts.buyer_zone_member)?,
+            ErrorCode::ZonesNotConnected
+        );
+        let linked_trade = is_linked_trade(
+            bid.buyer,
+            &ctx.accounts.buyer_link,
+            ctx.accounts.seller.key(),
+            &ctx.accounts.seller_link,
+        )?;
+
+        let total_cost = energy_amount
+            .checked_mul(bid.max_price_per_kwh)
+            .ok_or(ErrorCode::Overflow)?;
+        // The resting bid is the maker, the seller the taker
+        let maker_fee = calculate_fee(total_cost, bid.maker_fee_bps)?;
+        let taker_fee = calculate_fee(total_cost, market.taker_fee_bps)?;
+
+        // Deliver energy credits from seller to buyer
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.seller_credit_account.to_account_info(),
+            to: ctx.accounts.buyer_credit_account.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, energy_amount)?;
+
+        // Release the escrowed payment, net of the seller's fee, to the seller
+        let market_key = market.key();
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.bid_vault,
+            &ctx.accounts.seller_token_account,
+            &ctx.accounts.token_program,
+            total_cost - taker_fee,
+        )?;
+
+        // Move both fees from the bid escrow to the fee vault
+        if maker_fee + taker_fee > 0 {
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.bid_vault,
+                &ctx.accounts.fee_vault,
+                &ctx.accounts.token_program,
+                maker_fee + taker_fee,
+            )?;
+
+            market.total_fees_collected += maker_fee + taker_fee;
+            emit!(FeesCollectedEvent {
+                market: market_key,
+                mint: market.quote_mint,
+                maker_fee,
+                taker_fee,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+        }
+
+        // Update bid
+        bid.filled_amount += energy_amount;
+        if bid.filled_amount >= bid.energy_amount {
+            bid.status = OfferStatus::Completed;
+        }
+
+        // Update market stats
+        market.record_volume(energy_amount, linked_trade);
+
+        emit!(BidFilledEvent {
+            bid_id: bid.key(),
+            buyer: bid.buyer,
+            seller: ctx.accounts.seller.key(),
+            energy_amount,
+            total_cost,
+            linked_trade,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Bid filled: {} kWh for {} credits", energy_amount, total_cost);
+        Ok(())
+    }
+
+    /// Cancel an active bid and refund the unspent payment
+    ///
+    /// A filled bid may also be closed this way to refund whatever escrow the
+    /// fills left behind.
+    pub fn cancel_bid(ctx: Context<CancelBid>) -> Result<()> {
+        ctx.accounts.market.require_not_paused(PAUSE_CANCELLATIONS)?;
+
+        let bid = &mut ctx.accounts.bid;
+        let market = &ctx.accounts.market;
+
+        // A filled bid can still be closed to reclaim the escrow left over from fee rounding
+        let refund_amount = ctx.accounts.bid_vault.amount;
+        require!(
+            bid.status == OfferStatus::Active
+                || (bid.status == OfferStatus::Completed && refund_amount > 0),
+            ErrorCode::BidNotActive
+        );
+        require!(bid.buyer == ctx.accounts.buyer.key(), ErrorCode::Unauthorized);
+
+        if refund_amount > 0 {
+            vault_transfer(
+                market,
+                &ctx.accounts.vault_authority,
+                &ctx.accounts.bid_vault,
+                &ctx.accounts.buyer_token_account,
+                &ctx.accounts.token_program,
+                refund_amount,
+            )?;
+        }
+
+        if bid.status == OfferStatus::Active {
+            bid.status = OfferStatus::Cancelled;
+        }
+
+        emit!(BidCancelledEvent {
+            bid_id: bid.key(),
+            buyer: bid.buyer,
+            refunded_amount: refund_amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Bid cancelled");
+        Ok(())
+    }
+
+    /// Set up the market's order book, event queue and settlement vaults (admin only, once)
+    pub fn initialize_order_book(ctx: Context<InitializeOrderBook>) -> Result<()> {
+        orderbook::initialize_order_book(ctx)
+    }
+
+    /// Open a trader's order book balance account
+    pub fn initialize_open_orders(ctx: Context<InitializeOpenOrders>) -> Result<()> {
+        orderbook::initialize_open_orders(ctx)
+    }
+
+    /// Place a limit order, matching crossing orders at price-time priority
+    ///
+    /// `time_in_force` controls what happens to the part that does not fill
+    /// immediately: it rests on the book (`GoodTillCancelled`), is left unplaced
+    /// (`ImmediateOrCancel`) or fails the order (`FillOrKill`). `PostOnly` orders
+    /// fail instead of taking liquidity.
+    pub fn place_order(
+        ctx: Context<PlaceOrder>,
+        side: Side,
+        price_per_kwh: u64,
+        energy_amount: u64,
+        time_in_force: TimeInForce,
+    ) -> Result<()> {
+        orderbook::place_order(ctx, side, price_per_kwh, energy_amount, time_in_force)
+    }
+
+    /// Cancel a resting order and unlock its funds
+    pub fn cancel_order(
+        ctx: Context<CancelOrder>,
+        side: Side,
+        order_id: u64,
+    ) -> Result<()> {
+        orderbook::cancel_order(ctx, side, order_id)
+    }
+
+    /// Crank: settle queued fills into the makers' open orders accounts
+    ///
+    /// The makers' `OpenOrders` accounts are passed as writable remaining accounts.
+    /// Processing stops at the first event whose maker account was not supplied.
+    pub fn consume_events<'info>(
+        ctx: Context<'_, '_, '_, 'info, ConsumeEvents<'info>>,
+        limit: u16,
+    ) -> Result<()> {
+        orderbook::consume_events(ctx, limit)
+    }
+
+    /// Withdraw free order book balances to the owner's token accounts
+    pub fn settle_funds(ctx: Context<SettleFunds>) -> Result<()> {
+        orderbook::settle_funds(ctx)
+    }
+
+    /// Open a uniform-price call auction for a delivery interval (admin only)
+    pub fn open_auction(
+        ctx: Context<OpenAuction>,
+        delivery_start: i64,
+        delivery_end: i64,
+        gate_closure: i64,
+    ) -> Result<()> {
+        auction::open_auction(ctx, delivery_start, delivery_end, gate_closure)
+    }
+
+    /// Submit an order to a call auction, escrowing credits (asks) or payment (bids)
+    ///
+    /// Orders are not matched against each other until the auction is cleared.
+    pub fn submit_auction_order(
+        ctx: Context<SubmitAuctionOrder>,
+        side: Side,
+        price_per_kwh: u64,
+        energy_amount: u64,
+    ) -> Result<()> {
+        auction::submit_auction_order(ctx, side, price_per_kwh, energy_amount)
+    }
+
+    /// Clear a call auction at the single price that maximises matched volume
+    pub fn clear_auction(ctx: Context<ClearAuction>) -> Result<()> {
+        auction::clear_auction(ctx)
+    }
+
+    /// Pay out a cleared auction order at the clearing price and refund the unmatched escrow
+    pub fn settle_auction_order(
+        ctx: Context<SettleAuctionOrder>,
+        order_index: u32,
+    ) -> Result<()> {
+        auction::settle_auction_order(ctx, order_index)
+    }
+
+    /// Create an aggregator pool that sells many prosumers' credits as one seller
+    ///
+    /// Contributors pool credits while the pool is collecting; the operator then
+    /// posts offers on the pool's behalf and keeps `fee_bps` of the proceeds.
+    pub fn create_pool(ctx: Context<CreatePool>, pool_id: u16, fee_bps: u16) -> Result<()> {
+        pool::create_pool(ctx, pool_id, fee_bps)
+    }
+
+    /// Open a contributor's share record in a pool
+    pub fn join_pool(ctx: Context<JoinPool>) -> Result<()> {
+        pool::join_pool(ctx)
+    }
+
+    /// Contribute energy credits to a collecting pool, one share per credit
+    pub fn contribute_to_pool(ctx: Context<ContributeToPool>, amount: u64) -> Result<()> {
+        pool::contribute_to_pool(ctx, amount)
+    }
+
+    /// Withdraw credits from a pool by redeeming shares
+    ///
+    /// A collecting pool returns one credit per share. A closed pool returns the
+    /// shares' pro-rata part of the credits it still holds, so contributors should
+    /// wait for the pool's open offers to be cancelled or expire before leaving.
+    pub fn withdraw_from_pool(ctx: Context<WithdrawFromPool>, shares: u64) -> Result<()> {
+        pool::withdraw_from_pool(ctx, shares)
+    }
+
+    /// Stop collecting and start selling the pooled credits (operator only)
+    ///
+    /// Shares are fixed from here on, so every contributor owns the same fraction of
+    /// each credit the pool sells.
+    pub fn start_pool_selling(ctx: Context<UpdatePool>) -> Result<()> {
+        pool::start_pool_selling(ctx)
+    }
+
+    /// Stop selling and let contributors withdraw the unsold credits (operator only)
+    ///
+    /// Every pool offer must have been closed first, so that all unsold credits are
+    /// back in the pool's vault.
+    pub fn close_pool(ctx: Context<UpdatePool>) -> Result<()> {
+        pool::close_pool(ctx)
+    }
+
+    /// Post an immediate offer of pooled credits (operator only)
+    ///
+    /// The pool is the offer's seller, so trade proceeds land in the pool's quote
+    /// vault and unsold credits return to its credit vault.
+    pub fn create_pool_offer(
+        ctx: Context<CreatePoolOffer>,
+        energy_amount: u64,
+        price_per_kwh: u64,
+        duration_hours: u32,
+        min_fill_amount: u64,
+    ) -> Result<()> {
+        pool::create_pool_offer(ctx, energy_amount, price_per_kwh, duration_hours, min_fill_amount)
+    }
+
+    /// Cancel a pool offer, returning its unsold credits to the pool (operator only)
+    pub fn cancel_pool_offer(ctx: Context<CancelPoolOffer>) -> Result<()> {
+        pool::cancel_pool_offer(ctx)
+    }
+
+    /// Close a finished or expired pool offer, returning the rent to the operator (operator only)
+    ///
+    /// Credits left in the offer vault go back to the pool.
+    pub fn close_pool_offer(ctx: Context<ClosePoolOffer>) -> Result<()> {
+        pool::close_pool_offer(ctx)
+    }
+
+    /// Claim a contributor's pro-rata share of the pool's trade proceeds
+    pub fn claim_pool_proceeds(ctx: Context<ClaimPoolProceeds>) -> Result<()> {
+        pool::claim_pool_proceeds(ctx)
+    }
+
+    /// Claim the aggregator fee accrued on the pool's proceeds (operator only)
+    pub fn claim_pool_fees(ctx: Context<ClaimPoolFees>) -> Result<()> {
+        pool::claim_pool_fees(ctx)
+    }
+
+    /// Create a community asset splitting a shared solar meter's output by share units
+    ///
+    /// The meter's registered owner manages the asset. Credits minted through
+    /// energy-credit to the asset's credit vault, and proceeds of the asset's own
+    /// offers, are split between share holders according to their shares.
+    pub fn create_community_asset(ctx: Context<CreateCommunityAsset>) -> Result<()> {
+        community::create_community_asset(ctx)
+    }
+
+    /// Open a resident's share holding in a community asset
+    pub fn open_share_holding(ctx: Context<OpenShareHolding>) -> Result<()> {
+        community::open_share_holding(ctx)
+    }
+
+    /// Issue share units of a community asset to a holding (asset authority only)
+    ///
+    /// New shares only earn from revenue arriving after they are issued.
+    pub fn issue_shares(ctx: Context<IssueShares>, shares: u64) -> Result<()> {
+        community::issue_shares(ctx, shares)
+    }
+
+    /// Transfer share units of a community asset to another resident
+    ///
+    /// Revenue earned before the transfer stays with the sender.
+    pub fn transfer_shares(ctx: Context<TransferShares>, shares: u64) -> Result<()> {
+        community::transfer_shares(ctx, shares)
+    }
+
+    /// Choose how a community asset pays out its credits (asset authority only)
+    ///
+    /// `Credits` splits newly minted credits between holders in kind; `Proceeds`
+    /// keeps them for the authority to sell through community offers. Credits
+    /// already split stay with their holders.
+    pub fn set_community_payout(
+        ctx: Context<UpdateCommunityAsset>,
+        payout: CommunityPayout,
+    ) -> Result<()> {
+        community::set_community_payout(ctx, payout)
+    }
+
+    /// Claim a holder's share of a community asset's credits and proceeds
+    pub fn claim_community_revenue(ctx: Context<ClaimCommunityRevenue>) -> Result<()> {
+        community::claim_community_revenue(ctx)
+    }
+
+    /// Post an immediate offer of a community asset's unsplit credits (asset authority only)
+    ///
+    /// The asset is the offer's seller, so trade proceeds land in its quote vault
+    /// and unsold credits return to its credit vault.
+    pub fn create_community_offer(
+        ctx: Context<CreateCommunityOffer>,
+        energy_amount: u64,
+        price_per_kwh: u64,
+        duration_hours: u32,
+        min_fill_amount: u64,
+    ) -> Result<()> {
+        community::create_community_offer(
+            ctx,
+            energy_amount,
+            price_per_kwh,
+            duration_hours,
+            min_fill_amount,
+        )
+    }
+
+    /// Cancel a community offer, returning its unsold credits to the asset (asset authority only)
+    pub fn cancel_community_offer(ctx: Context<CancelCommunityOffer>) -> Result<()> {
+        community::cancel_community_offer(ctx)
+    }
+
+    /// Close a finished or expired community offer, returning the rent to the asset authority
+    ///
+    /// Credits left in the offer vault go back to the asset.
+    pub fn close_community_offer(ctx: Context<CloseCommunityOffer>) -> Result<()> {
+        community::close_community_offer(ctx)
+    }
+
+    /// Open a demand-response event buying load reduction in a zone
+    ///
+    /// The operator escrows `target_reduction` kWh at `max_price_per_kwh` as the
+    /// event budget. Consumers bid curtailment until `GATE_CLOSURE_SECS` before the
+    /// window opens.
+    pub fn open_demand_response(
+        ctx: Context<OpenDemandResponse>,
+        event_id: u16,
+        window_start: i64,
+        window_end: i64,
+        target_reduction: u64,
+        max_price_per_kwh: u64,
+    ) -> Result<()> {
+        demand_response::open_demand_response(
+            ctx,
+            event_id,
+            window_start,
+            window_end,
+            target_reduction,
+            max_price_per_kwh,
+        )
+    }
+
+    /// Bid to curtail consumption during a demand-response event
+    ///
+    /// The bid is measured on the consumer's `Consumption` meter, which must be
+    /// assigned to the event's zone. Its baseline is fixed here: the average growth
+    /// of the meter's consumption register over the event hours on each of the
+    /// previous `DR_BASELINE_DAYS` days. The `MeterReading` accounts covering the
+    /// start and end of those hours are passed as remaining accounts, most recent
+    /// day first, so bidding opens once the meter has reported the last of them.
+    pub fn submit_curtailment_bid<'info>(
+        ctx: Context<'_, '_, '_, 'info, SubmitCurtailmentBid<'info>>,
+        reduction_amount: u64,
+        price_per_kwh: u64,
+    ) -> Result<()> {
+        demand_response::submit_curtailment_bid(ctx, reduction_amount, price_per_kwh)
+    }
+
+    /// Clear a demand-response event once bidding has closed (operator only)
+    ///
+    /// Every curtailment bid of the event is passed as a remaining account, cheapest
+    /// first, and bids are accepted until the target is met; the marginal bid may be
+    /// accepted in part.
+    /// Every accepted bid is paid the price of the last accepted one. The budget
+    /// not needed at that price goes back to the operator. Clearing after the
+    /// window has opened accepts no bids and cancels the event.
+    pub fn clear_demand_response<'info>(
+        ctx: Context<'_, '_, '_, 'info, ClearDemandResponse<'info>>,
+    ) -> Result<()> {
+        demand_response::clear_demand_response(ctx)
+    }
+
+    /// Settle an accepted curtailment bid against the consumer's metered consumption
+    ///
+    /// Consumption is the growth of the meter's register over the event window;
+    /// the `MeterReading` accounts covering the window's start and end are passed
+    /// as remaining accounts, in that order. The consumer is paid the clearing
+    /// price for the reduction below the bid's baseline, up to the accepted amount,
+    /// and the rest of the bid's escrow goes back to the operator. Only the operator
+    /// may settle during the first `DR_OPERATOR_SETTLEMENT_SECS`, after which anyone
+    /// can; a meter that has not reported past the window by then delivers nothing.
+    /// Once the oracle may have archived the readings, `READING_ARCHIVE_SECS` after
+    /// the window opens, the bid can be settled without them as delivering nothing.
+    pub fn settle_curtailment<'info>(
+        ctx: Context<'_, '_, '_, 'info, SettleCurtailment<'info>>,
+    ) -> Result<()> {
+        demand_response::settle_curtailment(ctx)
+    }
+
+    /// Close a finished curtailment bid, returning its rent to the consumer
+    ///
+    /// A bid finishes once it is settled, or when its event cleared without it.
+    pub fn close_curtailment_bid(ctx: Context<CloseCurtailmentBid>) -> Result<()> {
+        demand_response::close_curtailment_bid(ctx)
+    }
+
+    /// Register a grid zone under the market (admin only)
+    ///
+    /// Once a market has zones, every seller must be assigned to one and the order
+    /// book and call auctions are closed to trading.
+    pub fn register_zone(
+        ctx: Context<RegisterZone>,
+        zone_id: u16,
+        price_adder_per_kwh: u64,
+    ) -> Result<()> {
+        zones::register_zone(ctx, zone_id, price_adder_per_kwh)
+    }
+
+    /// Update the price adder charged on imports into a zone (admin only)
+    pub fn update_zone(ctx: Context<UpdateZone>, price_adder_per_kwh: u64) -> Result<()> {
+        zones::update_zone(ctx, price_adder_per_kwh)
+    }
+
+    /// Connect two zones with a one-way transfer limit per interval (admin only)
+    pub fn create_zone_link(ctx: Context<CreateZoneLink>, transfer_capacity: u64) -> Result<()> {
+        zones::create_zone_link(ctx, transfer_capacity)
+    }
+
+    /// Change the transfer capacity of a zone link (admin only)
+    pub fn update_zone_link(ctx: Context<UpdateZoneLink>, transfer_capacity: u64) -> Result<()> {
+        zones::update_zone_link(ctx, transfer_capacity)
+    }
+
+    /// Assign a trading wallet or meter to a grid zone (admin only)
+    ///
+    /// Offers take their zone from the seller's assignment (a pool's own address,
+    /// a community asset's meter) and trades check the buyer's.
+    pub fn assign_zone(ctx: Context<AssignZone>, member: Pubkey) -> Result<()> {
+        zones::assign_zone(ctx, member)
+    }
+
+    /// Remove a wallet or meter from its grid zone (admin only)
+    pub fn unassign_zone(ctx: Context<UnassignZone>) -> Result<()> {
+        zones::unassign_zone(ctx)
+    }
+
+    /// Update maker and taker fees (admin only)
+    pub fn update_fees(
+        ctx: Context<UpdateMarketSettings>,
+        maker_fee_bps: u16,
+        taker_fee_bps: u16,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+        require!(
+            maker_fee_bps <= MAX_FEE_BPS && taker_fee_bps <= MAX_FEE_BPS,
+            ErrorCode::InvalidFee
+        );
+
+        market.maker_fee_bps = maker_fee_bps;
+        market.taker_fee_bps = taker_fee_bps;
+
+        msg!("Market fees updated: maker = {} bps, taker = {} bps", maker_fee_bps, taker_fee_bps);
+        Ok(())
+    }
+
+    /// Update the collateral requirement and under-delivery penalty (admin only)
+    ///
+    /// Offers keep the penalty rate in force when they were created.
+    pub fn update_collateral_settings(
+        ctx: Context<UpdateMarketSettings>,
+        collateral_bps: u16,
+        penalty_bps: u16,
+        insurance_bps: u16,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+        require!(
+            collateral_bps <= MAX_BPS && penalty_bps <= MAX_BPS && insurance_bps <= MAX_BPS,
+            ErrorCode::InvalidCollateralSettings
+        );
+
+        market.collateral_bps = collateral_bps;
+        market.penalty_bps = penalty_bps;
+        market.insurance_bps = insurance_bps;
+
+        msg!("Collateral settings updated: collateral = {} bps, penalty = {} bps, insurance = {} bps",
+             collateral_bps, penalty_bps, insurance_bps);
+        Ok(())
+    }
+
+    /// Withdraw collected fees from the fee vault (admin only)
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+        market.require_not_paused(PAUSE_WITHDRAWALS)?;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let market_key = market.key();
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.fee_vault,
+            &ctx.accounts.destination,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        emit!(FeesWithdrawnEvent {
+            market: market_key,
+            destination: ctx.accounts.destination.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Withdrew {} in fees", amount);
+        Ok(())
+    }
+
+    /// Pay out of the insurance vault (admin only)
+    ///
+    /// Covers losses that slashed collateral did not, such as a defaulting seller
+    /// whose collateral ran out. The market authority may be a governance multisig.
+    pub fn pay_insurance(ctx: Context<PayInsurance>, amount: u64) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+        market.require_not_paused(PAUSE_WITHDRAWALS)?;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let market_key = market.key();
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.insurance_vault,
+            &ctx.accounts.destination,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        emit!(InsurancePaidEvent {
+            market: market_key,
+            destination: ctx.accounts.destination.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Paid {} from the insurance vault", amount);
+        Ok(())
+    }
+
+    /// Accept an additional mint for settling trades (admin only)
+    ///
+    /// `price_scale` is the number of whole `mint` tokens per whole quote-mint
+    /// token, scaled by `PRICE_SCALE`. Decimals are taken from both mints.
+    pub fn add_accepted_mint(ctx: Context<AddAcceptedMint>, price_scale: u64) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+        require!(price_scale > 0, ErrorCode::InvalidPriceScale);
+        require!(ctx.accounts.mint.key() != market.quote_mint, ErrorCode::MintAlreadyAccepted);
+
+        let accepted_mint = &mut ctx.accounts.accepted_mint;
+        accepted_mint.market = market.key();
+        accepted_mint.mint = ctx.accounts.mint.key();
+        accepted_mint.decimals = ctx.accounts.mint.decimals;
+        accepted_mint.quote_decimals = ctx.accounts.quote_mint.decimals;
+        accepted_mint.price_scale = price_scale;
+        accepted_mint.fee_vault = ctx.accounts.fee_vault.key();
+        accepted_mint.is_active = true;
+        accepted_mint.total_fees_collected = 0;
+        accepted_mint.total_congestion_charges = 0;
+        accepted_mint.bump = *ctx.bumps.get("accepted_mint").unwrap();
+
+        emit!(AcceptedMintUpdatedEvent {
+            market: market.key(),
+            mint: accepted_mint.mint,
+            price_scale,
+            is_active: true,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Accepted mint {} at scale {}", accepted_mint.mint, price_scale);
+        Ok(())
+    }
+
+    /// Re-price or delist an accepted mint (admin only)
+    pub fn update_accepted_mint(
+        ctx: Context<UpdateAcceptedMint>,
+        price_scale: u64,
+        is_active: bool,
+    ) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+        require!(price_scale > 0, ErrorCode::InvalidPriceScale);
+
+        let accepted_mint = &mut ctx.accounts.accepted_mint;
+        accepted_mint.price_scale = price_scale;
+        accepted_mint.is_active = is_active;
+
+        emit!(AcceptedMintUpdatedEvent {
+            market: market.key(),
+            mint: accepted_mint.mint,
+            price_scale,
+            is_active,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Accepted mint {} updated: scale {}, active = {}", accepted_mint.mint, price_scale, is_active);
+        Ok(())
+    }
+
+    /// Withdraw fees collected in an accepted mint (admin only)
+    pub fn withdraw_accepted_mint_fees(
+        ctx: Context<WithdrawAcceptedMintFees>,
+        amount: u64,
+    ) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+        market.require_not_paused(PAUSE_WITHDRAWALS)?;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let market_key = market.key();
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.fee_vault,
+            &ctx.accounts.destination,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        emit!(FeesWithdrawnEvent {
+            market: market_key,
+            destination: ctx.accounts.destination.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Withdrew {} in fees of mint {}", amount, ctx.accounts.accepted_mint.mint);
+        Ok(())
+    }
+
+    /// Update market settings (admin only)
+    ///
+    /// An inactive market accepts no new offers and no trades; cancellations and
+    /// withdrawals stay open.
+    pub fn update_market_settings(
+        ctx: Context<UpdateMarketSettings>,
+        is_active: bool,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+        
+        market.is_active = is_active;
+
+        emit!(MarketPausedEvent {
+            market: market.key(),
+            is_active,
+            paused_flags: market.paused_flags,
+            auto_unpause_at: market.auto_unpause_at,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        msg!("Market settings updated: active = {}", is_active);
+        Ok(())
+    }
+
+    /// Propose a new market authority (admin only)
+    ///
+    /// The transfer completes once the proposed authority calls `accept_authority`.
+    /// Proposing the default pubkey withdraws a pending proposal.
+    pub fn propose_authority(
+        ctx: Context<UpdateMarketSettings>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+
+        market.pending_authority = new_authority;
+
+        emit!(AuthorityProposedEvent {
+            market: market.key(),
+            authority: market.authority,
+            pending_authority: new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Market authority proposed: {}", new_authority);
+        Ok(())
+    }
+
+    /// Accept a proposed market authority transfer
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let new_authority = ctx.accounts.new_authority.key();
+        require!(
+            market.pending_authority != Pubkey::default() && market.pending_authority == new_authority,
+            ErrorCode::Unauthorized
+        );
+
+        let previous_authority = market.authority;
+        market.authority = new_authority;
+        market.pending_authority = Pubkey::default();
+
+        emit!(AuthorityTransferredEvent {
+            market: market.key(),
+            previous_authority,
+            new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Market authority transferred to {}", new_authority);
+        Ok(())
+    }
+
+    /// Pause individual market operations (admin only)
+    ///
+    /// `paused_flags` is a combination of the `PAUSE_*` bits and replaces the
+    /// current set. With `auto_unpause_at` the pause lapses on its own at that time.
+    pub fn set_pause(
+        ctx: Context<UpdateMarketSettings>,
+        paused_flags: u8,
+        auto_unpause_at: Option<i64>,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+        require!(paused_flags & !PAUSE_ALL == 0, ErrorCode::InvalidPauseFlags);
+
+        let now = Clock::get()?.unix_timestamp;
+        if let Some(unpause_at) = auto_unpause_at {
+            require!(unpause_at > now, ErrorCode::InvalidPauseFlags);
+        }
+
+        market.paused_flags = paused_flags;
+        market.auto_unpause_at = auto_unpause_at.unwrap_or(0);
+
+        emit!(MarketPausedEvent {
+            market: market.key(),
+            is_active: market.is_active,
+            paused_flags,
+            auto_unpause_at: market.auto_unpause_at,
+            timestamp: now,
+        });
+
+        msg!("Market pause flags set: {:#06b}", paused_flags);
+        Ok(())
+    }
+
+    /// Choose how the market handles a trader taking their own resting order (admin only)
+    pub fn set_self_trade_mode(
+        ctx: Context<UpdateMarketSettings>,
+        self_trade_mode: SelfTradeMode,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+
+        market.self_trade_mode = self_trade_mode;
+
+        msg!("Market self-trade mode updated");
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct InitializeMarket<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Market::INIT_SPACE,
+        seeds = [
+            b"market",
+            authority.key().as_ref(),
+            quote_mint.key().as_ref(),
+            &market_index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: PDA that owns every token vault of this market
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    /// Energy credit mint traded on this market
+    pub credit_mint: Account<'info, Mint>,
+    
+    /// Token mint trades on this market are paid in
+    pub quote_mint: Account<'info, Mint>,
+    
+    /// Oracle whose meters and reference prices the market accepts
+    pub oracle: Account<'info, Oracle>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"fee_vault", market.key().as_ref()],
+        bump,
+        token::mint = quote_mint,
+        token::authority = vault_authority
+    )]
+    pub fee_vault: Account<'info, TokenAccount>,
+    
+    /// Holds the collateral locked by sellers of scheduled offers
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"collateral_vault", market.key().as_ref()],
+        bump,
+        token::mint = quote_mint,
+        token::authority = vault_authority
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    /// Receives the insurance share of slashed collateral
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"insurance_vault", market.key().as_ref()],
+        bump,
+        token::mint = quote_mint,
+        token::authority = vault_authority
+    )]
+    pub insurance_vault: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+    
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeSellerAccount<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + SellerAccount::INIT_SPACE,
+        seeds = [b"seller", market.key().as_ref(), seller.key().as_ref()],
+        bump
+    )]
+    pub seller_account: Account<'info, SellerAccount>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateOffer<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"seller", market.key().as_ref(), seller.key().as_ref()],
+        bump = seller_account.bump
+    )]
+    pub seller_account: Account<'info, SellerAccount>,
+    
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + EnergyOffer::INIT_SPACE,
+        seeds = [
+            b"offer",
+            market.key().as_ref(),
+            seller.key().as_ref(),
+            &seller_account.offer_nonce.to_le_bytes()
+        ],
+        bump
+    )]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(address = market.credit_mint)]
+    pub credit_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = seller,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump,
+        token::mint = credit_mint,
+        token::authority = vault_authority
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint,
+        token::authority = seller
+    )]
+    pub seller_credit_account: Account<'info, TokenAccount>,
+    
+    /// Seller's production meter, required for scheduled offers
+    #[account(constraint = meter.oracle == market.oracle @ ErrorCode::OracleMismatch)]
+    pub meter: Option<Account<'info, EnergyMeter>>,
+    
+    /// CHECK: seller's zone assignment address, which may hold no account
+    #[account(
+        seeds = [b"zone_member", market.key().as_ref(), seller.key().as_ref()],
+        bump
+    )]
+    pub seller_zone_member: UncheckedAccount<'info>,
+    
+    /// Oracle reference price, required for pegged offers
+    #[account(constraint = reference_price.oracle == market.oracle @ ErrorCode::OracleMismatch)]
+    pub reference_price: Option<Account<'info, ReferencePrice>>,
+    
+    /// Seller's quote account, required when the offer must lock collateral
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = seller
+    )]
+    pub seller_token_account: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", market.key().as_ref()],
+        bump = market.collateral_vault_bump
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+    
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteTrade<'info> {
+    #[account(mut, has_one = market)]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump = offer.vault_bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.settlement_mint(accepted_mint.as_ref()),
+        token::authority = buyer
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.settlement_mint(accepted_mint.as_ref()),
+        token::authority = offer.seller,
+        constraint = offer.accepts_proceeds(seller_token_account.key()) @ ErrorCode::ProceedsAccountMismatch
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint
+    )]
+    pub buyer_credit_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"fee_vault", market.key().as_ref()],
+        bump = market.fee_vault_bump
+    )]
+    pub fee_vault: Account<'info, TokenAccount>,
+    
+    /// CHECK: buyer's zone assignment address, which may hold no account
+    #[account(
+        seeds = [b"zone_member", market.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_zone_member: UncheckedAccount<'info>,
+    
+    /// Buyer's grid zone, required for inter-zone trades
+    #[account(has_one = market)]
+    pub buyer_zone: Option<Account<'info, Zone>>,
+    
+    /// Link from the offer's zone to the buyer's zone, required for inter-zone trades
+    #[account(mut, has_one = market)]
+    pub zone_link: Option<Box<Account<'info, ZoneLink>>>,
+    
+    /// Oracle reference price, required for pegged offers
+    #[account(constraint = reference_price.oracle == market.oracle @ ErrorCode::OracleMismatch)]
+    pub reference_price: Option<Account<'info, ReferencePrice>>,
+    
+    /// CHECK: buyer's oracle wallet link address, which may hold no account
+    #[account(
+        seeds = [b"wallet_link", buyer.key().as_ref()],
+        bump,
+        seeds::program = energy_oracle::ID
+    )]
+    pub buyer_link: UncheckedAccount<'info>,
+    
+    /// CHECK: seller's oracle wallet link address, which may hold no account
+    #[account(
+        seeds = [b"wallet_link", offer.seller.as_ref()],
+        bump,
+        seeds::program = energy_oracle::ID
+    )]
+    pub seller_link: UncheckedAccount<'info>,
+    
+    /// Registry entry of the mint to settle in, instead of the market's quote mint
+    #[account(
+        mut,
+        has_one = market,
+        constraint = accepted_mint.is_active @ ErrorCode::MintNotAccepted
+    )]
+    pub accepted_mint: Option<Account<'info, AcceptedMint>>,
+    
+    /// Fee vault of the accepted mint, required with `accepted_mint`
+    #[account(mut)]
+    pub accepted_mint_fee_vault: Option<Account<'info, TokenAccount>>,
+    
+    pub buyer: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct BuyBest<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = buyer
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint
+    )]
+    pub buyer_credit_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"fee_vault", market.key().as_ref()],
+        bump = market.fee_vault_bump
+    )]
+    pub fee_vault: Account<'info, TokenAccount>,
+    
+    /// CHECK: buyer's zone assignment address, which may hold no account
+    #[account(
+        seeds = [b"zone_member", market.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_zone_member: UncheckedAccount<'info>,
+    
+    /// Reference price for pegged offers; offers pegged to another are skipped
+    #[account(constraint = reference_price.oracle == market.oracle @ ErrorCode::OracleMismatch)]
+    pub reference_price: Option<Account<'info, ReferencePrice>>,
+    
+    /// CHECK: buyer's oracle wallet link address, which may hold no account
+    #[account(
+        seeds = [b"wallet_link", buyer.key().as_ref()],
+        bump,
+        seeds::program = energy_oracle::ID
+    )]
+    pub buyer_link: UncheckedAccount<'info>,
+    
+    pub buyer: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteScheduledTrade<'info> {
+    #[account(mut, has_one = market)]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + EnergyTrade::INIT_SPACE,
+        seeds = [b"trade", offer.key().as_ref(), &offer.trade_count.to_le_bytes()],
+        bump
+    )]
+    pub trade: Account<'info, EnergyTrade>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(address = market.quote_mint)]
+    pub quote_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = buyer,
+        seeds = [b"trade_escrow", trade.key().as_ref()],
+        bump,
+        token::mint = quote_mint,
+        token::authority = vault_authority
+    )]
+    pub trade_escrow: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = buyer
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"fee_vault", market.key().as_ref()],
+        bump = market.fee_vault_bump
+    )]
+    pub fee_vault: Account<'info, TokenAccount>,
+    
+    /// CHECK: buyer's zone assignment address, which may hold no account
+    #[account(
+        seeds = [b"zone_member", market.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_zone_member: UncheckedAccount<'info>,
+    
+    /// Buyer's grid zone, required for inter-zone trades
+    #[account(has_one = market)]
+    pub buyer_zone: Option<Account<'info, Zone>>,
+    
+    /// Link from the offer's zone to the buyer's zone, required for inter-zone trades
+    #[account(mut, has_one = market)]
+    pub zone_link: Option<Box<Account<'info, ZoneLink>>>,
+    
+    /// Oracle reference price, required for pegged offers
+    #[account(constraint = reference_price.oracle == market.oracle @ ErrorCode::OracleMismatch)]
+    pub reference_price: Option<Account<'info, ReferencePrice>>,
+    
+    /// CHECK: buyer's oracle wallet link address, which may hold no account
+    #[account(
+        seeds = [b"wallet_link", buyer.key().as_ref()],
+        bump,
+        seeds::program = energy_oracle::ID
+    )]
+    pub buyer_link: UncheckedAccount<'info>,
+    
+    /// CHECK: seller's oracle wallet link address, which may hold no account
+    #[account(
+        seeds = [b"wallet_link", offer.seller.as_ref()],
+        bump,
+        seeds::program = energy_oracle::ID
+    )]
+    pub seller_link: UncheckedAccount<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+    
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct SettleDelivery<'info> {
+    #[account(mut, has_one = offer, has_one = market, has_one = meter)]
+    pub trade: Account<'info, EnergyTrade>,
+    
+    #[account(mut)]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    pub meter: Account<'info, EnergyMeter>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump = offer.vault_bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"trade_escrow", trade.key().as_ref()],
+        bump = trade.escrow_bump
+    )]
+    pub trade_escrow: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint,
+        token::authority = trade.buyer
+    )]
+    pub buyer_credit_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = trade.buyer
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint,
+        token::authority = trade.seller
+    )]
+    pub seller_credit_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = trade.seller
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"fee_vault", market.key().as_ref()],
+        bump = market.fee_vault_bump
+    )]
+    pub fee_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", market.key().as_ref()],
+        bump = market.collateral_vault_bump
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"insurance_vault", market.key().as_ref()],
+        bump = market.insurance_vault_bump
+    )]
+    pub insurance_vault: Account<'info, TokenAccount>,
+    
+    pub settler: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AmendOffer<'info> {
+    #[account(mut, has_one = market, has_one = seller)]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump = offer.vault_bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint,
+        token::authority = seller
+    )]
+    pub seller_credit_account: Account<'info, TokenAccount>,
+    
+    /// Seller's quote account, required when a scheduled offer's collateral changes
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = seller
+    )]
+    pub seller_token_account: Option<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", market.key().as_ref()],
+        bump = market.collateral_vault_bump
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    pub seller: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    #[account(mut, has_one = market, has_one = seller)]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump = offer.vault_bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint,
+        token::authority = seller
+    )]
+    pub seller_credit_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = seller
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", market.key().as_ref()],
+        bump = market.collateral_vault_bump
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    pub seller: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseOffer<'info> {
+    #[account(mut, has_one = market, has_one = seller, close = seller)]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump = offer.vault_bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint,
+        token::authority = seller
+    )]
+    pub seller_credit_account: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RollRecurringOffer<'info> {
+    #[account(mut, has_one = market)]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump = offer.vault_bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint,
+        token::authority = offer.seller
+    )]
+    pub seller_credit_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireOffers<'info> {
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", market.key().as_ref()],
+        bump = market.collateral_vault_bump
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CreateBid<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + EnergyBid::INIT_SPACE
+    )]
+    pub bid: Account<'info, EnergyBid>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(address = market.quote_mint)]
+    pub quote_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = buyer,
+        seeds = [b"bid_vault", bid.key().as_ref()],
+        bump,
+        token::mint = quote_mint,
+        token::authority = vault_authority
+    )]
+    pub bid_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = buyer
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+    
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct FillBid<'info> {
+    #[account(mut, has_one = market)]
+    pub bid: Account<'info, EnergyBid>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"bid_vault", bid.key().as_ref()],
+        bump = bid.vault_bump
+    )]
+    pub bid_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint,
+        token::authority = seller
+    )]
+    pub seller_credit_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint,
+        token::authority = bid.buyer
+    )]
+    pub buyer_credit_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = seller
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"fee_vault", market.key().as_ref()],
+        bump = market.fee_vault_bump
+    )]
+    pub fee_vault: Account<'info, TokenAccount>,
+    
+    /// CHECK: buyer's zone assignment address, which may hold no account
+    #[account(
+        seeds = [b"zone_member", market.key().as_ref(), bid.buyer.as_ref()],
+        bump
+    )]
+    pub buyer_zone_member: UncheckedAccount<'info>,
+    
+    /// CHECK: seller's zone assignment address, which may hold no account
+    #[account(
+        seeds = [b"zone_member", market.key().as_ref(), seller.key().as_ref()],
+        bump
+    )]
+    pub seller_zone_member: UncheckedAccount<'info>,
+    
+    /// CHECK: buyer's oracle wallet link address, which may hold no account
+    #[account(
+        seeds = [b"wallet_link", bid.buyer.as_ref()],
+        bump,
+        seeds::program = energy_oracle::ID
+    )]
+    pub buyer_link: UncheckedAccount<'info>,
+    
+    /// CHECK: seller's oracle wallet link address, which may hold no account
+    #[account(
+        seeds = [b"wallet_link", seller.key().as_ref()],
+        bump,
+        seeds::program = energy_oracle::ID
+    )]
+    pub seller_link: UncheckedAccount<'info>,
+    
+    pub seller: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBid<'info> {
+    #[account(mut, has_one = market, has_one = buyer)]
+    pub bid: Account<'info, EnergyBid>,
+    
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"bid_vault", bid.key().as_ref()],
+        bump = bid.vault_bump
+    )]
+    pub bid_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = buyer
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+    
+    pub buyer: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"fee_vault", market.key().as_ref()],
+        bump = market.fee_vault_bump
+    )]
+    pub fee_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint
+    )]
+    pub destination: Account<'info, TokenAccount>,
+    
+    pub authority: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct PayInsurance<'info> {
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"insurance_vault", market.key().as_ref()],
+        bump = market.insurance_vault_bump
+    )]
+    pub insurance_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint
+    )]
+    pub destination: Account<'info, TokenAccount>,
+    
+    pub authority: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AddAcceptedMint<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + AcceptedMint::INIT_SPACE,
+        seeds = [b"accepted_mint", market.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub accepted_mint: Account<'info, AcceptedMint>,
+    
+    pub mint: Account<'info, Mint>,
+    
+    #[account(address = market.quote_mint)]
+    pub quote_mint: Account<'info, Mint>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"fee_vault", market.key().as_ref(), mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = vault_authority
+    )]
+    pub fee_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+    
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAcceptedMint<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market)]
+    pub accepted_mint: Account<'info, AcceptedMint>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawAcceptedMintFees<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(has_one = market, has_one = fee_vault)]
+    pub accepted_mint: Account<'info, AcceptedMint>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub fee_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = accepted_mint.mint
+    )]
+    pub destination: Account<'info, TokenAccount>,
+    
+    pub authority: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMarketSettings<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    pub new_authority: Signer<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Market {
+    pub authority: Pubkey,
+    /// Authority proposed by `propose_authority`, awaiting acceptance
+    pub pending_authority: Pubkey,
+    /// Authority that created the market, from which its address is derived
+    pub creator: Pubkey,
+    pub credit_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    /// Oracle whose meters and reference prices the market accepts
+    pub oracle: Pubkey,
+    pub market_index: u16,
+    pub bump: u8,
+    pub vault_authority_bump: u8,
+    pub fee_vault_bump: u8,
+    pub maker_fee_bps: u16,
+    pub taker_fee_bps: u16,
+    pub collateral_vault_bump: u8,
+    pub insurance_vault_bump: u8,
+    /// Collateral scheduled offers lock, as a share of their notional
+    pub collateral_bps: u16,
+    /// Collateral slashed per unit of undelivered value
+    pub penalty_bps: u16,
+    /// Share of slashed collateral paid into the insurance vault rather than the buyer
+    pub insurance_bps: u16,
+    pub total_fees_collected: u64,
+    pub total_penalties: u64,
+    pub total_congestion_charges: u64,
+    pub total_offers: u64,
+    pub total_bids: u64,
+    /// Volume traded between distinct wallets, excluding trades between linked wallets
+    ///
+    /// Trades between wallets of one owner that were never linked are included.
+    pub total_volume_traded: u64,
+    /// Volume traded between wallets linked to the same meter owner
+    pub total_linked_volume: u64,
+    pub is_active: bool,
+    /// `PAUSE_*` bits of the operations currently paused
+    pub paused_flags: u8,
+    /// Time the pause bits lapse (zero for an indefinite pause)
+    pub auto_unpause_at: i64,
+    pub order_book: Pubkey,
+    pub event_queue: Pubkey,
+    pub credit_vault_bump: u8,
+    pub quote_vault_bump: u8,
+    pub self_trade_mode: SelfTradeMode,
+    /// Grid zones registered under the market
+    pub zone_count: u16,
+}
+
+impl Market {
+    /// Mint a trade settles in: the accepted mint if one is given, otherwise the quote mint
+    pub fn settlement_mint(&self, accepted_mint: Option<&Account<AcceptedMint>>) -> Pubkey {
+        accepted_mint.map_or(self.quote_mint, |accepted_mint| accepted_mint.mint)
+    }
+
+    /// Add traded volume to the honest or the linked-wallet total
+    pub fn record_volume(&mut self, energy_amount: u64, linked_trade: bool) {
+        if linked_trade {
+            self.total_linked_volume += energy_amount;
+        } else {
+            self.total_volume_traded += energy_amount;
+        }
+    }
+
+    /// Fail if `operation` (a `PAUSE_*` bit) is paused or the market is inactive
+    ///
+    /// Deactivating the market stops new offers and trading only.
+    pub fn require_not_paused(&self, operation: u8) -> Result<()> {
+        if !self.is_active {
+            require!(
+                operation & (PAUSE_NEW_OFFERS | PAUSE_TRADING) == 0,
+                ErrorCode::MarketInactive
+            );
+        }
+        if self.paused_flags & operation != 0 {
+            let now = Clock::get()?.unix_timestamp;
+            require!(
+                self.auto_unpause_at != 0 && now >= self.auto_unpause_at,
+                ErrorCode::MarketPaused
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Per-market seller record that numbers the seller's offers
+#[account]
+#[derive(InitSpace)]
+pub struct SellerAccount {
+    pub market: Pubkey,
+    pub seller: Pubkey,
+    /// Nonce of the seller's next offer
+    pub offer_nonce: u64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct EnergyOffer {
+    pub seller: Pubkey,
+    /// Wallet that paid the offer's rent: the seller, or the operator of a pool or community asset
+    pub rent_payer: Pubkey,
+    /// Token account trade proceeds must be paid into, such as a pool's quote vault
+    /// (default: any quote account of the seller)
+    pub proceeds_account: Pubkey,
+    pub market: Pubkey,
+    /// Seller's offer nonce the offer address was derived from
+    pub nonce: u64,
+    pub bump: u8,
+    pub energy_amount: u64,
+    /// Fixed price, or the floor price of a pegged offer
+    pub price_per_kwh: u64,
+    /// Oracle reference price a pegged offer follows (default for fixed-price offers)
+    pub price_reference: Pubkey,
+    /// Premium (positive) or discount (negative) to the reference price
+    pub price_offset_bps: i16,
+    pub offer_type: OfferType,
+    pub status: OfferStatus,
+    pub created_at: i64,
+    /// Start of the current occurrence; equals `created_at` for non-recurring offers
+    pub opens_at: i64,
+    pub expires_at: i64,
+    pub filled_amount: u64,
+    /// Smallest quantity a single trade may take, unless it takes the whole remainder
+    pub min_fill_amount: u64,
+    /// Delivery window of a scheduled offer (zero for other offer types)
+    pub delivery_start: i64,
+    pub delivery_end: i64,
+    /// Production meter deliveries are verified against (default for unmetered offers)
+    pub meter: Pubkey,
+    /// Grid zone the energy is delivered from (default for offers outside any zone)
+    pub zone: Pubkey,
+    /// Verified production already credited to this offer's trades
+    pub delivered_amount: u64,
+    /// Quote collateral still held against undelivered energy
+    pub collateral_locked: u64,
+    /// Penalty rate snapshotted from the market at creation
+    pub penalty_bps: u16,
+    pub trade_count: u32,
+    /// Scheduled trades whose payment and credits are still in escrow
+    pub open_trades: u32,
+    pub recurrence_interval: Option<RecurrenceInterval>,
+    /// Occurrences still to come after the current one
+    pub occurrences_remaining: u16,
+    pub occurrence_index: u16,
+    pub vault_bump: u8,
+}
+
+impl EnergyOffer {
+    /// Whether trade proceeds may be paid into `token_account`
+    pub fn accepts_proceeds(&self, token_account: Pubkey) -> bool {
+        self.proceeds_account == Pubkey::default() || self.proceeds_account == token_account
+    }
+
+    /// Whether a trade of `amount` respects the offer's minimum fill size
+    pub fn meets_min_fill(&self, amount: u64) -> bool {
+        amount >= self.min_fill_amount || amount == self.energy_amount - self.filled_amount
+    }
+
+    /// Price per kWh the offer trades at right now
+    ///
+    /// Fixed-price offers return `price_per_kwh`. Pegged offers need their fresh
+    /// reference price and return it adjusted by the offset, floored at `price_per_kwh`.
+    pub fn resolve_price(
+        &self,
+        reference_price: Option<&Account<ReferencePrice>>,
+        now: i64,
+    ) -> Result<u64> {
+        if self.price_reference == Pubkey::default() {
+            return Ok(self.price_per_kwh);
+        }
+        let reference_price = reference_price.ok_or(ErrorCode::ReferencePriceRequired)?;
+        require!(
+            reference_price.key() == self.price_reference,
+            ErrorCode::ReferencePriceRequired
+        );
+        require!(
+            now - reference_price.updated_at <= MAX_REFERENCE_PRICE_AGE_SECS,
+            ErrorCode::StaleReferencePrice
+        );
+
+        let pegged = (reference_price.price_per_kwh as u128)
+            * (MAX_BPS as i32 + self.price_offset_bps as i32) as u128
+            / MAX_BPS as u128;
+        let pegged = u64::try_from(pegged).map_err(|_| ErrorCode::Overflow)?;
+        Ok(pegged.max(self.price_per_kwh))
+    }
+
+    /// Escrowed credits not yet sold: the rest of the current occurrence plus all future ones
+    pub fn unsold_amount(&self) -> u64 {
+        (self.energy_amount - self.filled_amount)
+            + self.energy_amount * self.occurrences_remaining as u64
+    }
+
+    /// Withdraw credits when the seller takes `amount` of their own offer
+    ///
+    /// Returns the credits to refund from the vault. `CancelResting` cancels the
+    /// offer; `DecrementBoth` counts the amount as filled so recurring escrow stays balanced.
+    pub fn prevent_self_trade(&mut self, mode: SelfTradeMode, amount: u64) -> Result<u64> {
+        let withdrawn = mode.withdrawn(self.energy_amount - self.filled_amount, amount)?;
+        if mode == SelfTradeMode::CancelResting {
+            let refund_amount = self.unsold_amount();
+            self.status = OfferStatus::Cancelled;
+            self.occurrences_remaining = 0;
+            return Ok(refund_amount);
+        }
+        self.filled_amount += withdrawn;
+        if self.filled_amount >= self.energy_amount {
+            self.status = OfferStatus::Completed;
+        }
+        Ok(withdrawn)
+    }
+}
+
+/// An additional mint a market settles trades in, with its price conversion
+#[account]
+#[derive(InitSpace)]
+pub struct AcceptedMint {
+    pub market: Pubkey,
+    pub mint: Pubkey,
+    pub decimals: u8,
+    /// Decimals of the market's quote mint, which offer prices are quoted in
+    pub quote_decimals: u8,
+    /// Whole `mint` tokens per whole quote-mint token, scaled by `PRICE_SCALE`
+    pub price_scale: u64,
+    /// Vault collecting the fees and congestion charges paid in this mint
+    pub fee_vault: Pubkey,
+    pub is_active: bool,
+    pub total_fees_collected: u64,
+    pub total_congestion_charges: u64,
+    pub bump: u8,
+}
+
+impl AcceptedMint {
+    /// Convert an amount of the market's quote mint into this mint, rounding up
+    ///
+    /// Converted amounts are owed by the buyer, so rounding never works in their favour.
+    pub fn convert(&self, amount: u64) -> Result<u64> {
+        let denominator = PRICE_SCALE as u128 * 10u128.pow(self.quote_decimals as u32);
+        let converted = (amount as u128)
+            .checked_mul(self.price_scale as u128)
+            .and_then(|value| value.checked_mul(10u128.pow(self.decimals as u32)))
+            .and_then(|value| value.checked_add(denominator - 1))
+            .ok_or(ErrorCode::Overflow)?
+            / denominator;
+        u64::try_from(converted).map_err(|_| error!(ErrorCode::Overflow))
+    }
+}
+
+/// A scheduled trade awaiting delivery verification
+#[account]
+#[derive(InitSpace)]
+pub struct EnergyTrade {
+    pub offer: Pubkey,
+    pub market: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub energy_amount: u64,
+    pub total_cost: u64,
+    pub delivery_start: i64,
+    pub delivery_end: i64,
+    pub meter: Pubkey,
+    pub delivered_amount: u64,
+    pub executed_at: i64,
+    pub is_settled: bool,
+    pub bump: u8,
+    pub escrow_bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct EnergyBid {
+    pub buyer: Pubkey,
+    pub market: Pubkey,
+    pub energy_amount: u64,
+    pub max_price_per_kwh: u64,
+    pub status: OfferStatus,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub filled_amount: u64,
+    pub maker_fee_bps: u16,
+    pub vault_bump: u8,
+}
+
+/// Whether a meter's register at `time` can only be read from a `MeterReading`
+pub fn register_needs_reading(meter: &EnergyMeter, time: i64) -> bool {
+    time > meter.registered_at && time <= meter.last_reading_at
+}
+
+/// A meter's cumulative register of `reading_type` at `time`
+///
+/// The register is zero before the meter was registered and the meter's current
+/// total after its last reading. In between, the next of `readings` must be the
+/// reading whose period covers `time`; periods never overlap, so the value does
+/// not depend on which readings the caller would rather show.
+pub fn meter_register_at<'info>(
+    meter: &Account<'info, EnergyMeter>,
+    reading_type: ReadingType,
+    time: i64,
+    readings: &mut std::slice::Iter<'_, AccountInfo<'info>>,
+) -> Result<u64> {
+    if time <= meter.registered_at {
+        return Ok(0);
+    }
+    if time > meter.last_reading_at {
+        return Ok(meter.register(reading_type));
+    }
+
+    let reading_info = readings.next().ok_or(ErrorCode::MissingReading)?;
+    let reading: Account<MeterReading> = Account::try_from(reading_info)?;
+    require!(reading.meter == meter.key(), ErrorCode::ReadingMeterMismatch);
+    reading
+        .register_at(reading_type, time)
+        .ok_or_else(|| error!(ErrorCode::ReadingOutsideWindow))
+}
+
+/// Meter owner a wallet trades for, or the wallet itself if it is not linked
+///
+/// The link account sits at the wallet's oracle `wallet_link` address and only
+/// holds a `WalletLink` if the wallet has been linked to an owner.
+pub fn beneficial_owner(wallet: Pubkey, link: &AccountInfo) -> Result<Pubkey> {
+    if link.owner != &energy_oracle::ID || link.data_is_empty() {
+        return Ok(wallet);
+    }
+    let link = WalletLink::try_deserialize(&mut &link.data.borrow()[..])?;
+    Ok(link.owner)
+}
+
+/// Whether two distinct wallets trade for the same meter owner
+///
+/// Only wallets their owner has linked through the oracle are recognised, and
+/// linking is voluntary: a meter owner trading with a fresh, unlinked wallet is
+/// not flagged. Volume incentives should treat `total_volume_traded` as an upper
+/// bound on honest volume and weigh it against other signals.
+pub fn is_linked_trade(
+    buyer: Pubkey,
+    buyer_link: &AccountInfo,
+    seller: Pubkey,
+    seller_link: &AccountInfo,
+) -> Result<bool> {
+    Ok(buyer != seller && beneficial_owner(buyer, buyer_link)? == beneficial_owner(seller, seller_link)?)
+}
+
+/// Fee owed on a quote amount at the given rate, rounded down
+pub fn calculate_fee(amount: u64, fee_bps: u16) -> Result<u64> {
+    let fee = (amount as u128) * (fee_bps as u128) / 10_000;
+    u64::try_from(fee).map_err(|_| error!(ErrorCode::Overflow))
+}
+
+/// Wind up an offer sold by a pool or community asset and close its vault
+///
+/// Finished offers can be closed at any time, active ones once expired. Credits
+/// left in the offer vault go back to the seller's `credit_vault` and the vault's
+/// rent to `rent_payer`; the caller closes the offer account itself.
+pub fn close_operator_offer<'info>(
+    market: &Account<'info, Market>,
+    vault_authority: &UncheckedAccount<'info>,
+    offer: &mut Account<'info, EnergyOffer>,
+    offer_vault: &Account<'info, TokenAccount>,
+    credit_vault: &Account<'info, TokenAccount>,
+    rent_payer: &Signer<'info>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    market.require_not_paused(PAUSE_CANCELLATIONS)?;
+
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        offer.status == OfferStatus::Completed
+            || offer.status == OfferStatus::Cancelled
+            || (offer.status == OfferStatus::Active && now >= offer.expires_at),
+        ErrorCode::OfferNotClosable
+    );
+    require!(
+        offer.open_trades == 0 && offer.collateral_locked == 0,
+        ErrorCode::OfferNotClosable
+    );
+
+    let refund_amount = offer_vault.amount;
+    if refund_amount > 0 {
+        vault_transfer(
+            market,
+            vault_authority,
+            offer_vault,
+            credit_vault,
+            token_program,
+            refund_amount,
+        )?;
+    }
+    vault_close_account(market, vault_authority, offer_vault, rent_payer, token_program)?;
+
+    if offer.status == OfferStatus::Active {
+        offer.status = OfferStatus::Expired;
+    }
+
+    emit!(OfferClosedEvent {
+        offer_id: offer.key(),
+        market: market.key(),
+        seller: offer.seller,
+        status: offer.status.clone(),
+        energy_amount: offer.energy_amount,
+        filled_amount: offer.filled_amount,
+        refunded_amount: refund_amount,
+        timestamp: now,
+    });
+    Ok(())
+}
+
+/// Transfer tokens out of an account owned by one of the program's PDAs
+pub fn signed_transfer<'info>(
+    from: &impl ToAccountInfo<'info>,
+    to: &impl ToAccountInfo<'info>,
+    authority: &impl ToAccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    signer: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = Transfer {
+        from: from.to_account_info(),
+        to: to.to_account_info(),
+        authority: authority.to_account_info(),
+    };
+    let cpi_program = token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    token::transfer(cpi_ctx, amount)
+}
+
+/// Transfer tokens out of one of the market's vaults
+pub fn vault_transfer<'info>(
+    market: &Account<'info, Market>,
+    vault_authority: &UncheckedAccount<'info>,
+    from: &impl ToAccountInfo<'info>,
+    to: &impl ToAccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let market_key = market.key();
+    let seeds = &[
+        b"vault_authority".as_ref(),
+        market_key.as_ref(),
+        &[market.vault_authority_bump],
+    ];
+    signed_transfer(from, to, vault_authority, token_program, &[&seeds[..]], amount)
+}
+
+/// Close one of the market's emptied vaults, returning its rent to `destination`
+pub fn vault_close_account<'info>(
+    market: &Account<'info, Market>,
+    vault_authority: &UncheckedAccount<'info>,
+    account: &impl ToAccountInfo<'info>,
+    destination: &impl ToAccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let market_key = market.key();
+    let seeds = &[
+        b"vault_authority".as_ref(),
+        market_key.as_ref(),
+        &[market.vault_authority_bump],
+    ];
+    let signer = &[&seeds[..]];
+    let cpi_accounts = CloseAccount {
+        account: account.to_account_info(),
+        destination: destination.to_account_info(),
+        authority: vault_authority.to_account_info(),
+    };
+    let cpi_program = token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    token::close_account(cpi_ctx)
+}
+
+/// How a market handles a trader taking their own resting order
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum SelfTradeMode {
+    /// Fail the taking instruction
+    Reject,
+    /// Cancel the resting order and carry on
+    CancelResting,
+    /// Withdraw the overlapping quantity from both orders
+    DecrementBoth,
+}
+
+impl SelfTradeMode {
+    /// Quantity withdrawn from a resting order of `resting` units its owner would take `amount` of
+    pub fn withdrawn(self, resting: u64, amount: u64) -> Result<u64> {
+        match self {
+            SelfTradeMode::Reject => err!(ErrorCode::SelfTrade),
+            SelfTradeMode::CancelResting => Ok(resting),
+            SelfTradeMode::DecrementBoth => Ok(amount.min(resting)),
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+impl Side {
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Bid => Side::Ask,
+            Side::Ask => Side::Bid,
+        }
+    }
+}
+
+/// Optional settings of a new offer
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct OfferParams {
+    /// Delivery window, for scheduled offers only
+    pub delivery_window: Option<DeliveryWindow>,
+    /// Repeat schedule, for recurring offers only
+    pub recurrence: Option<RecurrenceRule>,
+    /// Offset from the oracle reference price, for pegged offers
+    pub price_offset_bps: Option<i16>,
+    /// Smallest quantity a trade may take, unless it takes the rest
+    pub min_fill_amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct DeliveryWindow {
+    pub start: i64,
+    pub end: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    pub interval: RecurrenceInterval,
+    /// Total number of occurrences, including the first
+    pub occurrences: u16,
+}
+
+/// How often a recurring offer repeats, on UTC calendar days
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum RecurrenceInterval {
+    Daily,
+    Weekdays,
+    Weekly,
+}
+
+impl RecurrenceInterval {
+    const DAY: i64 = 86_400;
+
+    /// Shortest gap between two occurrences
+    pub fn min_period(self) -> i64 {
+        match self {
+            RecurrenceInterval::Daily | RecurrenceInterval::Weekdays => Self::DAY,
+            RecurrenceInterval::Weekly => 7 * Self::DAY,
+        }
+    }
+
+    /// Start time of the occurrence following one that started at `start`
+    pub fn next_start(self, start: i64) -> i64 {
+        match self {
+            RecurrenceInterval::Daily => start + Self::DAY,
+            RecurrenceInterval::Weekly => start + 7 * Self::DAY,
+            RecurrenceInterval::Weekdays => {
+                let mut next = start + Self::DAY;
+                // 1970-01-01 was a Thursday; 0 = Sunday, 6 = Saturday
+                while matches!((next.div_euclid(Self::DAY) + 4).rem_euclid(7), 0 | 6) {
+                    next += Self::DAY;
+                }
+                next
+            }
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum OfferType {
+    Immediate,
+    Scheduled,
+    Recurring,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub enum OfferStatus {
+    Active,
+    Completed,
+    Cancelled,
+    Expired,
+}
+
+#[event]
+pub struct OfferCreatedEvent {
+    pub offer_id: Pubkey,
+    pub seller: Pubkey,
+    pub energy_amount: u64,
+    pub price_per_kwh: u64,
+    pub offer_type: OfferType,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct TradeExecutedEvent {
+    pub offer_id: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub energy_amount: u64,
+    /// Mint the trade was paid in
+    pub quote_mint: Pubkey,
+    pub total_cost: u64,
+    /// Price adder paid for importing the energy from another zone
+    pub congestion_charge: u64,
+    /// Buyer and seller are wallets linked to the same meter owner
+    pub linked_trade: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SelfTradePreventedEvent {
+    pub market: Pubkey,
+    pub trader: Pubkey,
+    pub mode: SelfTradeMode,
+    /// Quantity withdrawn from the resting order
+    pub energy_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DeliverySettledEvent {
+    pub trade: Pubkey,
+    pub offer_id: Pubkey,
+    pub meter: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub energy_amount: u64,
+    pub delivered_amount: u64,
+    pub seller_payment: u64,
+    pub buyer_refund: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CollateralSlashedEvent {
+    pub offer_id: Pubkey,
+    pub trade: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub buyer_compensation: u64,
+    pub insurance_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OfferAmendedEvent {
+    pub offer_id: Pubkey,
+    pub seller: Pubkey,
+    pub price_per_kwh: u64,
+    pub energy_amount: u64,
+    pub expires_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OfferCancelledEvent {
+    pub offer_id: Pubkey,
+    pub seller: Pubkey,
+    pub refunded_amount: u64,
+    pub collateral_released: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OfferClosedEvent {
+    pub offer_id: Pubkey,
+    pub market: Pubkey,
+    pub seller: Pubkey,
+    pub status: OfferStatus,
+    pub energy_amount: u64,
+    pub filled_amount: u64,
+    pub refunded_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OfferRolledEvent {
+    pub offer_id: Pubkey,
+    pub seller: Pubkey,
+    pub occurrence_index: u16,
+    pub occurrences_remaining: u16,
+    pub opens_at: i64,
+    pub expires_at: i64,
+    pub refunded_amount: u64,
+}
+
+#[event]
+pub struct OfferExpiredEvent {
+    pub offer_id: Pubkey,
+    pub seller: Pubkey,
+    pub refunded_amount: u64,
+    pub crank_bounty: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BidCreatedEvent {
+    pub bid_id: Pubkey,
+    pub buyer: Pubkey,
+    pub energy_amount: u64,
+    pub max_price_per_kwh: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct BidFilledEvent {
+    pub bid_id: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub energy_amount: u64,
+    pub total_cost: u64,
+    /// Buyer and seller are different wallets linked to the same meter owner
+    pub linked_trade: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BidCancelledEvent {
+    pub bid_id: Pubkey,
+    pub buyer: Pubkey,
+    pub refunded_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MarketPausedEvent {
+    pub market: Pubkey,
+    pub is_active: bool,
+    pub paused_flags: u8,
+    pub auto_unpause_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityProposedEvent {
+    pub market: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityTransferredEvent {
+    pub market: Pubkey,
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeesCollectedEvent {
+    pub market: Pubkey,
+    /// Mint the fees were collected in
+    pub mint: Pubkey,
+    pub maker_fee: u64,
+    pub taker_fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AcceptedMintUpdatedEvent {
+    pub market: Pubkey,
+    pub mint: Pubkey,
+    pub price_scale: u64,
+    pub is_active: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeesWithdrawnEvent {
+    pub market: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct InsurancePaidEvent {
+    pub market: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
     pub timestamp: i64,
 }
 
@@ -295,4 +4280,244 @@ pub enum ErrorCode {
     Overflow,
     #[msg("Unauthorized access")]
     Unauthorized,
+    #[msg("Bid is not active")]
+    BidNotActive,
+    #[msg("Bid has expired")]
+    BidExpired,
+    #[msg("Order book side is full")]
+    OrderBookFull,
+    #[msg("Event queue is full, crank consume_events")]
+    EventQueueFull,
+    #[msg("Order not found")]
+    OrderNotFound,
+    #[msg("Order book is already initialized")]
+    OrderBookAlreadyInitialized,
+    #[msg("Invalid auction window")]
+    InvalidAuctionWindow,
+    #[msg("Auction gate has closed")]
+    AuctionClosed,
+    #[msg("Auction gate has not closed yet")]
+    AuctionNotClosed,
+    #[msg("Auction has already been cleared")]
+    AuctionAlreadyCleared,
+    #[msg("Auction has not been cleared")]
+    AuctionNotCleared,
+    #[msg("Auction order limit reached")]
+    AuctionFull,
+    #[msg("Invalid order index")]
+    InvalidOrderIndex,
+    #[msg("Order has already been settled")]
+    OrderAlreadySettled,
+    #[msg("Invalid remaining accounts")]
+    InvalidRemainingAccounts,
+    #[msg("Invalid escrow vault")]
+    InvalidVault,
+    #[msg("Fee exceeds the maximum allowed")]
+    InvalidFee,
+    #[msg("Invalid delivery window")]
+    InvalidDeliveryWindow,
+    #[msg("Scheduled offers must be bought with execute_scheduled_trade")]
+    ScheduledOfferRequiresEscrow,
+    #[msg("Offer is not a scheduled offer")]
+    OfferNotScheduled,
+    #[msg("Gate closure has passed for this delivery window")]
+    GateClosed,
+    #[msg("Delivery window has not ended")]
+    DeliveryWindowOpen,
+    #[msg("Trade has already been settled")]
+    TradeAlreadySettled,
+    #[msg("Invalid recurrence rule")]
+    InvalidRecurrence,
+    #[msg("Offer is not a recurring offer")]
+    OfferNotRecurring,
+    #[msg("Recurring offer has no occurrences remaining")]
+    NoOccurrencesRemaining,
+    #[msg("Current occurrence is still open")]
+    OccurrenceStillOpen,
+    #[msg("Offer is not open for trading yet")]
+    OfferNotOpen,
+    #[msg("Invalid energy meter")]
+    InvalidMeter,
+    #[msg("Meter reading does not belong to the trade's meter")]
+    ReadingMeterMismatch,
+    #[msg("Meter reading is not a verified production reading")]
+    InvalidReading,
+    #[msg("Meter reading is outside the delivery window")]
+    ReadingOutsideWindow,
+    #[msg("Meter reading passed more than once")]
+    DuplicateReading,
+    #[msg("Meter reading covering a window boundary is missing")]
+    MissingReading,
+    #[msg("Invalid collateral settings")]
+    InvalidCollateralSettings,
+    #[msg("Seller token account required to lock collateral")]
+    CollateralAccountRequired,
+    #[msg("Invalid zone link")]
+    InvalidZoneLink,
+    #[msg("Zone has the maximum number of neighbours")]
+    TooManyNeighbours,
+    #[msg("Buyer zone required for zoned offers")]
+    ZoneRequired,
+    #[msg("Zones are not connected")]
+    ZonesNotConnected,
+    #[msg("Zone does not match the member's assignment")]
+    ZoneMismatch,
+    #[msg("Markets with grid zones cannot trade on the order book or in auctions")]
+    ZonedTradingUnsupported,
+    #[msg("Inter-zone transfer capacity exceeded")]
+    TransferCapacityExceeded,
+    #[msg("Transfer interval is too far ahead to book")]
+    ZoneFlowUnavailable,
+    #[msg("Market is not active")]
+    MarketInactive,
+    #[msg("This market operation is paused")]
+    MarketPaused,
+    #[msg("Invalid pause flags")]
+    InvalidPauseFlags,
+    #[msg("Price offset must be above -100% and at most +100%")]
+    InvalidPriceOffset,
+    #[msg("Offer's reference price account required")]
+    ReferencePriceRequired,
+    #[msg("Reference price is stale")]
+    StaleReferencePrice,
+    #[msg("Price exceeds the buyer's limit")]
+    PriceSlippage,
+    #[msg("Post-only order would take liquidity")]
+    PostOnlyWouldCross,
+    #[msg("Fill-or-kill order could not be filled in full")]
+    FillOrKillNotFilled,
+    #[msg("Minimum fill cannot exceed the offered amount")]
+    InvalidMinFill,
+    #[msg("Trade is below the offer's minimum fill size")]
+    BelowMinimumFill,
+    #[msg("Order would trade against the trader's own resting order")]
+    SelfTrade,
+    #[msg("Wallet link account does not match the trader")]
+    InvalidWalletLink,
+    #[msg("Account belongs to a different oracle than the market")]
+    OracleMismatch,
+    #[msg("Offer is still open or has unsettled trades")]
+    OfferNotClosable,
+    #[msg("Mint is not accepted for settlement on this market")]
+    MintNotAccepted,
+    #[msg("Mint is already accepted on this market")]
+    MintAlreadyAccepted,
+    #[msg("Invalid price scale")]
+    InvalidPriceScale,
+    #[msg("Pool is not accepting contributions")]
+    PoolNotCollecting,
+    #[msg("Pool is not selling")]
+    PoolNotSelling,
+    #[msg("Pool still has open offers")]
+    PoolHasOpenOffers,
+    #[msg("Pooled credits are locked while the pool is selling")]
+    PoolLocked,
+    #[msg("Not enough pool shares")]
+    InsufficientShares,
+    #[msg("Nothing to claim")]
+    NothingToClaim,
+    #[msg("Community asset pays its credits out in kind")]
+    CommunityPaysCredits,
+    #[msg("Shares must move between two different holdings")]
+    InvalidShareTransfer,
+    #[msg("Demand-response bidding has closed")]
+    BiddingClosed,
+    #[msg("Demand-response bidding is still open")]
+    BiddingOpen,
+    #[msg("Demand-response event has already cleared")]
+    EventAlreadyCleared,
+    #[msg("Curtailment bids must be passed cheapest first")]
+    BidsNotSorted,
+    #[msg("Curtailment bid was not accepted")]
+    BidNotAccepted,
+    #[msg("Curtailment bid is still awaiting clearing or settlement")]
+    BidNotClosable,
+    #[msg("Demand-response event has reached its bid limit")]
+    DemandResponseFull,
+    #[msg("Every curtailment bid of the event must be passed")]
+    MissingCurtailmentBid,
+    #[msg("Sellers in a market with grid zones must be assigned to a zone")]
+    ZoneAssignmentRequired,
+    #[msg("Trade proceeds must be paid into the offer's proceeds account")]
+    ProceedsAccountMismatch,
+    #[msg("Offer cannot settle in an accepted mint")]
+    AcceptedMintUnsupported,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86_400;
+    // Monday 2024-01-01 15:00 UTC
+    const MONDAY: i64 = 1_704_121_200;
+
+    #[test]
+    fn next_start_steps_daily_and_weekly_intervals() {
+        let friday = MONDAY + 4 * DAY;
+        assert_eq!(RecurrenceInterval::Daily.next_start(friday), friday + DAY);
+        assert_eq!(RecurrenceInterval::Weekly.next_start(friday), friday + 7 * DAY);
+    }
+
+    #[test]
+    fn next_start_skips_weekends_for_weekdays() {
+        let weekdays = RecurrenceInterval::Weekdays;
+        assert_eq!(weekdays.next_start(MONDAY), MONDAY + DAY);
+        assert_eq!(weekdays.next_start(MONDAY + 3 * DAY), MONDAY + 4 * DAY);
+        // Friday and weekend starts roll over to the next Monday at the same time of day
+        assert_eq!(weekdays.next_start(MONDAY + 4 * DAY), MONDAY + 7 * DAY);
+        assert_eq!(weekdays.next_start(MONDAY + 5 * DAY), MONDAY + 7 * DAY);
+        assert_eq!(weekdays.next_start(MONDAY + 6 * DAY), MONDAY + 7 * DAY);
+    }
+
+    #[test]
+    fn next_start_finds_weekdays_before_the_epoch() {
+        // Friday 1969-12-26 rolls over to Monday 1969-12-29
+        assert_eq!(RecurrenceInterval::Weekdays.next_start(-6 * DAY), -3 * DAY);
+        // Wednesday 1969-12-31 23:00 moves to Thursday 1970-01-01
+        assert_eq!(RecurrenceInterval::Weekdays.next_start(-3_600), DAY - 3_600);
+    }
+
+    fn accepted_mint(decimals: u8, quote_decimals: u8, price_scale: u64) -> AcceptedMint {
+        AcceptedMint {
+            market: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            decimals,
+            quote_decimals,
+            price_scale,
+            fee_vault: Pubkey::new_unique(),
+            is_active: true,
+            total_fees_collected: 0,
+            total_congestion_charges: 0,
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn convert_scales_by_price_and_decimals() {
+        // Two 9-decimal tokens per 6-decimal quote token
+        let mint = accepted_mint(9, 6, 2 * PRICE_SCALE);
+        assert_eq!(mint.convert(1_000_000).unwrap(), 2_000_000_000);
+        assert_eq!(mint.convert(0).unwrap(), 0);
+    }
+
+    #[test]
+    fn convert_rounds_up_against_the_buyer() {
+        let third = accepted_mint(6, 6, PRICE_SCALE / 3);
+        assert_eq!(third.convert(3).unwrap(), 1);
+        assert_eq!(third.convert(6).unwrap(), 2);
+
+        // A 2-decimal token is worth 10,000 base units of a 6-decimal quote mint
+        let coarse = accepted_mint(2, 6, PRICE_SCALE);
+        assert_eq!(coarse.convert(1).unwrap(), 1);
+        assert_eq!(coarse.convert(10_000).unwrap(), 1);
+        assert_eq!(coarse.convert(10_001).unwrap(), 2);
+    }
+
+    #[test]
+    fn convert_rejects_amounts_beyond_u64() {
+        let mint = accepted_mint(6, 6, 2 * PRICE_SCALE);
+        assert!(mint.convert(u64::MAX).is_err());
+        assert_eq!(mint.convert(u64::MAX / 2).unwrap(), u64::MAX - 1);
+    }
 }
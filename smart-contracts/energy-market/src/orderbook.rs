@@ -0,0 +1,847 @@
+//! Continuous limit order book with a crankable fill queue
+
+use super::*;
+
+pub(crate) fn initialize_order_book(ctx: Context<InitializeOrderBook>) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+    require!(
+        market.order_book == Pubkey::default(),
+        ErrorCode::OrderBookAlreadyInitialized
+    );
+
+    let mut order_book = ctx.accounts.order_book.load_init()?;
+    order_book.market = market.key();
+    order_book.next_order_id = 1;
+
+    let mut event_queue = ctx.accounts.event_queue.load_init()?;
+    event_queue.market = market.key();
+
+    market.order_book = ctx.accounts.order_book.key();
+    market.event_queue = ctx.accounts.event_queue.key();
+    market.credit_vault_bump = *ctx.bumps.get("credit_vault").unwrap();
+    market.quote_vault_bump = *ctx.bumps.get("quote_vault").unwrap();
+
+    msg!("Order book initialized");
+    Ok(())
+}
+
+pub(crate) fn initialize_open_orders(ctx: Context<InitializeOpenOrders>) -> Result<()> {
+    let open_orders = &mut ctx.accounts.open_orders;
+    open_orders.market = ctx.accounts.market.key();
+    open_orders.owner = ctx.accounts.owner.key();
+    open_orders.credit_free = 0;
+    open_orders.credit_locked = 0;
+    open_orders.quote_free = 0;
+    open_orders.quote_locked = 0;
+    open_orders.bump = *ctx.bumps.get("open_orders").unwrap();
+
+    msg!("Open orders account initialized for {}", open_orders.owner);
+    Ok(())
+}
+
+pub(crate) fn place_order(
+    ctx: Context<PlaceOrder>,
+    side: Side,
+    price_per_kwh: u64,
+    energy_amount: u64,
+    time_in_force: TimeInForce,
+) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_TRADING)?;
+
+    require!(energy_amount > 0, ErrorCode::InvalidAmount);
+    require!(price_per_kwh > 0, ErrorCode::InvalidPrice);
+    // The book and auctions have no notion of grid zones
+    require!(ctx.accounts.market.zone_count == 0, ErrorCode::ZonedTradingUnsupported);
+
+    let market = &mut ctx.accounts.market;
+    let open_orders = &mut ctx.accounts.open_orders;
+
+    // Deposit whatever the free balance cannot cover. Bids reserve the fee at
+    // the higher of the two rates, since any part may fill as maker or taker.
+    let (required, free) = match side {
+        Side::Bid => {
+            let notional = energy_amount
+                .checked_mul(price_per_kwh)
+                .ok_or(ErrorCode::Overflow)?;
+            let max_fee_bps = market.maker_fee_bps.max(market.taker_fee_bps);
+            (notional + calculate_fee(notional, max_fee_bps)?, open_orders.quote_free)
+        }
+        Side::Ask => (energy_amount, open_orders.credit_free),
+    };
+    let deposit = required.saturating_sub(free);
+    if deposit > 0 {
+        let vault = match side {
+            Side::Bid => ctx.accounts.quote_vault.to_account_info(),
+            Side::Ask => ctx.accounts.credit_vault.to_account_info(),
+        };
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.owner_token_account.to_account_info(),
+            to: vault,
+            authority: ctx.accounts.owner.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, deposit)?;
+
+        match side {
+            Side::Bid => open_orders.quote_free += deposit,
+            Side::Ask => open_orders.credit_free += deposit,
+        }
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    let open_orders_key = open_orders.key();
+    let owner_beneficiary = beneficial_owner(ctx.accounts.owner.key(), &ctx.accounts.owner_link)?;
+    let mut order_book = ctx.accounts.order_book.load_mut()?;
+    let mut event_queue = ctx.accounts.event_queue.load_mut()?;
+
+    let order_id = order_book.next_order_id;
+    order_book.next_order_id += 1;
+
+    if time_in_force == TimeInForce::PostOnly {
+        if let Some(maker) = order_book.best(side.opposite()) {
+            let crosses = match side {
+                Side::Bid => maker.price <= price_per_kwh,
+                Side::Ask => maker.price >= price_per_kwh,
+            };
+            require!(!crosses, ErrorCode::PostOnlyWouldCross);
+        }
+    }
+
+    let mut remaining = energy_amount;
+    let mut fees_collected: u64 = 0;
+    let mut maker_fees: u64 = 0;
+    let mut taker_fees: u64 = 0;
+    while remaining > 0 {
+        let maker = match order_book.best(side.opposite()) {
+            Some(order) => order,
+            None => break,
+        };
+        let crosses = match side {
+            Side::Bid => maker.price <= price_per_kwh,
+            Side::Ask => maker.price >= price_per_kwh,
+        };
+        if !crosses {
+            break;
+        }
+
+        // The owner's own resting order is withdrawn per the market's self-trade mode
+        if maker.owner == open_orders_key {
+            let withdrawn = market.self_trade_mode.withdrawn(maker.quantity, remaining)?;
+            match side.opposite() {
+                Side::Bid => {
+                    let unlocked = if withdrawn == maker.quantity {
+                        maker.locked
+                    } else {
+                        let notional = withdrawn * maker.price;
+                        notional + calculate_fee(notional, maker.fee_bps)?
+                    };
+                    order_book.fill_best(Side::Bid, withdrawn, unlocked);
+                    open_orders.quote_locked -= unlocked;
+                    open_orders.quote_free += unlocked;
+                }
+                Side::Ask => {
+                    order_book.fill_best(Side::Ask, withdrawn, 0);
+                    open_orders.credit_locked -= withdrawn;
+                    open_orders.credit_free += withdrawn;
+                }
+            }
+            if market.self_trade_mode == SelfTradeMode::DecrementBoth {
+                remaining -= withdrawn;
+            }
+
+            emit!(SelfTradePreventedEvent {
+                market: market.key(),
+                trader: ctx.accounts.owner.key(),
+                mode: market.self_trade_mode,
+                energy_amount: withdrawn,
+                timestamp: now,
+            });
+            continue;
+        }
+
+        let fill_amount = remaining.min(maker.quantity);
+        let linked_trade = maker.beneficial_owner == owner_beneficiary;
+        let fill_cost = fill_amount
+            .checked_mul(maker.price)
+            .ok_or(ErrorCode::Overflow)?;
+        let maker_fee = calculate_fee(fill_cost, maker.fee_bps)?;
+        let taker_fee = calculate_fee(fill_cost, market.taker_fee_bps)?;
+
+        // A resting bid releases its locked payment plus fee, and any rounding
+        // dust left in the lock once the order is exhausted
+        let maker_unlock = match side {
+            Side::Bid => 0,
+            Side::Ask if fill_amount == maker.quantity => maker.locked,
+            Side::Ask => fill_cost + maker_fee,
+        };
+
+        // The maker's side is settled later by the consume_events crank
+        event_queue.push(FillEvent {
+            maker: maker.owner,
+            taker: open_orders_key,
+            maker_order_id: maker.order_id,
+            price: maker.price,
+            quantity: fill_amount,
+            timestamp: now,
+            maker_fee,
+            maker_unlock,
+            taker_side: side as u8,
+            padding: [0; 7],
+        })?;
+        order_book.fill_best(side.opposite(), fill_amount, maker_unlock);
+
+        // Settle the taker's side of the fill immediately
+        match side {
+            Side::Bid => {
+                open_orders.quote_free -= fill_cost + taker_fee;
+                open_orders.credit_free += fill_amount;
+            }
+            Side::Ask => {
+                open_orders.credit_free -= fill_amount;
+                open_orders.quote_free += fill_cost - taker_fee;
+            }
+        }
+
+        remaining -= fill_amount;
+        fees_collected += maker_fee + taker_fee;
+        maker_fees += maker_fee;
+        taker_fees += taker_fee;
+        market.record_volume(fill_amount, linked_trade);
+
+        emit!(OrderFilledEvent {
+            maker: maker.owner,
+            taker: open_orders_key,
+            maker_order_id: maker.order_id,
+            taker_order_id: order_id,
+            taker_side: side,
+            price_per_kwh: maker.price,
+            energy_amount: fill_amount,
+            maker_fee,
+            taker_fee,
+            linked_trade,
+            timestamp: now,
+        });
+    }
+
+    if time_in_force == TimeInForce::FillOrKill {
+        require!(remaining == 0, ErrorCode::FillOrKillNotFilled);
+    }
+
+    // Rest the unfilled remainder on the book; an IOC remainder stays in the free balance
+    if remaining > 0 && time_in_force != TimeInForce::ImmediateOrCancel {
+        let locked = match side {
+            Side::Bid => {
+                let notional = remaining * price_per_kwh;
+                let locked = notional + calculate_fee(notional, market.maker_fee_bps)?;
+                open_orders.quote_free -= locked;
+                open_orders.quote_locked += locked;
+                locked
+            }
+            Side::Ask => {
+                open_orders.credit_free -= remaining;
+                open_orders.credit_locked += remaining;
+                0
+            }
+        };
+        order_book.insert(
+            side,
+            BookOrder {
+                owner: open_orders_key,
+                beneficial_owner: owner_beneficiary,
+                order_id,
+                price: price_per_kwh,
+                quantity: remaining,
+                timestamp: now,
+                locked,
+                fee_bps: market.maker_fee_bps,
+                padding: [0; 6],
+            },
+        )?;
+    }
+
+    // Fees for every fill are already in the quote vault, sweep them in one transfer
+    if fees_collected > 0 {
+        let market_key = market.key();
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.quote_vault,
+            &ctx.accounts.fee_vault,
+            &ctx.accounts.token_program,
+            fees_collected,
+        )?;
+
+        market.total_fees_collected += fees_collected;
+        emit!(FeesCollectedEvent {
+            market: market_key,
+            mint: market.quote_mint,
+            maker_fee: maker_fees,
+            taker_fee: taker_fees,
+            timestamp: now,
+        });
+    }
+
+    emit!(OrderPlacedEvent {
+        order_id,
+        owner: ctx.accounts.owner.key(),
+        side,
+        price_per_kwh,
+        energy_amount,
+        filled_amount: energy_amount - remaining,
+        timestamp: now,
+    });
+
+    msg!("Placed order {}: {} of {} kWh filled", order_id, energy_amount - remaining, energy_amount);
+    Ok(())
+}
+
+pub(crate) fn cancel_order(
+    ctx: Context<CancelOrder>,
+    side: Side,
+    order_id: u64,
+) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_CANCELLATIONS)?;
+
+    let open_orders = &mut ctx.accounts.open_orders;
+    let mut order_book = ctx.accounts.order_book.load_mut()?;
+
+    let order = order_book.remove(side, order_id, open_orders.key())?;
+    match side {
+        Side::Bid => {
+            open_orders.quote_locked -= order.locked;
+            open_orders.quote_free += order.locked;
+        }
+        Side::Ask => {
+            open_orders.credit_locked -= order.quantity;
+            open_orders.credit_free += order.quantity;
+        }
+    }
+
+    emit!(OrderCancelledEvent {
+        order_id,
+        owner: ctx.accounts.owner.key(),
+        side,
+        energy_amount: order.quantity,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Order {} cancelled", order_id);
+    Ok(())
+}
+
+pub(crate) fn consume_events<'info>(
+    ctx: Context<'_, '_, '_, 'info, ConsumeEvents<'info>>,
+    limit: u16,
+) -> Result<()> {
+    // Settling fills only moves book balances, so the crank follows the trading switch
+    ctx.accounts.market.require_not_paused(PAUSE_TRADING)?;
+
+    let mut event_queue = ctx.accounts.event_queue.load_mut()?;
+
+    let mut processed: u16 = 0;
+    while processed < limit && event_queue.count > 0 {
+        let event = event_queue.peek();
+        let maker_info = match ctx
+            .remaining_accounts
+            .iter()
+            .find(|account| account.key() == event.maker)
+        {
+            Some(account) => account,
+            None => break,
+        };
+
+        let mut maker: Account<OpenOrders> = Account::try_from(maker_info)?;
+        require!(maker.market == ctx.accounts.market.key(), ErrorCode::Unauthorized);
+
+        let fill_cost = event.quantity * event.price;
+        if event.taker_side == Side::Bid as u8 {
+            // Maker's ask was lifted
+            maker.credit_locked -= event.quantity;
+            maker.quote_free += fill_cost - event.maker_fee;
+        } else {
+            // Maker's bid was hit
+            maker.quote_locked -= event.maker_unlock;
+            maker.quote_free += event.maker_unlock - fill_cost - event.maker_fee;
+            maker.credit_free += event.quantity;
+        }
+        maker.exit(&crate::ID)?;
+
+        event_queue.pop();
+        processed += 1;
+    }
+
+    msg!("Consumed {} events, {} remaining", processed, event_queue.count);
+    Ok(())
+}
+
+pub(crate) fn settle_funds(ctx: Context<SettleFunds>) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_WITHDRAWALS)?;
+
+    let market = &ctx.accounts.market;
+    let open_orders = &mut ctx.accounts.open_orders;
+
+    let credit_amount = open_orders.credit_free;
+    let quote_amount = open_orders.quote_free;
+
+    if credit_amount > 0 {
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.credit_vault,
+            &ctx.accounts.owner_credit_account,
+            &ctx.accounts.token_program,
+            credit_amount,
+        )?;
+    }
+
+    if quote_amount > 0 {
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.quote_vault,
+            &ctx.accounts.owner_token_account,
+            &ctx.accounts.token_program,
+            quote_amount,
+        )?;
+    }
+
+    open_orders.credit_free = 0;
+    open_orders.quote_free = 0;
+
+    emit!(FundsSettledEvent {
+        owner: open_orders.owner,
+        credit_amount,
+        quote_amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Settled {} credits and {} quote tokens", credit_amount, quote_amount);
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitializeOrderBook<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(zero)]
+    pub order_book: AccountLoader<'info, OrderBook>,
+    
+    #[account(zero)]
+    pub event_queue: AccountLoader<'info, EventQueue>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(address = market.credit_mint)]
+    pub credit_mint: Account<'info, Mint>,
+    
+    #[account(address = market.quote_mint)]
+    pub quote_mint: Account<'info, Mint>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"credit_vault", market.key().as_ref()],
+        bump,
+        token::mint = credit_mint,
+        token::authority = vault_authority
+    )]
+    pub credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"quote_vault", market.key().as_ref()],
+        bump,
+        token::mint = quote_mint,
+        token::authority = vault_authority
+    )]
+    pub quote_vault: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+    
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeOpenOrders<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + OpenOrders::INIT_SPACE,
+        seeds = [b"open_orders", market.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub open_orders: Account<'info, OpenOrders>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceOrder<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, address = market.order_book)]
+    pub order_book: AccountLoader<'info, OrderBook>,
+    
+    #[account(mut, address = market.event_queue)]
+    pub event_queue: AccountLoader<'info, EventQueue>,
+    
+    #[account(
+        mut,
+        seeds = [b"open_orders", market.key().as_ref(), owner.key().as_ref()],
+        bump = open_orders.bump,
+        has_one = owner
+    )]
+    pub open_orders: Account<'info, OpenOrders>,
+    
+    #[account(
+        mut,
+        seeds = [b"credit_vault", market.key().as_ref()],
+        bump = market.credit_vault_bump
+    )]
+    pub credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_vault", market.key().as_ref()],
+        bump = market.quote_vault_bump
+    )]
+    pub quote_vault: Account<'info, TokenAccount>,
+    
+    /// Funding account: quote tokens for bids, energy credits for asks
+    #[account(
+        mut,
+        token::authority = owner
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"fee_vault", market.key().as_ref()],
+        bump = market.fee_vault_bump
+    )]
+    pub fee_vault: Account<'info, TokenAccount>,
+    
+    /// CHECK: owner's oracle wallet link address, which may hold no account
+    #[account(
+        seeds = [b"wallet_link", owner.key().as_ref()],
+        bump,
+        seeds::program = energy_oracle::ID
+    )]
+    pub owner_link: UncheckedAccount<'info>,
+    
+    pub owner: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, address = market.order_book)]
+    pub order_book: AccountLoader<'info, OrderBook>,
+    
+    #[account(
+        mut,
+        seeds = [b"open_orders", market.key().as_ref(), owner.key().as_ref()],
+        bump = open_orders.bump,
+        has_one = owner
+    )]
+    pub open_orders: Account<'info, OpenOrders>,
+    
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ConsumeEvents<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, address = market.event_queue)]
+    pub event_queue: AccountLoader<'info, EventQueue>,
+}
+
+#[derive(Accounts)]
+pub struct SettleFunds<'info> {
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"open_orders", market.key().as_ref(), owner.key().as_ref()],
+        bump = open_orders.bump,
+        has_one = owner
+    )]
+    pub open_orders: Account<'info, OpenOrders>,
+    
+    #[account(
+        mut,
+        seeds = [b"credit_vault", market.key().as_ref()],
+        bump = market.credit_vault_bump
+    )]
+    pub credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"quote_vault", market.key().as_ref()],
+        bump = market.quote_vault_bump
+    )]
+    pub quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint
+    )]
+    pub owner_credit_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    
+    pub owner: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct OpenOrders {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub credit_free: u64,
+    pub credit_locked: u64,
+    pub quote_free: u64,
+    pub quote_locked: u64,
+    pub bump: u8,
+}
+
+/// Central limit order book. Each slab is kept sorted so the best order
+/// (highest bid / lowest ask, earliest first at equal price) sits at the end.
+#[account(zero_copy)]
+pub struct OrderBook {
+    pub market: Pubkey,
+    pub next_order_id: u64,
+    pub bid_count: u32,
+    pub ask_count: u32,
+    pub bids: [BookOrder; MAX_BOOK_ORDERS],
+    pub asks: [BookOrder; MAX_BOOK_ORDERS],
+}
+
+#[zero_copy]
+#[derive(Default)]
+pub struct BookOrder {
+    /// `OpenOrders` account of the order's owner
+    pub owner: Pubkey,
+    /// Meter owner the order's wallet was linked to when placed, or the wallet itself
+    pub beneficial_owner: Pubkey,
+    pub order_id: u64,
+    pub price: u64,
+    pub quantity: u64,
+    pub timestamp: i64,
+    /// Quote tokens (price plus maker fee) still locked by a resting bid
+    pub locked: u64,
+    pub fee_bps: u16,
+    pub padding: [u8; 6],
+}
+
+/// Ring buffer of fills waiting for the maker side to be settled
+#[account(zero_copy)]
+pub struct EventQueue {
+    pub market: Pubkey,
+    pub head: u64,
+    pub count: u64,
+    pub seq_num: u64,
+    pub events: [FillEvent; EVENT_QUEUE_CAPACITY],
+}
+
+#[zero_copy]
+pub struct FillEvent {
+    /// `OpenOrders` account of the resting order
+    pub maker: Pubkey,
+    /// `OpenOrders` account of the incoming order
+    pub taker: Pubkey,
+    pub maker_order_id: u64,
+    pub price: u64,
+    pub quantity: u64,
+    pub timestamp: i64,
+    pub maker_fee: u64,
+    /// Quote tokens released from the maker's lock when a resting bid is hit
+    pub maker_unlock: u64,
+    pub taker_side: u8,
+    pub padding: [u8; 7],
+}
+
+impl OrderBook {
+    fn slab_mut(&mut self, side: Side) -> (&mut [BookOrder; MAX_BOOK_ORDERS], &mut u32) {
+        match side {
+            Side::Bid => (&mut self.bids, &mut self.bid_count),
+            Side::Ask => (&mut self.asks, &mut self.ask_count),
+        }
+    }
+
+    pub fn best(&self, side: Side) -> Option<BookOrder> {
+        let (orders, count) = match side {
+            Side::Bid => (&self.bids, self.bid_count),
+            Side::Ask => (&self.asks, self.ask_count),
+        };
+        if count == 0 {
+            None
+        } else {
+            Some(orders[count as usize - 1])
+        }
+    }
+
+    /// Reduce the best order by a filled quantity, removing it once exhausted
+    pub fn fill_best(&mut self, side: Side, quantity: u64, unlocked: u64) {
+        let (orders, count) = self.slab_mut(side);
+        let best = &mut orders[*count as usize - 1];
+        best.quantity -= quantity;
+        best.locked -= unlocked;
+        if best.quantity == 0 {
+            *best = BookOrder::default();
+            *count -= 1;
+        }
+    }
+
+    pub fn insert(&mut self, side: Side, order: BookOrder) -> Result<()> {
+        let (orders, count) = self.slab_mut(side);
+        let len = *count as usize;
+        require!(len < MAX_BOOK_ORDERS, ErrorCode::OrderBookFull);
+
+        // New orders queue behind existing orders at the same price
+        let index = orders[..len]
+            .iter()
+            .position(|resting| match side {
+                Side::Bid => resting.price >= order.price,
+                Side::Ask => resting.price <= order.price,
+            })
+            .unwrap_or(len);
+        orders.copy_within(index..len, index + 1);
+        orders[index] = order;
+        *count += 1;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, side: Side, order_id: u64, owner: Pubkey) -> Result<BookOrder> {
+        let (orders, count) = self.slab_mut(side);
+        let len = *count as usize;
+        let index = orders[..len]
+            .iter()
+            .position(|resting| resting.order_id == order_id)
+            .ok_or(ErrorCode::OrderNotFound)?;
+        let order = orders[index];
+        require!(order.owner == owner, ErrorCode::Unauthorized);
+
+        orders.copy_within(index + 1..len, index);
+        orders[len - 1] = BookOrder::default();
+        *count -= 1;
+        Ok(order)
+    }
+}
+
+impl EventQueue {
+    pub fn push(&mut self, event: FillEvent) -> Result<()> {
+        require!((self.count as usize) < EVENT_QUEUE_CAPACITY, ErrorCode::EventQueueFull);
+        let index = (self.head as usize + self.count as usize) % EVENT_QUEUE_CAPACITY;
+        self.events[index] = event;
+        self.count += 1;
+        self.seq_num += 1;
+        Ok(())
+    }
+
+    pub fn peek(&self) -> FillEvent {
+        self.events[self.head as usize]
+    }
+
+    pub fn pop(&mut self) {
+        self.head = (self.head + 1) % EVENT_QUEUE_CAPACITY as u64;
+        self.count -= 1;
+    }
+}
+
+/// What happens to the part of an order that does not fill immediately
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TimeInForce {
+    GoodTillCancelled,
+    ImmediateOrCancel,
+    FillOrKill,
+    PostOnly,
+}
+
+#[event]
+pub struct OrderPlacedEvent {
+    pub order_id: u64,
+    pub owner: Pubkey,
+    pub side: Side,
+    pub price_per_kwh: u64,
+    pub energy_amount: u64,
+    pub filled_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderFilledEvent {
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub maker_order_id: u64,
+    pub taker_order_id: u64,
+    pub taker_side: Side,
+    pub price_per_kwh: u64,
+    pub energy_amount: u64,
+    pub maker_fee: u64,
+    pub taker_fee: u64,
+    /// Maker and taker are different wallets linked to the same meter owner
+    pub linked_trade: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderCancelledEvent {
+    pub order_id: u64,
+    pub owner: Pubkey,
+    pub side: Side,
+    pub energy_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FundsSettledEvent {
+    pub owner: Pubkey,
+    pub credit_amount: u64,
+    pub quote_amount: u64,
+    pub timestamp: i64,
+}
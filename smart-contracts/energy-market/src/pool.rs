@@ -0,0 +1,992 @@
+//! Aggregator pools selling many prosumers' credits as one seller
+
+use super::*;
+
+pub(crate) fn create_pool(ctx: Context<CreatePool>, pool_id: u16, fee_bps: u16) -> Result<()> {
+    require!(fee_bps <= MAX_POOL_FEE_BPS, ErrorCode::InvalidFee);
+
+    let pool = &mut ctx.accounts.pool;
+    pool.market = ctx.accounts.market.key();
+    pool.operator = ctx.accounts.operator.key();
+    pool.pool_id = pool_id;
+    pool.fee_bps = fee_bps;
+    pool.status = PoolStatus::Collecting;
+    pool.total_shares = 0;
+    pool.acc_proceeds_per_share = 0;
+    pool.proceeds_balance = 0;
+    pool.total_proceeds = 0;
+    pool.fees_owed = 0;
+    pool.offer_nonce = 0;
+    pool.open_offers = 0;
+    pool.bump = *ctx.bumps.get("pool").unwrap();
+    pool.credit_vault_bump = *ctx.bumps.get("pool_credit_vault").unwrap();
+    pool.quote_vault_bump = *ctx.bumps.get("pool_quote_vault").unwrap();
+
+    emit!(PoolCreatedEvent {
+        pool: pool.key(),
+        market: pool.market,
+        operator: pool.operator,
+        pool_id,
+        fee_bps,
+    });
+
+    msg!("Created pool {} with a {} bps aggregator fee", pool_id, fee_bps);
+    Ok(())
+}
+
+pub(crate) fn join_pool(ctx: Context<JoinPool>) -> Result<()> {
+    let member = &mut ctx.accounts.member;
+    member.pool = ctx.accounts.pool.key();
+    member.contributor = ctx.accounts.contributor.key();
+    member.shares = 0;
+    member.proceeds_debt = 0;
+    member.unclaimed_proceeds = 0;
+    member.bump = *ctx.bumps.get("member").unwrap();
+    Ok(())
+}
+
+pub(crate) fn contribute_to_pool(ctx: Context<ContributeToPool>, amount: u64) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_NEW_OFFERS)?;
+    require!(amount > 0, ErrorCode::InvalidAmount);
+
+    let pool = &mut ctx.accounts.pool;
+    require!(pool.status == PoolStatus::Collecting, ErrorCode::PoolNotCollecting);
+
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.contributor_credit_account.to_account_info(),
+        to: ctx.accounts.pool_credit_vault.to_account_info(),
+        authority: ctx.accounts.contributor.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    token::transfer(cpi_ctx, amount)?;
+
+    let member = &mut ctx.accounts.member;
+    pool.update_shares(member, amount, true)?;
+
+    emit!(PoolContributedEvent {
+        pool: pool.key(),
+        contributor: member.contributor,
+        amount,
+        shares: member.shares,
+        total_shares: pool.total_shares,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Contributed {} credits to the pool", amount);
+    Ok(())
+}
+
+pub(crate) fn withdraw_from_pool(ctx: Context<WithdrawFromPool>, shares: u64) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_WITHDRAWALS)?;
+    require!(shares > 0, ErrorCode::InvalidAmount);
+
+    let pool = &mut ctx.accounts.pool;
+    let member = &mut ctx.accounts.member;
+    require!(shares <= member.shares, ErrorCode::InsufficientShares);
+
+    let amount = match pool.status {
+        PoolStatus::Collecting => shares,
+        PoolStatus::Selling => return err!(ErrorCode::PoolLocked),
+        PoolStatus::Closed => {
+            let amount = (shares as u128) * (ctx.accounts.pool_credit_vault.amount as u128)
+                / (pool.total_shares as u128);
+            u64::try_from(amount).map_err(|_| error!(ErrorCode::Overflow))?
+        }
+    };
+
+    // Shares stop earning once redeemed; proceeds earned so far stay claimable
+    pool.accrue(ctx.accounts.pool_quote_vault.amount)?;
+    pool.update_shares(member, shares, false)?;
+
+    if amount > 0 {
+        pool_transfer(
+            pool,
+            &ctx.accounts.pool_credit_vault,
+            &ctx.accounts.contributor_credit_account,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+    }
+
+    emit!(PoolWithdrawnEvent {
+        pool: pool.key(),
+        contributor: member.contributor,
+        shares,
+        amount,
+        total_shares: pool.total_shares,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Redeemed {} pool shares for {} credits", shares, amount);
+    Ok(())
+}
+
+pub(crate) fn start_pool_selling(ctx: Context<UpdatePool>) -> Result<()> {
+    let pool = &mut ctx.accounts.pool;
+    require!(pool.status == PoolStatus::Collecting, ErrorCode::PoolNotCollecting);
+    require!(pool.total_shares > 0, ErrorCode::InvalidAmount);
+
+    pool.status = PoolStatus::Selling;
+
+    emit!(PoolStatusChangedEvent {
+        pool: pool.key(),
+        status: pool.status,
+        total_shares: pool.total_shares,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Pool is selling {} shares", pool.total_shares);
+    Ok(())
+}
+
+pub(crate) fn close_pool(ctx: Context<UpdatePool>) -> Result<()> {
+    let pool = &mut ctx.accounts.pool;
+    require!(pool.status == PoolStatus::Selling, ErrorCode::PoolNotSelling);
+    require!(pool.open_offers == 0, ErrorCode::PoolHasOpenOffers);
+
+    pool.status = PoolStatus::Closed;
+
+    emit!(PoolStatusChangedEvent {
+        pool: pool.key(),
+        status: pool.status,
+        total_shares: pool.total_shares,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Pool closed");
+    Ok(())
+}
+
+pub(crate) fn create_pool_offer(
+    ctx: Context<CreatePoolOffer>,
+    energy_amount: u64,
+    price_per_kwh: u64,
+    duration_hours: u32,
+    min_fill_amount: u64,
+) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_NEW_OFFERS)?;
+
+    require!(energy_amount > 0, ErrorCode::InvalidAmount);
+    require!(price_per_kwh > 0, ErrorCode::InvalidPrice);
+    require!(duration_hours > 0 && duration_hours <= 168, ErrorCode::InvalidDuration); // Max 1 week
+    require!(min_fill_amount <= energy_amount, ErrorCode::InvalidMinFill);
+
+    let pool = &mut ctx.accounts.pool;
+    require!(pool.status == PoolStatus::Selling, ErrorCode::PoolNotSelling);
+
+    // Lock the offered credits in the offer vault until traded or cancelled
+    pool_transfer(
+        pool,
+        &ctx.accounts.pool_credit_vault,
+        &ctx.accounts.offer_vault,
+        &ctx.accounts.token_program,
+        energy_amount,
+    )?;
+
+    let zone = seller_zone(&ctx.accounts.market, &ctx.accounts.pool_zone_member)?;
+    let now = Clock::get()?.unix_timestamp;
+
+    let offer = &mut ctx.accounts.offer;
+    let market = &mut ctx.accounts.market;
+    offer.seller = pool.key();
+    offer.rent_payer = ctx.accounts.operator.key();
+    offer.proceeds_account = ctx.accounts.pool_quote_vault.key();
+    offer.market = market.key();
+    offer.nonce = pool.offer_nonce;
+    offer.bump = *ctx.bumps.get("offer").unwrap();
+    pool.offer_nonce += 1;
+    pool.open_offers += 1;
+    offer.energy_amount = energy_amount;
+    offer.price_per_kwh = price_per_kwh;
+    offer.price_reference = Pubkey::default();
+    offer.price_offset_bps = 0;
+    offer.offer_type = OfferType::Immediate;
+    offer.status = OfferStatus::Active;
+    offer.created_at = now;
+    offer.opens_at = now;
+    offer.expires_at = now + (duration_hours as i64 * 3600);
+    offer.filled_amount = 0;
+    offer.min_fill_amount = min_fill_amount;
+    offer.delivery_start = 0;
+    offer.delivery_end = 0;
+    offer.meter = Pubkey::default();
+    offer.zone = zone;
+    offer.delivered_amount = 0;
+    offer.collateral_locked = 0;
+    offer.penalty_bps = market.penalty_bps;
+    offer.trade_count = 0;
+    offer.open_trades = 0;
+    offer.recurrence_interval = None;
+    offer.occurrences_remaining = 0;
+    offer.occurrence_index = 0;
+    offer.vault_bump = *ctx.bumps.get("offer_vault").unwrap();
+
+    market.total_offers += 1;
+
+    emit!(OfferCreatedEvent {
+        offer_id: offer.key(),
+        seller: offer.seller,
+        energy_amount,
+        price_per_kwh,
+        offer_type: OfferType::Immediate,
+        expires_at: offer.expires_at,
+    });
+
+    msg!("Pool offered {} kWh at {} per kWh", energy_amount, price_per_kwh);
+    Ok(())
+}
+
+pub(crate) fn cancel_pool_offer(ctx: Context<CancelPoolOffer>) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_CANCELLATIONS)?;
+
+    let offer = &mut ctx.accounts.offer;
+    let market = &ctx.accounts.market;
+    require!(offer.status == OfferStatus::Active, ErrorCode::OfferNotActive);
+
+    let refund_amount = offer.unsold_amount();
+    if refund_amount > 0 {
+        vault_transfer(
+            market,
+            &ctx.accounts.vault_authority,
+            &ctx.accounts.offer_vault,
+            &ctx.accounts.pool_credit_vault,
+            &ctx.accounts.token_program,
+            refund_amount,
+        )?;
+    }
+
+    offer.status = OfferStatus::Cancelled;
+    offer.occurrences_remaining = 0;
+
+    emit!(OfferCancelledEvent {
+        offer_id: offer.key(),
+        seller: offer.seller,
+        refunded_amount: refund_amount,
+        collateral_released: 0,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Pool offer cancelled");
+    Ok(())
+}
+
+pub(crate) fn close_pool_offer(ctx: Context<ClosePoolOffer>) -> Result<()> {
+    close_operator_offer(
+        &ctx.accounts.market,
+        &ctx.accounts.vault_authority,
+        &mut ctx.accounts.offer,
+        &ctx.accounts.offer_vault,
+        &ctx.accounts.pool_credit_vault,
+        &ctx.accounts.operator,
+        &ctx.accounts.token_program,
+    )?;
+    ctx.accounts.pool.open_offers -= 1;
+
+    msg!("Pool offer closed");
+    Ok(())
+}
+
+pub(crate) fn claim_pool_proceeds(ctx: Context<ClaimPoolProceeds>) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_WITHDRAWALS)?;
+
+    let pool = &mut ctx.accounts.pool;
+    let member = &mut ctx.accounts.member;
+    pool.accrue(ctx.accounts.pool_quote_vault.amount)?;
+    let amount = pool.settle_member(member)?;
+    require!(amount > 0, ErrorCode::NothingToClaim);
+
+    member.unclaimed_proceeds = 0;
+    pool.proceeds_balance -= amount;
+
+    pool_transfer(
+        pool,
+        &ctx.accounts.pool_quote_vault,
+        &ctx.accounts.contributor_token_account,
+        &ctx.accounts.token_program,
+        amount,
+    )?;
+
+    emit!(PoolProceedsClaimedEvent {
+        pool: pool.key(),
+        claimant: member.contributor,
+        amount,
+        aggregator_fee: false,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Claimed {} in pool proceeds", amount);
+    Ok(())
+}
+
+pub(crate) fn claim_pool_fees(ctx: Context<ClaimPoolFees>) -> Result<()> {
+    ctx.accounts.market.require_not_paused(PAUSE_WITHDRAWALS)?;
+
+    let pool = &mut ctx.accounts.pool;
+    pool.accrue(ctx.accounts.pool_quote_vault.amount)?;
+    let amount = pool.fees_owed;
+    require!(amount > 0, ErrorCode::NothingToClaim);
+
+    pool.fees_owed = 0;
+    pool.proceeds_balance -= amount;
+
+    pool_transfer(
+        pool,
+        &ctx.accounts.pool_quote_vault,
+        &ctx.accounts.operator_token_account,
+        &ctx.accounts.token_program,
+        amount,
+    )?;
+
+    emit!(PoolProceedsClaimedEvent {
+        pool: pool.key(),
+        claimant: pool.operator,
+        amount,
+        aggregator_fee: true,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Claimed {} in aggregator fees", amount);
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(pool_id: u16)]
+pub struct CreatePool<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init,
+        payer = operator,
+        space = 8 + Pool::INIT_SPACE,
+        seeds = [
+            b"pool",
+            market.key().as_ref(),
+            operator.key().as_ref(),
+            &pool_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(address = market.credit_mint)]
+    pub credit_mint: Account<'info, Mint>,
+    
+    #[account(address = market.quote_mint)]
+    pub quote_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = operator,
+        seeds = [b"pool_credit_vault", pool.key().as_ref()],
+        bump,
+        token::mint = credit_mint,
+        token::authority = pool
+    )]
+    pub pool_credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        init,
+        payer = operator,
+        seeds = [b"pool_quote_vault", pool.key().as_ref()],
+        bump,
+        token::mint = quote_mint,
+        token::authority = pool
+    )]
+    pub pool_quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+    
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct JoinPool<'info> {
+    pub pool: Account<'info, Pool>,
+    
+    #[account(mut)]
+    pub contributor: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = contributor,
+        space = 8 + PoolMember::INIT_SPACE,
+        seeds = [b"pool_member", pool.key().as_ref(), contributor.key().as_ref()],
+        bump
+    )]
+    pub member: Account<'info, PoolMember>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ContributeToPool<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market)]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool_member", pool.key().as_ref(), contributor.key().as_ref()],
+        bump = member.bump
+    )]
+    pub member: Account<'info, PoolMember>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool_credit_vault", pool.key().as_ref()],
+        bump = pool.credit_vault_bump
+    )]
+    pub pool_credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint,
+        token::authority = contributor
+    )]
+    pub contributor_credit_account: Account<'info, TokenAccount>,
+    
+    pub contributor: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFromPool<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market)]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool_member", pool.key().as_ref(), contributor.key().as_ref()],
+        bump = member.bump
+    )]
+    pub member: Account<'info, PoolMember>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool_credit_vault", pool.key().as_ref()],
+        bump = pool.credit_vault_bump
+    )]
+    pub pool_credit_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        seeds = [b"pool_quote_vault", pool.key().as_ref()],
+        bump = pool.quote_vault_bump
+    )]
+    pub pool_quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.credit_mint,
+        token::authority = contributor
+    )]
+    pub contributor_credit_account: Account<'info, TokenAccount>,
+    
+    pub contributor: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePool<'info> {
+    #[account(mut, has_one = operator @ ErrorCode::Unauthorized)]
+    pub pool: Account<'info, Pool>,
+    
+    pub operator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreatePoolOffer<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market, has_one = operator @ ErrorCode::Unauthorized)]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = operator,
+        space = 8 + EnergyOffer::INIT_SPACE,
+        seeds = [
+            b"offer",
+            market.key().as_ref(),
+            pool.key().as_ref(),
+            &pool.offer_nonce.to_le_bytes()
+        ],
+        bump
+    )]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(address = market.credit_mint)]
+    pub credit_mint: Account<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = operator,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump,
+        token::mint = credit_mint,
+        token::authority = vault_authority
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool_credit_vault", pool.key().as_ref()],
+        bump = pool.credit_vault_bump
+    )]
+    pub pool_credit_vault: Account<'info, TokenAccount>,
+    
+    /// Vault the offer's trade proceeds are paid into
+    #[account(
+        seeds = [b"pool_quote_vault", pool.key().as_ref()],
+        bump = pool.quote_vault_bump
+    )]
+    pub pool_quote_vault: Account<'info, TokenAccount>,
+    
+    /// CHECK: pool's zone assignment address, which may hold no account
+    #[account(
+        seeds = [b"zone_member", market.key().as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub pool_zone_member: UncheckedAccount<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+    
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPoolOffer<'info> {
+    #[account(
+        mut,
+        has_one = market,
+        constraint = offer.seller == pool.key() @ ErrorCode::Unauthorized
+    )]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    pub market: Account<'info, Market>,
+    
+    #[account(has_one = market, has_one = operator @ ErrorCode::Unauthorized)]
+    pub pool: Account<'info, Pool>,
+    
+    pub operator: Signer<'info>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump = offer.vault_bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool_credit_vault", pool.key().as_ref()],
+        bump = pool.credit_vault_bump
+    )]
+    pub pool_credit_vault: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePoolOffer<'info> {
+    #[account(
+        mut,
+        has_one = market,
+        constraint = offer.seller == pool.key() @ ErrorCode::Unauthorized,
+        constraint = offer.rent_payer == operator.key() @ ErrorCode::Unauthorized,
+        close = operator
+    )]
+    pub offer: Account<'info, EnergyOffer>,
+    
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market, has_one = operator @ ErrorCode::Unauthorized)]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    
+    /// CHECK: PDA that owns the market's token vaults
+    #[account(
+        seeds = [b"vault_authority", market.key().as_ref()],
+        bump = market.vault_authority_bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump = offer.vault_bump
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool_credit_vault", pool.key().as_ref()],
+        bump = pool.credit_vault_bump
+    )]
+    pub pool_credit_vault: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPoolProceeds<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market)]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool_member", pool.key().as_ref(), contributor.key().as_ref()],
+        bump = member.bump
+    )]
+    pub member: Account<'info, PoolMember>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool_quote_vault", pool.key().as_ref()],
+        bump = pool.quote_vault_bump
+    )]
+    pub pool_quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        token::authority = contributor
+    )]
+    pub contributor_token_account: Account<'info, TokenAccount>,
+    
+    pub contributor: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPoolFees<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market, has_one = operator @ ErrorCode::Unauthorized)]
+    pub pool: Account<'info, Pool>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool_quote_vault", pool.key().as_ref()],
+        bump = pool.quote_vault_bump
+    )]
+    pub pool_quote_vault: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = pool_quote_vault.mint
+    )]
+    pub operator_token_account: Account<'info, TokenAccount>,
+    
+    pub operator: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+/// Aggregator pool selling many contributors' credits under one seller
+#[account]
+#[derive(InitSpace)]
+pub struct Pool {
+    pub market: Pubkey,
+    /// Aggregator that posts the pool's offers
+    pub operator: Pubkey,
+    pub pool_id: u16,
+    /// Aggregator's cut of the pool's trade proceeds
+    pub fee_bps: u16,
+    pub status: PoolStatus,
+    /// One share per contributed credit
+    pub total_shares: u64,
+    /// Proceeds earned per share, scaled by `ACC_PRECISION`
+    pub acc_proceeds_per_share: u128,
+    /// Quote vault balance already split between contributors and the operator
+    pub proceeds_balance: u64,
+    pub total_proceeds: u64,
+    /// Aggregator fee not yet claimed
+    pub fees_owed: u64,
+    /// Nonce of the pool's next offer
+    pub offer_nonce: u64,
+    /// Offers created by the pool and not yet closed
+    pub open_offers: u32,
+    pub bump: u8,
+    pub credit_vault_bump: u8,
+    pub quote_vault_bump: u8,
+}
+
+impl Pool {
+    /// Split proceeds that reached the quote vault since the last call
+    pub fn accrue(&mut self, vault_balance: u64) -> Result<()> {
+        let proceeds = vault_balance.saturating_sub(self.proceeds_balance);
+        if proceeds == 0 {
+            return Ok(());
+        }
+
+        // Proceeds arriving after every share was redeemed go to the operator
+        let fee = if self.total_shares == 0 {
+            proceeds
+        } else {
+            calculate_fee(proceeds, self.fee_bps)?
+        };
+        let distributed = proceeds - fee;
+        if distributed > 0 {
+            self.acc_proceeds_per_share += (distributed as u128) * ACC_PRECISION
+                / (self.total_shares as u128);
+        }
+
+        self.fees_owed = self.fees_owed.checked_add(fee).ok_or(ErrorCode::Overflow)?;
+        self.total_proceeds = self.total_proceeds.checked_add(proceeds).ok_or(ErrorCode::Overflow)?;
+        self.proceeds_balance = vault_balance;
+        Ok(())
+    }
+
+    /// Move a member's accrued proceeds into their unclaimed balance and return it
+    pub fn settle_member(&self, member: &mut PoolMember) -> Result<u64> {
+        let pending = ((member.shares as u128) * self.acc_proceeds_per_share - member.proceeds_debt)
+            / ACC_PRECISION;
+        member.proceeds_debt += pending * ACC_PRECISION;
+        member.unclaimed_proceeds = member
+            .unclaimed_proceeds
+            .checked_add(u64::try_from(pending).map_err(|_| error!(ErrorCode::Overflow))?)
+            .ok_or(ErrorCode::Overflow)?;
+        Ok(member.unclaimed_proceeds)
+    }
+
+    /// Issue or redeem a member's shares, keeping the proceeds they earned so far
+    pub fn update_shares(&mut self, member: &mut PoolMember, shares: u64, issue: bool) -> Result<()> {
+        self.settle_member(member)?;
+        if issue {
+            member.shares = member.shares.checked_add(shares).ok_or(ErrorCode::Overflow)?;
+            self.total_shares = self.total_shares.checked_add(shares).ok_or(ErrorCode::Overflow)?;
+        } else {
+            member.shares -= shares;
+            self.total_shares -= shares;
+        }
+        member.proceeds_debt = (member.shares as u128) * self.acc_proceeds_per_share;
+        Ok(())
+    }
+}
+
+/// Transfer tokens out of one of a pool's vaults
+pub fn pool_transfer<'info>(
+    pool: &Account<'info, Pool>,
+    from: &impl ToAccountInfo<'info>,
+    to: &impl ToAccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let pool_id_bytes = pool.pool_id.to_le_bytes();
+    let seeds = &[
+        b"pool".as_ref(),
+        pool.market.as_ref(),
+        pool.operator.as_ref(),
+        pool_id_bytes.as_ref(),
+        &[pool.bump],
+    ];
+    signed_transfer(from, to, pool, token_program, &[&seeds[..]], amount)
+}
+
+/// A contributor's shares in an aggregator pool
+#[account]
+#[derive(InitSpace)]
+pub struct PoolMember {
+    pub pool: Pubkey,
+    pub contributor: Pubkey,
+    pub shares: u64,
+    /// Proceeds per share already accounted for, scaled by `ACC_PRECISION`
+    pub proceeds_debt: u128,
+    /// Proceeds settled to the member but not yet claimed
+    pub unclaimed_proceeds: u64,
+    pub bump: u8,
+}
+
+/// Lifecycle of an aggregator pool
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum PoolStatus {
+    /// Accepting contributions and withdrawals one credit per share
+    Collecting,
+    /// Shares are fixed while the operator sells the pooled credits
+    Selling,
+    /// Contributors withdraw the unsold credits pro-rata
+    Closed,
+}
+
+#[event]
+pub struct PoolCreatedEvent {
+    pub pool: Pubkey,
+    pub market: Pubkey,
+    pub operator: Pubkey,
+    pub pool_id: u16,
+    pub fee_bps: u16,
+}
+
+#[event]
+pub struct PoolContributedEvent {
+    pub pool: Pubkey,
+    pub contributor: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub total_shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolWithdrawnEvent {
+    pub pool: Pubkey,
+    pub contributor: Pubkey,
+    pub shares: u64,
+    pub amount: u64,
+    pub total_shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolStatusChangedEvent {
+    pub pool: Pubkey,
+    pub status: PoolStatus,
+    pub total_shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolProceedsClaimedEvent {
+    pub pool: Pubkey,
+    pub claimant: Pubkey,
+    pub amount: u64,
+    /// Whether the operator claimed the aggregator fee
+    pub aggregator_fee: bool,
+    pub timestamp: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(fee_bps: u16) -> Pool {
+        Pool {
+            market: Pubkey::new_unique(),
+            operator: Pubkey::new_unique(),
+            pool_id: 0,
+            fee_bps,
+            status: PoolStatus::Selling,
+            total_shares: 0,
+            acc_proceeds_per_share: 0,
+            proceeds_balance: 0,
+            total_proceeds: 0,
+            fees_owed: 0,
+            offer_nonce: 0,
+            open_offers: 0,
+            bump: 255,
+            credit_vault_bump: 255,
+            quote_vault_bump: 255,
+        }
+    }
+
+    fn member(pool: &mut Pool, shares: u64) -> PoolMember {
+        let mut member = PoolMember {
+            pool: Pubkey::new_unique(),
+            contributor: Pubkey::new_unique(),
+            shares: 0,
+            proceeds_debt: 0,
+            unclaimed_proceeds: 0,
+            bump: 255,
+        };
+        pool.update_shares(&mut member, shares, true).unwrap();
+        member
+    }
+
+    #[test]
+    fn accrue_splits_new_proceeds_between_operator_and_shares() {
+        let mut pool = pool(1_000);
+        let mut small = member(&mut pool, 30);
+        let mut large = member(&mut pool, 70);
+
+        pool.accrue(1_000).unwrap();
+        assert_eq!(pool.fees_owed, 100);
+        assert_eq!(pool.total_proceeds, 1_000);
+        assert_eq!(pool.settle_member(&mut small).unwrap(), 270);
+        assert_eq!(pool.settle_member(&mut large).unwrap(), 630);
+
+        // Proceeds already split are not counted twice, and settling again pays nothing new
+        pool.accrue(1_000).unwrap();
+        assert_eq!(pool.fees_owed, 100);
+        assert_eq!(pool.settle_member(&mut small).unwrap(), 270);
+
+        pool.accrue(2_000).unwrap();
+        assert_eq!(pool.fees_owed, 200);
+        assert_eq!(pool.total_proceeds, 2_000);
+        assert_eq!(pool.settle_member(&mut small).unwrap(), 540);
+        assert_eq!(pool.settle_member(&mut large).unwrap(), 1_260);
+    }
+
+    #[test]
+    fn accrue_pays_the_operator_when_no_shares_remain() {
+        let mut pool = pool(1_000);
+        pool.accrue(500).unwrap();
+        assert_eq!(pool.fees_owed, 500);
+        assert_eq!(pool.acc_proceeds_per_share, 0);
+    }
+
+    #[test]
+    fn settle_member_only_pays_proceeds_earned_while_holding_shares() {
+        let mut pool = pool(0);
+        let mut early = member(&mut pool, 50);
+        pool.accrue(500).unwrap();
+
+        let mut late = member(&mut pool, 50);
+        assert_eq!(pool.settle_member(&mut late).unwrap(), 0);
+
+        pool.accrue(1_500).unwrap();
+        assert_eq!(pool.settle_member(&mut early).unwrap(), 1_000);
+        assert_eq!(pool.settle_member(&mut late).unwrap(), 500);
+
+        // Redeeming shares keeps what was earned but stops further accrual
+        pool.update_shares(&mut late, 50, false).unwrap();
+        pool.accrue(2_000).unwrap();
+        assert_eq!(pool.settle_member(&mut late).unwrap(), 500);
+        assert_eq!(pool.settle_member(&mut early).unwrap(), 1_500);
+    }
+
+    #[test]
+    fn settle_member_rounds_down_so_the_vault_stays_solvent() {
+        let mut pool = pool(0);
+        let mut members = [member(&mut pool, 1), member(&mut pool, 1), member(&mut pool, 1)];
+        pool.accrue(100).unwrap();
+
+        let paid: u64 = members.iter_mut().map(|member| pool.settle_member(member).unwrap()).sum();
+        assert_eq!(paid, 99);
+    }
+}
@@ -0,0 +1,393 @@
+//! Grid zones and the transfer capacity of the links between them
+
+use super::*;
+
+pub(crate) fn register_zone(
+    ctx: Context<RegisterZone>,
+    zone_id: u16,
+    price_adder_per_kwh: u64,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+    market.zone_count = market.zone_count.checked_add(1).ok_or(ErrorCode::Overflow)?;
+
+    let zone = &mut ctx.accounts.zone;
+    zone.market = market.key();
+    zone.zone_id = zone_id;
+    zone.price_adder_per_kwh = price_adder_per_kwh;
+    zone.neighbours = Vec::new();
+    zone.bump = *ctx.bumps.get("zone").unwrap();
+
+    emit!(ZoneRegisteredEvent {
+        market: market.key(),
+        zone: zone.key(),
+        zone_id,
+        price_adder_per_kwh,
+    });
+
+    msg!("Registered zone {}", zone_id);
+    Ok(())
+}
+
+pub(crate) fn update_zone(ctx: Context<UpdateZone>, price_adder_per_kwh: u64) -> Result<()> {
+    let market = &ctx.accounts.market;
+    require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+
+    let zone = &mut ctx.accounts.zone;
+    zone.price_adder_per_kwh = price_adder_per_kwh;
+
+    msg!("Zone {} price adder updated: {}", zone.zone_id, price_adder_per_kwh);
+    Ok(())
+}
+
+pub(crate) fn create_zone_link(ctx: Context<CreateZoneLink>, transfer_capacity: u64) -> Result<()> {
+    let market = &ctx.accounts.market;
+    require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+
+    let from_zone = &mut ctx.accounts.from_zone;
+    let to_zone = &ctx.accounts.to_zone;
+    require!(from_zone.key() != to_zone.key(), ErrorCode::InvalidZoneLink);
+    require!(
+        from_zone.neighbours.len() < MAX_ZONE_NEIGHBOURS,
+        ErrorCode::TooManyNeighbours
+    );
+    from_zone.neighbours.push(to_zone.zone_id);
+
+    let zone_link = &mut ctx.accounts.zone_link;
+    zone_link.market = market.key();
+    zone_link.from_zone = from_zone.key();
+    zone_link.to_zone = to_zone.key();
+    zone_link.transfer_capacity = transfer_capacity;
+    zone_link.flows = [ZoneFlow::default(); ZONE_FLOW_SLOTS];
+    zone_link.bump = *ctx.bumps.get("zone_link").unwrap();
+
+    emit!(ZoneLinkUpdatedEvent {
+        market: market.key(),
+        from_zone_id: from_zone.zone_id,
+        to_zone_id: to_zone.zone_id,
+        transfer_capacity,
+    });
+
+    msg!("Linked zone {} to zone {}", from_zone.zone_id, to_zone.zone_id);
+    Ok(())
+}
+
+pub(crate) fn update_zone_link(ctx: Context<UpdateZoneLink>, transfer_capacity: u64) -> Result<()> {
+    let market = &ctx.accounts.market;
+    require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+
+    let zone_link = &mut ctx.accounts.zone_link;
+    zone_link.transfer_capacity = transfer_capacity;
+
+    emit!(ZoneLinkUpdatedEvent {
+        market: market.key(),
+        from_zone_id: ctx.accounts.from_zone.zone_id,
+        to_zone_id: ctx.accounts.to_zone.zone_id,
+        transfer_capacity,
+    });
+
+    msg!("Zone link capacity updated: {}", transfer_capacity);
+    Ok(())
+}
+
+pub(crate) fn assign_zone(ctx: Context<AssignZone>, member: Pubkey) -> Result<()> {
+    let market = &ctx.accounts.market;
+    require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+
+    let zone_member = &mut ctx.accounts.zone_member;
+    zone_member.market = market.key();
+    zone_member.member = member;
+    zone_member.zone = ctx.accounts.zone.key();
+    zone_member.bump = *ctx.bumps.get("zone_member").unwrap();
+
+    emit!(ZoneAssignedEvent {
+        market: market.key(),
+        member,
+        zone: zone_member.zone,
+    });
+
+    msg!("Assigned {} to zone {}", member, ctx.accounts.zone.zone_id);
+    Ok(())
+}
+
+pub(crate) fn unassign_zone(ctx: Context<UnassignZone>) -> Result<()> {
+    let market = &ctx.accounts.market;
+    require!(market.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+
+    let member = ctx.accounts.zone_member.member;
+    emit!(ZoneAssignedEvent {
+        market: market.key(),
+        member,
+        zone: Pubkey::default(),
+    });
+
+    msg!("Removed {} from its zone", member);
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(zone_id: u16)]
+pub struct RegisterZone<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Zone::INIT_SPACE,
+        seeds = [b"zone", market.key().as_ref(), &zone_id.to_le_bytes()],
+        bump
+    )]
+    pub zone: Account<'info, Zone>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateZone<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market)]
+    pub zone: Account<'info, Zone>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateZoneLink<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, has_one = market)]
+    pub from_zone: Account<'info, Zone>,
+    
+    #[account(has_one = market)]
+    pub to_zone: Account<'info, Zone>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ZoneLink::INIT_SPACE,
+        seeds = [b"zone_link", from_zone.key().as_ref(), to_zone.key().as_ref()],
+        bump
+    )]
+    pub zone_link: Box<Account<'info, ZoneLink>>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateZoneLink<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(has_one = market)]
+    pub from_zone: Account<'info, Zone>,
+    
+    #[account(has_one = market)]
+    pub to_zone: Account<'info, Zone>,
+    
+    #[account(
+        mut,
+        seeds = [b"zone_link", from_zone.key().as_ref(), to_zone.key().as_ref()],
+        bump = zone_link.bump
+    )]
+    pub zone_link: Box<Account<'info, ZoneLink>>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(member: Pubkey)]
+pub struct AssignZone<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(has_one = market)]
+    pub zone: Account<'info, Zone>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ZoneMember::INIT_SPACE,
+        seeds = [b"zone_member", market.key().as_ref(), member.as_ref()],
+        bump
+    )]
+    pub zone_member: Account<'info, ZoneMember>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnassignZone<'info> {
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        has_one = market,
+        close = authority,
+        seeds = [b"zone_member", market.key().as_ref(), zone_member.member.as_ref()],
+        bump = zone_member.bump
+    )]
+    pub zone_member: Account<'info, ZoneMember>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+/// A grid zone of the distribution network
+#[account]
+#[derive(InitSpace)]
+pub struct Zone {
+    pub market: Pubkey,
+    pub zone_id: u16,
+    /// Added to the price of energy imported into this zone from another zone
+    pub price_adder_per_kwh: u64,
+    /// Zones this zone can export to
+    #[max_len(8)]
+    pub neighbours: Vec<u16>,
+    pub bump: u8,
+}
+
+/// One-way transfer path between two neighbouring zones
+#[account]
+#[derive(InitSpace)]
+pub struct ZoneLink {
+    pub market: Pubkey,
+    pub from_zone: Pubkey,
+    pub to_zone: Pubkey,
+    /// Energy that may flow from `from_zone` to `to_zone` per interval
+    pub transfer_capacity: u64,
+    /// Flow booked per interval, indexed by interval number modulo `ZONE_FLOW_SLOTS`
+    pub flows: [ZoneFlow; ZONE_FLOW_SLOTS],
+    pub bump: u8,
+}
+
+/// Authority-set grid zone of a trading wallet or meter
+#[account]
+#[derive(InitSpace)]
+pub struct ZoneMember {
+    pub market: Pubkey,
+    /// Wallet or meter the assignment applies to
+    pub member: Pubkey,
+    pub zone: Pubkey,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct ZoneFlow {
+    pub interval_start: i64,
+    pub transferred: u64,
+}
+
+impl ZoneLink {
+    /// Book `amount` of transfer capacity in the interval containing `timestamp`
+    pub fn book_flow(&mut self, timestamp: i64, amount: u64) -> Result<()> {
+        let interval_start = timestamp - timestamp.rem_euclid(ZONE_INTERVAL_SECS);
+        let slot = (interval_start / ZONE_INTERVAL_SECS).rem_euclid(ZONE_FLOW_SLOTS as i64) as usize;
+        let flow = &mut self.flows[slot];
+        if flow.interval_start != interval_start {
+            // A slot still holding a later interval means the trade is too far out of range
+            require!(flow.interval_start < interval_start, ErrorCode::ZoneFlowUnavailable);
+            flow.interval_start = interval_start;
+            flow.transferred = 0;
+        }
+
+        let transferred = flow.transferred.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        require!(transferred <= self.transfer_capacity, ErrorCode::TransferCapacityExceeded);
+        flow.transferred = transferred;
+        Ok(())
+    }
+}
+
+/// Zone assigned to a wallet or meter, read from its `zone_member` address
+///
+/// The address holds no account for members outside any zone.
+pub fn assigned_zone(zone_member: &AccountInfo) -> Result<Pubkey> {
+    if zone_member.data_is_empty() {
+        return Ok(Pubkey::default());
+    }
+    let zone_member: Account<ZoneMember> = Account::try_from(zone_member)?;
+    Ok(zone_member.zone)
+}
+
+/// Zone a seller's offers trade from, read from its `zone_member` address
+///
+/// Once the market has zones every seller must be assigned to one, so credits
+/// moved to a fresh wallet cannot escape the transfer limits of their zone.
+pub fn seller_zone(market: &Market, zone_member: &AccountInfo) -> Result<Pubkey> {
+    let zone = assigned_zone(zone_member)?;
+    require!(
+        market.zone_count == 0 || zone != Pubkey::default(),
+        ErrorCode::ZoneAssignmentRequired
+    );
+    Ok(zone)
+}
+
+/// Check a trade against grid zone constraints and return the congestion charge owed
+///
+/// Offers without a zone, which only markets without zones have, trade freely.
+/// Zoned offers can only be bought by wallets assigned to a zone; trades within a
+/// zone are uncharged, and trades across zones need a direct link with spare
+/// capacity in the delivery interval and pay the buyer zone's price adder.
+pub fn apply_zone_transfer(
+    offer: &EnergyOffer,
+    buyer_zone_member: &AccountInfo,
+    buyer_zone: Option<&Account<Zone>>,
+    zone_link: Option<&mut Account<ZoneLink>>,
+    delivery_time: i64,
+    energy_amount: u64,
+) -> Result<u64> {
+    if offer.zone == Pubkey::default() {
+        return Ok(0);
+    }
+    let buyer_zone_key = assigned_zone(buyer_zone_member)?;
+    require!(buyer_zone_key != Pubkey::default(), ErrorCode::ZoneRequired);
+    if buyer_zone_key == offer.zone {
+        return Ok(0);
+    }
+    let buyer_zone = buyer_zone.ok_or(ErrorCode::ZoneRequired)?;
+    require!(buyer_zone.key() == buyer_zone_key, ErrorCode::ZoneMismatch);
+
+    let zone_link = zone_link.ok_or(ErrorCode::ZonesNotConnected)?;
+    require!(
+        zone_link.from_zone == offer.zone && zone_link.to_zone == buyer_zone.key(),
+        ErrorCode::ZonesNotConnected
+    );
+    zone_link.book_flow(delivery_time, energy_amount)?;
+
+    let charge = energy_amount
+        .checked_mul(buyer_zone.price_adder_per_kwh)
+        .ok_or(ErrorCode::Overflow)?;
+    Ok(charge)
+}
+
+#[event]
+pub struct ZoneRegisteredEvent {
+    pub market: Pubkey,
+    pub zone: Pubkey,
+    pub zone_id: u16,
+    pub price_adder_per_kwh: u64,
+}
+
+#[event]
+pub struct ZoneAssignedEvent {
+    pub market: Pubkey,
+    pub member: Pubkey,
+    /// Assigned zone, or the default key once the member is removed from its zone
+    pub zone: Pubkey,
+}
+
+#[event]
+pub struct ZoneLinkUpdatedEvent {
+    pub market: Pubkey,
+    pub from_zone_id: u16,
+    pub to_zone_id: u16,
+    pub transfer_capacity: u64,
+}
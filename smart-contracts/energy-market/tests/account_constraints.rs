@@ -0,0 +1,1108 @@
+//! Account validation of the market instructions when an account is substituted,
+//! and settlement of deliveries whose meter readings are gone
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Once;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::{ProgramResult, SUCCESS};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::system_program;
+use anchor_spl::token::spl_token;
+use anchor_spl::token::spl_token::state::AccountState;
+use energy_market::{
+    meter_register_at, AcceptedMint, CreateOffer, CurtailmentBid, DemandResponseEvent,
+    DemandResponseStatus, EnergyOffer, EnergyTrade, ExecuteTrade, Market, OfferStatus,
+    OfferType, SelfTradeMode, SellerAccount, SettleDelivery, SubmitCurtailmentBid,
+};
+use energy_oracle::{EnergyMeter, MeterReading, MeterType, ReadingType, ReferencePrice};
+
+const NOW: i64 = 1_700_000_000;
+
+/// Stands in for the runtime pieces the instructions use: the rent and clock
+/// sysvars (the clock reading `NOW`), the system program's `create_account` and
+/// the token program's `initialize_account3` and `transfer`
+struct Runtime;
+
+impl SyscallStubs for Runtime {
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        SUCCESS
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Clock) = Clock { unix_timestamp: NOW, ..Clock::default() } };
+        SUCCESS
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        _signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let account = |index: usize| {
+            let key = instruction.accounts[index].pubkey;
+            account_infos.iter().find(|info| *info.key == key).unwrap()
+        };
+        let data = &instruction.data;
+        if instruction.program_id == system_program::ID {
+            assert_eq!(data[..4], [0, 0, 0, 0], "only create_account is supported");
+            let lamports = u64::from_le_bytes(data[4..12].try_into().unwrap());
+            let space = u64::from_le_bytes(data[12..20].try_into().unwrap());
+            assert_eq!(account(1).data_len() as u64, space);
+            **account(0).try_borrow_mut_lamports()? -= lamports;
+            **account(1).try_borrow_mut_lamports()? += lamports;
+            account(1).assign(&Pubkey::try_from(&data[20..52]).unwrap());
+        } else if data[0] == 3 {
+            assert_eq!(instruction.program_id, spl_token::ID);
+            let amount = u64::from_le_bytes(data[1..9].try_into().unwrap());
+            let move_tokens = |index: usize, change: &dyn Fn(u64) -> Option<u64>| {
+                let mut data = account(index).data.borrow_mut();
+                let mut token_account = spl_token::state::Account::unpack(&data)?;
+                token_account.amount =
+                    change(token_account.amount).ok_or(ProgramError::InsufficientFunds)?;
+                spl_token::state::Account::pack(token_account, &mut data)
+            };
+            move_tokens(0, &|balance| balance.checked_sub(amount))?;
+            move_tokens(1, &|balance| balance.checked_add(amount))?;
+        } else {
+            assert_eq!(instruction.program_id, spl_token::ID);
+            assert_eq!(data[0], 18, "only initialize_account3 and transfer are supported");
+            let token_account = spl_token::state::Account {
+                mint: *account(1).key,
+                owner: Pubkey::try_from(&data[1..33]).unwrap(),
+                state: AccountState::Initialized,
+                ..Default::default()
+            };
+            spl_token::state::Account::pack(token_account, &mut account(0).data.borrow_mut())?;
+        }
+        Ok(())
+    }
+}
+
+struct TestAccount {
+    key: Pubkey,
+    owner: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+    is_signer: bool,
+    executable: bool,
+}
+
+impl TestAccount {
+    fn new(owner: Pubkey, data: Vec<u8>) -> Self {
+        Self {
+            key: Pubkey::new_unique(),
+            owner,
+            lamports: 1_000_000_000,
+            data,
+            is_signer: false,
+            executable: false,
+        }
+    }
+
+    fn wallet(key: Pubkey) -> Self {
+        Self { key, ..Self::new(system_program::ID, Vec::new()) }
+    }
+
+    fn signer(key: Pubkey) -> Self {
+        Self { is_signer: true, ..Self::wallet(key) }
+    }
+
+    fn program(id: Pubkey) -> Self {
+        Self { key: id, executable: true, ..Self::new(Pubkey::default(), Vec::new()) }
+    }
+
+    /// Placeholder for an optional account that is not passed
+    fn none() -> Self {
+        Self::program(energy_market::ID)
+    }
+
+    /// Unfunded address that holds no account, or that an instruction initializes
+    fn uninitialized(key: Pubkey, space: usize) -> Self {
+        Self { key, lamports: 0, ..Self::new(system_program::ID, vec![0; space]) }
+    }
+
+    fn anchor<T: AccountSerialize + Owner>(key: Pubkey, value: &T) -> Self {
+        let mut data = Vec::new();
+        value.try_serialize(&mut data).unwrap();
+        Self { key, ..Self::new(T::owner(), data) }
+    }
+
+    fn mint(key: Pubkey) -> Self {
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        let mint = spl_token::state::Mint {
+            mint_authority: COption::Some(Pubkey::new_unique()),
+            supply: 0,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        };
+        spl_token::state::Mint::pack(mint, &mut data).unwrap();
+        Self { key, ..Self::new(spl_token::ID, data) }
+    }
+
+    fn token_account(key: Pubkey, mint: Pubkey, owner: Pubkey) -> Self {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        let account = spl_token::state::Account {
+            mint,
+            owner,
+            state: AccountState::Initialized,
+            ..Default::default()
+        };
+        spl_token::state::Account::pack(account, &mut data).unwrap();
+        Self { key, ..Self::new(spl_token::ID, data) }
+    }
+
+    /// Token account holding `amount`
+    fn funded(mut self, amount: u64) -> Self {
+        let mut token_account = spl_token::state::Account::unpack(&self.data).unwrap();
+        token_account.amount = amount;
+        spl_token::state::Account::pack(token_account, &mut self.data).unwrap();
+        self
+    }
+
+    fn rent_sysvar() -> Self {
+        let rent = Rent::default();
+        let mut data = rent.lamports_per_byte_year.to_le_bytes().to_vec();
+        data.extend_from_slice(&rent.exemption_threshold.to_le_bytes());
+        data.push(rent.burn_percent);
+        Self { key: sysvar::rent::ID, ..Self::new(sysvar::ID, data) }
+    }
+
+    fn info(&mut self) -> AccountInfo<'_> {
+        AccountInfo::new(
+            &self.key,
+            self.is_signer,
+            true,
+            &mut self.lamports,
+            &mut self.data,
+            &self.owner,
+            self.executable,
+            0,
+        )
+    }
+}
+
+fn install_runtime() {
+    static RUNTIME: Once = Once::new();
+    RUNTIME.call_once(|| {
+        set_syscall_stubs(Box::new(Runtime));
+    });
+}
+
+fn try_accounts<'info, T: Accounts<'info>>(accounts: &'info mut [TestAccount]) -> Result<()> {
+    install_runtime();
+    let infos: Vec<AccountInfo<'info>> = accounts.iter_mut().map(TestAccount::info).collect();
+    T::try_accounts(
+        &energy_market::ID,
+        &mut &infos[..],
+        &[],
+        &mut BTreeMap::new(),
+        &mut BTreeSet::new(),
+    )
+    .map(|_| ())
+}
+
+fn assert_rejected(result: Result<impl Sized>, expected: impl Into<u32>) {
+    match result {
+        Ok(_) => panic!("substituted account was accepted"),
+        Err(err) => assert_eq!(ProgramError::from(err), ProgramError::Custom(expected.into())),
+    }
+}
+
+fn market_pda(seeds: &[&[u8]]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(seeds, &energy_market::ID)
+}
+
+fn oracle_pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &energy_oracle::ID).0
+}
+
+/// Market with a seller's metered scheduled offer, one trade on it and a buyer
+struct Exchange {
+    market_key: Pubkey,
+    market: Market,
+    seller: Pubkey,
+    buyer: Pubkey,
+    meter_key: Pubkey,
+    meter: EnergyMeter,
+    reference_price_key: Pubkey,
+    reference_price: ReferencePrice,
+    offer_key: Pubkey,
+    offer: EnergyOffer,
+    trade_key: Pubkey,
+    trade: EnergyTrade,
+}
+
+impl Exchange {
+    fn new() -> Self {
+        let market_key = Pubkey::new_unique();
+        let oracle = Pubkey::new_unique();
+        let bump = |seed: &[u8]| market_pda(&[seed, market_key.as_ref()]).1;
+        let market = Market {
+            authority: Pubkey::new_unique(),
+            pending_authority: Pubkey::default(),
+            creator: Pubkey::new_unique(),
+            credit_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            oracle,
+            market_index: 0,
+            bump: 255,
+            vault_authority_bump: bump(b"vault_authority"),
+            fee_vault_bump: bump(b"fee_vault"),
+            maker_fee_bps: 10,
+            taker_fee_bps: 20,
+            collateral_vault_bump: bump(b"collateral_vault"),
+            insurance_vault_bump: bump(b"insurance_vault"),
+            collateral_bps: 1_000,
+            penalty_bps: 500,
+            insurance_bps: 2_000,
+            total_fees_collected: 0,
+            total_penalties: 0,
+            total_congestion_charges: 0,
+            total_offers: 1,
+            total_bids: 0,
+            total_volume_traded: 0,
+            total_linked_volume: 0,
+            is_active: true,
+            paused_flags: 0,
+            auto_unpause_at: 0,
+            order_book: Pubkey::default(),
+            event_queue: Pubkey::default(),
+            credit_vault_bump: 0,
+            quote_vault_bump: 0,
+            self_trade_mode: SelfTradeMode::Reject,
+            zone_count: 0,
+        };
+
+        let seller = Pubkey::new_unique();
+        let buyer = Pubkey::new_unique();
+        let meter_key = Pubkey::new_unique();
+        let meter = EnergyMeter {
+            meter_id: "MTR-001".to_string(),
+            meter_type: MeterType::Solar,
+            location: "Feeder 4".to_string(),
+            owner: seller,
+            oracle,
+            is_authorized: true,
+            registered_at: NOW - 10 * 86_400,
+            last_reading_at: NOW,
+            total_readings: 1,
+            production_register: 1_600,
+            consumption_register: 0,
+        };
+        let reference_price_key = Pubkey::new_unique();
+        let reference_price = ReferencePrice {
+            oracle,
+            name: "grid-tariff".to_string(),
+            price_per_kwh: 120,
+            updated_at: NOW,
+            bump: 255,
+        };
+
+        let offer_key = Pubkey::new_unique();
+        let offer = EnergyOffer {
+            seller,
+            rent_payer: seller,
+            proceeds_account: Pubkey::default(),
+            market: market_key,
+            nonce: 0,
+            bump: 255,
+            energy_amount: 1_000,
+            price_per_kwh: 100,
+            price_reference: Pubkey::default(),
+            price_offset_bps: 0,
+            offer_type: OfferType::Scheduled,
+            status: OfferStatus::Active,
+            created_at: NOW - 86_400,
+            opens_at: NOW - 86_400,
+            expires_at: NOW + 86_400,
+            filled_amount: 400,
+            min_fill_amount: 0,
+            delivery_start: NOW - 7_200,
+            delivery_end: NOW - 3_600,
+            meter: meter_key,
+            zone: Pubkey::default(),
+            delivered_amount: 0,
+            collateral_locked: 0,
+            penalty_bps: 500,
+            trade_count: 1,
+            open_trades: 1,
+            recurrence_interval: None,
+            occurrences_remaining: 0,
+            occurrence_index: 0,
+            vault_bump: market_pda(&[b"offer_vault", offer_key.as_ref()]).1,
+        };
+        let trade_key = Pubkey::new_unique();
+        let trade = EnergyTrade {
+            offer: offer_key,
+            market: market_key,
+            buyer,
+            seller,
+            energy_amount: 400,
+            total_cost: 40_000,
+            delivery_start: offer.delivery_start,
+            delivery_end: offer.delivery_end,
+            meter: meter_key,
+            delivered_amount: 0,
+            executed_at: NOW - 86_400,
+            is_settled: false,
+            bump: 255,
+            escrow_bump: market_pda(&[b"trade_escrow", trade_key.as_ref()]).1,
+        };
+
+        Self {
+            market_key,
+            market,
+            seller,
+            buyer,
+            meter_key,
+            meter,
+            reference_price_key,
+            reference_price,
+            offer_key,
+            offer,
+            trade_key,
+            trade,
+        }
+    }
+
+    fn market(&self) -> TestAccount {
+        TestAccount::anchor(self.market_key, &self.market)
+    }
+
+    fn offer(&self) -> TestAccount {
+        TestAccount::anchor(self.offer_key, &self.offer)
+    }
+
+    fn trade(&self) -> TestAccount {
+        TestAccount::anchor(self.trade_key, &self.trade)
+    }
+
+    fn meter(&self) -> TestAccount {
+        TestAccount::anchor(self.meter_key, &self.meter)
+    }
+
+    fn reference_price(&self) -> TestAccount {
+        TestAccount::anchor(self.reference_price_key, &self.reference_price)
+    }
+
+    fn vault_authority(&self) -> Pubkey {
+        market_pda(&[b"vault_authority", self.market_key.as_ref()]).0
+    }
+
+    /// Market vault at the address derived from `seed` and the market
+    fn vault(&self, seed: &[u8], mint: Pubkey) -> TestAccount {
+        let (key, _) = market_pda(&[seed, self.market_key.as_ref()]);
+        TestAccount::token_account(key, mint, self.vault_authority())
+    }
+
+    fn offer_vault(&self) -> TestAccount {
+        let (key, _) = market_pda(&[b"offer_vault", self.offer_key.as_ref()]);
+        TestAccount::token_account(key, self.market.credit_mint, self.vault_authority())
+    }
+
+    fn quote_account(&self, owner: Pubkey) -> TestAccount {
+        TestAccount::token_account(Pubkey::new_unique(), self.market.quote_mint, owner)
+    }
+
+    fn credit_account(&self, owner: Pubkey) -> TestAccount {
+        TestAccount::token_account(Pubkey::new_unique(), self.market.credit_mint, owner)
+    }
+
+    fn zone_member(&self, member: Pubkey) -> TestAccount {
+        let (key, _) = market_pda(&[b"zone_member", self.market_key.as_ref(), member.as_ref()]);
+        TestAccount::uninitialized(key, 0)
+    }
+
+    fn wallet_link(&self, wallet: Pubkey) -> TestAccount {
+        TestAccount::uninitialized(oracle_pda(&[b"wallet_link", wallet.as_ref()]), 0)
+    }
+
+    /// The same kind of account registered with another oracle
+    fn foreign_meter(&self) -> TestAccount {
+        let meter = EnergyMeter { oracle: Pubkey::new_unique(), ..self.meter.clone() };
+        TestAccount::anchor(self.meter_key, &meter)
+    }
+
+    fn foreign_reference_price(&self) -> TestAccount {
+        let reference_price = ReferencePrice {
+            oracle: Pubkey::new_unique(),
+            ..self.reference_price.clone()
+        };
+        TestAccount::anchor(self.reference_price_key, &reference_price)
+    }
+
+    fn execute_trade(&self) -> ExecuteTradeAccounts {
+        ExecuteTradeAccounts {
+            offer: self.offer(),
+            market: self.market(),
+            vault_authority: TestAccount::wallet(self.vault_authority()),
+            offer_vault: self.offer_vault(),
+            buyer_token_account: self.quote_account(self.buyer),
+            seller_token_account: self.quote_account(self.seller),
+            buyer_credit_account: self.credit_account(self.buyer),
+            fee_vault: self.vault(b"fee_vault", self.market.quote_mint),
+            buyer_zone_member: self.zone_member(self.buyer),
+            buyer_zone: TestAccount::none(),
+            zone_link: TestAccount::none(),
+            reference_price: self.reference_price(),
+            buyer_link: self.wallet_link(self.buyer),
+            seller_link: self.wallet_link(self.seller),
+            accepted_mint: TestAccount::none(),
+            accepted_mint_fee_vault: TestAccount::none(),
+            buyer: TestAccount::signer(self.buyer),
+        }
+    }
+
+    fn settle_delivery(&self) -> SettleDeliveryAccounts {
+        let (trade_escrow, _) = market_pda(&[b"trade_escrow", self.trade_key.as_ref()]);
+        SettleDeliveryAccounts {
+            trade: self.trade(),
+            offer: self.offer(),
+            market: self.market(),
+            meter: self.meter(),
+            vault_authority: TestAccount::wallet(self.vault_authority()),
+            offer_vault: self.offer_vault(),
+            trade_escrow: TestAccount::token_account(
+                trade_escrow,
+                self.market.quote_mint,
+                self.vault_authority(),
+            ),
+            buyer_credit_account: self.credit_account(self.buyer),
+            buyer_token_account: self.quote_account(self.buyer),
+            seller_credit_account: self.credit_account(self.seller),
+            seller_token_account: self.quote_account(self.seller),
+            fee_vault: self.vault(b"fee_vault", self.market.quote_mint),
+            collateral_vault: self.vault(b"collateral_vault", self.market.quote_mint),
+            insurance_vault: self.vault(b"insurance_vault", self.market.quote_mint),
+            settler: TestAccount::signer(Pubkey::new_unique()),
+        }
+    }
+
+    fn create_offer(&self) -> CreateOfferAccounts {
+        let market = self.market_key;
+        let (seller_account_key, seller_account_bump) =
+            market_pda(&[b"seller", market.as_ref(), self.seller.as_ref()]);
+        let seller_account = SellerAccount {
+            market,
+            seller: self.seller,
+            offer_nonce: 1,
+            bump: seller_account_bump,
+        };
+        let (offer, _) = market_pda(&[
+            b"offer",
+            market.as_ref(),
+            self.seller.as_ref(),
+            &seller_account.offer_nonce.to_le_bytes(),
+        ]);
+        let (offer_vault, _) = market_pda(&[b"offer_vault", offer.as_ref()]);
+        CreateOfferAccounts {
+            market: self.market(),
+            seller: TestAccount::signer(self.seller),
+            seller_account: TestAccount::anchor(seller_account_key, &seller_account),
+            offer: TestAccount::uninitialized(offer, 8 + EnergyOffer::INIT_SPACE),
+            vault_authority: TestAccount::wallet(self.vault_authority()),
+            credit_mint: TestAccount::mint(self.market.credit_mint),
+            offer_vault: TestAccount::uninitialized(offer_vault, spl_token::state::Account::LEN),
+            seller_credit_account: self.credit_account(self.seller),
+            meter: self.meter(),
+            seller_zone_member: self.zone_member(self.seller),
+            reference_price: self.reference_price(),
+            seller_token_account: self.quote_account(self.seller),
+            collateral_vault: self.vault(b"collateral_vault", self.market.quote_mint),
+        }
+    }
+
+    fn submit_curtailment_bid(&self) -> SubmitCurtailmentBidAccounts {
+        let consumer = Pubkey::new_unique();
+        let meter = EnergyMeter {
+            meter_type: MeterType::Consumption,
+            owner: consumer,
+            ..self.meter.clone()
+        };
+        let event_key = Pubkey::new_unique();
+        let event = DemandResponseEvent {
+            market: self.market_key,
+            operator: Pubkey::new_unique(),
+            event_id: 1,
+            zone: Pubkey::new_unique(),
+            window_start: NOW + 86_400,
+            window_end: NOW + 90_000,
+            target_reduction: 500,
+            max_price_per_kwh: 200,
+            clearing_price: 0,
+            accepted_reduction: 0,
+            delivered_reduction: 0,
+            total_paid: 0,
+            bid_count: 0,
+            status: DemandResponseStatus::Open,
+            bump: 255,
+            vault_bump: 255,
+        };
+        let (bid, _) =
+            market_pda(&[b"curtailment_bid", event_key.as_ref(), self.meter_key.as_ref()]);
+        SubmitCurtailmentBidAccounts {
+            market: self.market(),
+            event: TestAccount::anchor(event_key, &event),
+            meter: TestAccount::anchor(self.meter_key, &meter),
+            meter_zone_member: self.zone_member(self.meter_key),
+            bid: TestAccount::uninitialized(bid, 8 + CurtailmentBid::INIT_SPACE),
+            consumer: TestAccount::signer(consumer),
+        }
+    }
+}
+
+struct ExecuteTradeAccounts {
+    offer: TestAccount,
+    market: TestAccount,
+    vault_authority: TestAccount,
+    offer_vault: TestAccount,
+    buyer_token_account: TestAccount,
+    seller_token_account: TestAccount,
+    buyer_credit_account: TestAccount,
+    fee_vault: TestAccount,
+    buyer_zone_member: TestAccount,
+    buyer_zone: TestAccount,
+    zone_link: TestAccount,
+    reference_price: TestAccount,
+    buyer_link: TestAccount,
+    seller_link: TestAccount,
+    accepted_mint: TestAccount,
+    accepted_mint_fee_vault: TestAccount,
+    buyer: TestAccount,
+}
+
+impl ExecuteTradeAccounts {
+    fn check(self) -> Result<()> {
+        let mut accounts = [
+            self.offer,
+            self.market,
+            self.vault_authority,
+            self.offer_vault,
+            self.buyer_token_account,
+            self.seller_token_account,
+            self.buyer_credit_account,
+            self.fee_vault,
+            self.buyer_zone_member,
+            self.buyer_zone,
+            self.zone_link,
+            self.reference_price,
+            self.buyer_link,
+            self.seller_link,
+            self.accepted_mint,
+            self.accepted_mint_fee_vault,
+            self.buyer,
+            TestAccount::program(spl_token::ID),
+        ];
+        try_accounts::<ExecuteTrade>(&mut accounts)
+    }
+}
+
+struct SettleDeliveryAccounts {
+    trade: TestAccount,
+    offer: TestAccount,
+    market: TestAccount,
+    meter: TestAccount,
+    vault_authority: TestAccount,
+    offer_vault: TestAccount,
+    trade_escrow: TestAccount,
+    buyer_credit_account: TestAccount,
+    buyer_token_account: TestAccount,
+    seller_credit_account: TestAccount,
+    seller_token_account: TestAccount,
+    fee_vault: TestAccount,
+    collateral_vault: TestAccount,
+    insurance_vault: TestAccount,
+    settler: TestAccount,
+}
+
+impl SettleDeliveryAccounts {
+    fn check(self) -> Result<()> {
+        try_accounts::<SettleDelivery>(&mut self.accounts())
+    }
+
+    /// Run `settle_delivery` with no readings, returning the settled trade and the
+    /// buyer's quote balance
+    fn settle_without_readings(self) -> Result<(EnergyTrade, u64)> {
+        install_runtime();
+        let mut accounts = self.accounts();
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(TestAccount::info).collect();
+        let mut settle = SettleDelivery::try_accounts(
+            &energy_market::ID,
+            &mut &infos[..],
+            &[],
+            &mut BTreeMap::new(),
+            &mut BTreeSet::new(),
+        )?;
+        energy_market::energy_market::settle_delivery(Context::new(
+            &energy_market::ID,
+            &mut settle,
+            &[],
+            BTreeMap::new(),
+        ))?;
+        let buyer_balance = spl_token::state::Account::unpack(&infos[8].data.borrow())?.amount;
+        Ok(((*settle.trade).clone(), buyer_balance))
+    }
+
+    fn accounts(self) -> [TestAccount; 16] {
+        [
+            self.trade,
+            self.offer,
+            self.market,
+            self.meter,
+            self.vault_authority,
+            self.offer_vault,
+            self.trade_escrow,
+            self.buyer_credit_account,
+            self.buyer_token_account,
+            self.seller_credit_account,
+            self.seller_token_account,
+            self.fee_vault,
+            self.collateral_vault,
+            self.insurance_vault,
+            self.settler,
+            TestAccount::program(spl_token::ID),
+        ]
+    }
+}
+
+struct CreateOfferAccounts {
+    market: TestAccount,
+    seller: TestAccount,
+    seller_account: TestAccount,
+    offer: TestAccount,
+    vault_authority: TestAccount,
+    credit_mint: TestAccount,
+    offer_vault: TestAccount,
+    seller_credit_account: TestAccount,
+    meter: TestAccount,
+    seller_zone_member: TestAccount,
+    reference_price: TestAccount,
+    seller_token_account: TestAccount,
+    collateral_vault: TestAccount,
+}
+
+impl CreateOfferAccounts {
+    fn check(self) -> Result<()> {
+        let mut accounts = [
+            self.market,
+            self.seller,
+            self.seller_account,
+            self.offer,
+            self.vault_authority,
+            self.credit_mint,
+            self.offer_vault,
+            self.seller_credit_account,
+            self.meter,
+            self.seller_zone_member,
+            self.reference_price,
+            self.seller_token_account,
+            self.collateral_vault,
+            TestAccount::program(spl_token::ID),
+            TestAccount::program(system_program::ID),
+            TestAccount::rent_sysvar(),
+        ];
+        try_accounts::<CreateOffer>(&mut accounts)
+    }
+}
+
+struct SubmitCurtailmentBidAccounts {
+    market: TestAccount,
+    event: TestAccount,
+    meter: TestAccount,
+    meter_zone_member: TestAccount,
+    bid: TestAccount,
+    consumer: TestAccount,
+}
+
+impl SubmitCurtailmentBidAccounts {
+    fn check(self) -> Result<()> {
+        let mut accounts = [
+            self.market,
+            self.event,
+            self.meter,
+            self.meter_zone_member,
+            self.bid,
+            self.consumer,
+            TestAccount::program(system_program::ID),
+        ];
+        try_accounts::<SubmitCurtailmentBid>(&mut accounts)
+    }
+}
+
+#[test]
+fn execute_trade_accepts_matching_accounts() {
+    Exchange::new().execute_trade().check().unwrap();
+}
+
+#[test]
+fn execute_trade_rejects_offer_of_another_market() {
+    let exchange = Exchange::new();
+    let offer = EnergyOffer { market: Pubkey::new_unique(), ..exchange.offer.clone() };
+    let accounts = ExecuteTradeAccounts {
+        offer: TestAccount::anchor(exchange.offer_key, &offer),
+        ..exchange.execute_trade()
+    };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn execute_trade_rejects_seller_account_of_another_owner() {
+    let exchange = Exchange::new();
+    let accounts = ExecuteTradeAccounts {
+        seller_token_account: exchange.quote_account(Pubkey::new_unique()),
+        ..exchange.execute_trade()
+    };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintTokenOwner);
+}
+
+#[test]
+fn execute_trade_rejects_seller_account_in_another_quote_mint() {
+    let exchange = Exchange::new();
+    let seller_token_account =
+        TestAccount::token_account(Pubkey::new_unique(), Pubkey::new_unique(), exchange.seller);
+    let accounts = ExecuteTradeAccounts { seller_token_account, ..exchange.execute_trade() };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintTokenMint);
+}
+
+#[test]
+fn execute_trade_accepts_proceeds_account_of_pool_offer() {
+    let exchange = Exchange::new();
+    let pool_quote_vault = Pubkey::new_unique();
+    let offer = EnergyOffer { proceeds_account: pool_quote_vault, ..exchange.offer.clone() };
+    let accounts = ExecuteTradeAccounts {
+        offer: TestAccount::anchor(exchange.offer_key, &offer),
+        seller_token_account: TestAccount::token_account(
+            pool_quote_vault,
+            exchange.market.quote_mint,
+            exchange.seller,
+        ),
+        ..exchange.execute_trade()
+    };
+    accounts.check().unwrap();
+}
+
+#[test]
+fn execute_trade_rejects_pool_offer_paid_outside_its_proceeds_account() {
+    let exchange = Exchange::new();
+    let offer = EnergyOffer { proceeds_account: Pubkey::new_unique(), ..exchange.offer.clone() };
+    let accounts = ExecuteTradeAccounts {
+        offer: TestAccount::anchor(exchange.offer_key, &offer),
+        ..exchange.execute_trade()
+    };
+    assert_rejected(accounts.check(), energy_market::ErrorCode::ProceedsAccountMismatch);
+}
+
+#[test]
+fn execute_trade_rejects_buyer_account_in_another_quote_mint() {
+    let exchange = Exchange::new();
+    let buyer_token_account = exchange.credit_account(exchange.buyer);
+    let accounts = ExecuteTradeAccounts { buyer_token_account, ..exchange.execute_trade() };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintTokenMint);
+}
+
+#[test]
+fn execute_trade_rejects_credit_account_of_another_mint() {
+    let exchange = Exchange::new();
+    let buyer_credit_account = exchange.quote_account(exchange.buyer);
+    let accounts = ExecuteTradeAccounts { buyer_credit_account, ..exchange.execute_trade() };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintTokenMint);
+}
+
+#[test]
+fn execute_trade_rejects_fee_vault_off_its_address() {
+    let exchange = Exchange::new();
+    let accounts = ExecuteTradeAccounts {
+        fee_vault: exchange.quote_account(exchange.vault_authority()),
+        ..exchange.execute_trade()
+    };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn execute_trade_rejects_reference_price_of_another_oracle() {
+    let exchange = Exchange::new();
+    let accounts = ExecuteTradeAccounts {
+        reference_price: exchange.foreign_reference_price(),
+        ..exchange.execute_trade()
+    };
+    assert_rejected(accounts.check(), energy_market::ErrorCode::OracleMismatch);
+}
+
+#[test]
+fn execute_trade_rejects_accepted_mint_of_another_market() {
+    let exchange = Exchange::new();
+    let accepted_mint = AcceptedMint {
+        market: Pubkey::new_unique(),
+        mint: Pubkey::new_unique(),
+        decimals: 6,
+        quote_decimals: 6,
+        price_scale: 1_000_000,
+        fee_vault: Pubkey::new_unique(),
+        is_active: true,
+        total_fees_collected: 0,
+        total_congestion_charges: 0,
+        bump: 255,
+    };
+    let accounts = ExecuteTradeAccounts {
+        buyer_token_account: TestAccount::token_account(
+            Pubkey::new_unique(),
+            accepted_mint.mint,
+            exchange.buyer,
+        ),
+        seller_token_account: TestAccount::token_account(
+            Pubkey::new_unique(),
+            accepted_mint.mint,
+            exchange.seller,
+        ),
+        accepted_mint: TestAccount::anchor(Pubkey::new_unique(), &accepted_mint),
+        ..exchange.execute_trade()
+    };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn execute_trade_rejects_buyer_link_off_its_address() {
+    let exchange = Exchange::new();
+    let accounts = ExecuteTradeAccounts {
+        buyer_link: exchange.wallet_link(Pubkey::new_unique()),
+        ..exchange.execute_trade()
+    };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn settle_delivery_accepts_matching_accounts() {
+    Exchange::new().settle_delivery().check().unwrap();
+}
+
+#[test]
+fn settle_delivery_rejects_meter_other_than_the_trades() {
+    let exchange = Exchange::new();
+    let meter = TestAccount::anchor(Pubkey::new_unique(), &exchange.meter);
+    let accounts = SettleDeliveryAccounts { meter, ..exchange.settle_delivery() };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn settle_delivery_rejects_seller_account_of_another_owner() {
+    let exchange = Exchange::new();
+    let accounts = SettleDeliveryAccounts {
+        seller_token_account: exchange.quote_account(Pubkey::new_unique()),
+        ..exchange.settle_delivery()
+    };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintTokenOwner);
+}
+
+#[test]
+fn settle_delivery_rejects_buyer_account_in_another_quote_mint() {
+    let exchange = Exchange::new();
+    let accounts = SettleDeliveryAccounts {
+        buyer_token_account: exchange.credit_account(exchange.buyer),
+        ..exchange.settle_delivery()
+    };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintTokenMint);
+}
+
+#[test]
+fn settle_delivery_rejects_insurance_vault_off_its_address() {
+    let exchange = Exchange::new();
+    let accounts = SettleDeliveryAccounts {
+        insurance_vault: exchange.vault(b"fee_vault", exchange.market.quote_mint),
+        ..exchange.settle_delivery()
+    };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn create_offer_accepts_matching_accounts() {
+    Exchange::new().create_offer().check().unwrap();
+}
+
+#[test]
+fn create_offer_rejects_meter_of_another_oracle() {
+    let exchange = Exchange::new();
+    let accounts = CreateOfferAccounts {
+        meter: exchange.foreign_meter(),
+        ..exchange.create_offer()
+    };
+    assert_rejected(accounts.check(), energy_market::ErrorCode::OracleMismatch);
+}
+
+#[test]
+fn create_offer_rejects_reference_price_of_another_oracle() {
+    let exchange = Exchange::new();
+    let accounts = CreateOfferAccounts {
+        reference_price: exchange.foreign_reference_price(),
+        ..exchange.create_offer()
+    };
+    assert_rejected(accounts.check(), energy_market::ErrorCode::OracleMismatch);
+}
+
+#[test]
+fn create_offer_rejects_seller_account_of_another_owner() {
+    let exchange = Exchange::new();
+    let accounts = CreateOfferAccounts {
+        seller_token_account: exchange.quote_account(Pubkey::new_unique()),
+        ..exchange.create_offer()
+    };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintTokenOwner);
+}
+
+#[test]
+fn create_offer_rejects_seller_account_in_another_quote_mint() {
+    let exchange = Exchange::new();
+    let accounts = CreateOfferAccounts {
+        seller_token_account: exchange.credit_account(exchange.seller),
+        ..exchange.create_offer()
+    };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintTokenMint);
+}
+
+#[test]
+fn create_offer_rejects_credit_mint_other_than_the_markets() {
+    let accounts = CreateOfferAccounts {
+        credit_mint: TestAccount::mint(Pubkey::new_unique()),
+        ..Exchange::new().create_offer()
+    };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintAddress);
+}
+
+#[test]
+fn submit_curtailment_bid_accepts_matching_accounts() {
+    Exchange::new().submit_curtailment_bid().check().unwrap();
+}
+
+#[test]
+fn submit_curtailment_bid_rejects_meter_of_another_oracle() {
+    let exchange = Exchange::new();
+    let mut accounts = exchange.submit_curtailment_bid();
+    let mut meter = EnergyMeter::try_deserialize(&mut &accounts.meter.data[..]).unwrap();
+    meter.oracle = Pubkey::new_unique();
+    accounts.meter = TestAccount::anchor(exchange.meter_key, &meter);
+    assert_rejected(accounts.check(), energy_market::ErrorCode::OracleMismatch);
+}
+
+#[test]
+fn submit_curtailment_bid_rejects_meter_of_another_owner() {
+    let exchange = Exchange::new();
+    let accounts = SubmitCurtailmentBidAccounts {
+        meter: exchange.meter(),
+        ..exchange.submit_curtailment_bid()
+    };
+    assert_rejected(accounts.check(), energy_market::ErrorCode::Unauthorized);
+}
+
+#[test]
+fn submit_curtailment_bid_rejects_zone_member_of_another_meter() {
+    let exchange = Exchange::new();
+    let accounts = SubmitCurtailmentBidAccounts {
+        meter_zone_member: exchange.zone_member(Pubkey::new_unique()),
+        ..exchange.submit_curtailment_bid()
+    };
+    assert_rejected(accounts.check(), ErrorCode::ConstraintSeeds);
+}
+
+/// Reading covering the last hour of the exchange's meter, which produced 600 kWh in it
+fn production_reading(exchange: &Exchange, meter: Pubkey) -> TestAccount {
+    let reading = MeterReading {
+        meter_id: exchange.meter.meter_id.clone(),
+        meter,
+        submitter: Pubkey::new_unique(),
+        reading_value: 600,
+        reading_type: ReadingType::Production,
+        timestamp: NOW,
+        signature: vec![1; 64],
+        is_verified: true,
+        period_start: NOW - 3_600,
+        production_register: exchange.meter.production_register,
+        consumption_register: 0,
+    };
+    TestAccount::anchor(Pubkey::new_unique(), &reading)
+}
+
+fn register_at(exchange: &Exchange, readings: &mut [TestAccount], time: i64) -> Result<u64> {
+    let mut meter = exchange.meter();
+    let meter_info = meter.info();
+    let meter = Account::<EnergyMeter>::try_from(&meter_info)?;
+    let infos: Vec<AccountInfo> = readings.iter_mut().map(TestAccount::info).collect();
+    meter_register_at(&meter, ReadingType::Production, time, &mut infos.iter())
+}
+
+#[test]
+fn meter_register_at_reads_the_covering_reading() {
+    let exchange = Exchange::new();
+    let mut readings = [production_reading(&exchange, exchange.meter_key)];
+    assert_eq!(register_at(&exchange, &mut readings, NOW - 1_800).unwrap(), 1_300);
+}
+
+#[test]
+fn meter_register_at_rejects_reading_of_another_meter() {
+    let exchange = Exchange::new();
+    let mut readings = [production_reading(&exchange, Pubkey::new_unique())];
+    let result = register_at(&exchange, &mut readings, NOW - 1_800);
+    assert_rejected(result, energy_market::ErrorCode::ReadingMeterMismatch);
+}
+
+#[test]
+fn meter_register_at_rejects_reading_not_owned_by_the_oracle() {
+    let exchange = Exchange::new();
+    let mut reading = production_reading(&exchange, exchange.meter_key);
+    reading.owner = energy_market::ID;
+    let result = register_at(&exchange, &mut [reading], NOW - 1_800);
+    assert_rejected(result, ErrorCode::AccountOwnedByWrongProgram);
+}
+
+#[test]
+fn meter_register_at_rejects_missing_reading() {
+    let exchange = Exchange::new();
+    let result = register_at(&exchange, &mut [], NOW - 1_800);
+    assert_rejected(result, energy_market::ErrorCode::MissingReading);
+}
+
+/// Exchange whose trade was delivered a month ago and never settled
+fn overdue_exchange() -> Exchange {
+    let mut exchange = Exchange::new();
+    exchange.meter.registered_at = NOW - 60 * 86_400;
+    exchange.offer.delivery_start = NOW - 31 * 86_400;
+    exchange.offer.delivery_end = exchange.offer.delivery_start + 3_600;
+    exchange.trade.delivery_start = exchange.offer.delivery_start;
+    exchange.trade.delivery_end = exchange.offer.delivery_end;
+    exchange
+}
+
+fn funded_settlement(exchange: &Exchange) -> SettleDeliveryAccounts {
+    let accounts = exchange.settle_delivery();
+    SettleDeliveryAccounts {
+        offer_vault: accounts.offer_vault.funded(exchange.trade.energy_amount),
+        trade_escrow: accounts.trade_escrow.funded(exchange.trade.total_cost),
+        ..accounts
+    }
+}
+
+#[test]
+fn settle_delivery_settles_overdue_trade_as_undelivered_without_archived_readings() {
+    let exchange = overdue_exchange();
+    let (trade, buyer_balance) = funded_settlement(&exchange).settle_without_readings().unwrap();
+    assert!(trade.is_settled);
+    assert_eq!(trade.delivered_amount, 0);
+    assert_eq!(buyer_balance, exchange.trade.total_cost);
+}
+
+#[test]
+fn settle_delivery_needs_readings_until_they_may_be_archived() {
+    let exchange = Exchange::new();
+    let accounts = SettleDeliveryAccounts {
+        settler: TestAccount::signer(exchange.seller),
+        ..funded_settlement(&exchange)
+    };
+    let result = accounts.settle_without_readings();
+    assert_rejected(result, energy_market::ErrorCode::MissingReading);
+}
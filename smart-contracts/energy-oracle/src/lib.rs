@@ -2,6 +2,9 @@ use anchor_lang::prelude::*;
 
 declare_id!("EnergyOracle111111111111111111111111111111");
 
+/// Seconds a meter reading is kept before its account can be closed
+pub const READING_ARCHIVE_SECS: i64 = 30 * 86_400;
+
 #[program]
 pub mod energy_oracle {
     use super::*;
@@ -10,6 +13,7 @@ pub mod energy_oracle {
     pub fn initialize_oracle(ctx: Context<InitializeOracle>) -> Result<()> {
         let oracle = &mut ctx.accounts.oracle;
         oracle.authority = ctx.accounts.authority.key();
+        oracle.pending_authority = Pubkey::default();
         oracle.total_meters = 0;
         oracle.total_readings = 0;
         oracle.is_active = true;
@@ -36,10 +40,13 @@ pub mod energy_oracle {
         meter.meter_type = meter_type;
         meter.location = location.clone();
         meter.owner = owner;
+        meter.oracle = oracle.key();
         meter.is_authorized = true;
         meter.registered_at = Clock::get()?.unix_timestamp;
         meter.last_reading_at = 0;
         meter.total_readings = 0;
+        meter.production_register = 0;
+        meter.consumption_register = 0;
 
         oracle.total_meters += 1;
 
@@ -56,6 +63,10 @@ pub mod energy_oracle {
     }
 
     /// Submit energy meter reading
+    ///
+    /// Readings are accepted only from gateways the oracle authority has added,
+    /// which forward the meters' signed telemetry; meter owners cannot report their
+    /// own readings.
     pub fn submit_reading(
         ctx: Context<SubmitReading>,
         meter_id: String,
@@ -85,11 +96,37 @@ pub mod energy_oracle {
 
         // Store the reading
         reading.meter_id = meter_id.clone();
+        reading.meter = meter.key();
+        reading.submitter = ctx.accounts.submitter.key();
         reading.reading_value = reading_value;
         reading.reading_type = reading_type;
         reading.timestamp = current_time;
         reading.signature = signature;
-        reading.is_verified = true; // In production, implement proper signature verification
+        // The meter's signature was checked by the authorised gateway that submitted it
+        reading.is_verified = true;
+
+        // The reading covers the period since the meter's previous reading
+        reading.period_start = if meter.last_reading_at == 0 {
+            meter.registered_at
+        } else {
+            meter.last_reading_at
+        };
+        match reading_type {
+            ReadingType::Production => {
+                meter.production_register = meter
+                    .production_register
+                    .checked_add(reading_value)
+                    .ok_or(ErrorCode::Overflow)?;
+            }
+            ReadingType::Consumption => {
+                meter.consumption_register = meter
+                    .consumption_register
+                    .checked_add(reading_value)
+                    .ok_or(ErrorCode::Overflow)?;
+            }
+        }
+        reading.production_register = meter.production_register;
+        reading.consumption_register = meter.consumption_register;
 
         // Update meter stats
         meter.last_reading_at = current_time;
@@ -152,6 +189,59 @@ pub mod energy_oracle {
         })
     }
 
+    /// Publish a new reference price feed, such as a grid tariff (authority only)
+    pub fn create_reference_price(
+        ctx: Context<CreateReferencePrice>,
+        name: String,
+        price_per_kwh: u64,
+    ) -> Result<()> {
+        require!(!name.is_empty() && name.len() <= 32, ErrorCode::InvalidPriceFeed);
+        require!(price_per_kwh > 0, ErrorCode::InvalidPrice);
+
+        let oracle = &ctx.accounts.oracle;
+        require!(oracle.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+
+        let reference_price = &mut ctx.accounts.reference_price;
+        reference_price.oracle = oracle.key();
+        reference_price.name = name.clone();
+        reference_price.price_per_kwh = price_per_kwh;
+        reference_price.updated_at = Clock::get()?.unix_timestamp;
+        reference_price.bump = *ctx.bumps.get("reference_price").unwrap();
+
+        emit!(ReferencePriceUpdatedEvent {
+            name: name.clone(),
+            price_per_kwh,
+            timestamp: reference_price.updated_at,
+        });
+
+        msg!("Created reference price {}: {}", name, price_per_kwh);
+        Ok(())
+    }
+
+    /// Update a reference price feed (authority only)
+    pub fn update_reference_price(
+        ctx: Context<UpdateReferencePrice>,
+        price_per_kwh: u64,
+    ) -> Result<()> {
+        require!(price_per_kwh > 0, ErrorCode::InvalidPrice);
+
+        let oracle = &ctx.accounts.oracle;
+        require!(oracle.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+
+        let reference_price = &mut ctx.accounts.reference_price;
+        reference_price.price_per_kwh = price_per_kwh;
+        reference_price.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(ReferencePriceUpdatedEvent {
+            name: reference_price.name.clone(),
+            price_per_kwh,
+            timestamp: reference_price.updated_at,
+        });
+
+        msg!("Updated reference price {}: {}", reference_price.name, price_per_kwh);
+        Ok(())
+    }
+
     /// Update oracle settings
     pub fn update_oracle_settings(
         ctx: Context<UpdateOracleSettings>,
@@ -165,6 +255,153 @@ pub mod energy_oracle {
         msg!("Oracle settings updated: active = {}", is_active);
         Ok(())
     }
+
+    /// Propose a new oracle authority
+    ///
+    /// The transfer completes once the proposed authority calls `accept_authority`.
+    /// Proposing the default pubkey withdraws a pending proposal.
+    pub fn propose_authority(
+        ctx: Context<UpdateOracleSettings>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        let oracle = &mut ctx.accounts.oracle;
+        require!(oracle.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+
+        oracle.pending_authority = new_authority;
+
+        emit!(AuthorityProposedEvent {
+            oracle: oracle.key(),
+            authority: oracle.authority,
+            pending_authority: new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Oracle authority proposed: {}", new_authority);
+        Ok(())
+    }
+
+    /// Accept a proposed oracle authority transfer
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let oracle = &mut ctx.accounts.oracle;
+        let new_authority = ctx.accounts.new_authority.key();
+        require!(
+            oracle.pending_authority != Pubkey::default() && oracle.pending_authority == new_authority,
+            ErrorCode::Unauthorized
+        );
+
+        let previous_authority = oracle.authority;
+        oracle.authority = new_authority;
+        oracle.pending_authority = Pubkey::default();
+
+        emit!(AuthorityTransferredEvent {
+            oracle: oracle.key(),
+            previous_authority,
+            new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Oracle authority transferred to {}", new_authority);
+        Ok(())
+    }
+
+    /// Authorize a gateway to submit meter readings (authority only)
+    pub fn add_gateway(ctx: Context<AddGateway>, gateway: Pubkey) -> Result<()> {
+        let gateway_account = &mut ctx.accounts.gateway_account;
+        gateway_account.oracle = ctx.accounts.oracle.key();
+        gateway_account.gateway = gateway;
+        gateway_account.added_at = Clock::get()?.unix_timestamp;
+        gateway_account.bump = *ctx.bumps.get("gateway_account").unwrap();
+
+        emit!(GatewayUpdatedEvent {
+            oracle: gateway_account.oracle,
+            gateway,
+            is_authorized: true,
+            timestamp: gateway_account.added_at,
+        });
+
+        msg!("Added gateway {}", gateway);
+        Ok(())
+    }
+
+    /// Revoke a gateway's authorization, returning its rent to the authority
+    pub fn remove_gateway(ctx: Context<RemoveGateway>) -> Result<()> {
+        let gateway_account = &ctx.accounts.gateway_account;
+
+        emit!(GatewayUpdatedEvent {
+            oracle: gateway_account.oracle,
+            gateway: gateway_account.gateway,
+            is_authorized: false,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Removed gateway {}", gateway_account.gateway);
+        Ok(())
+    }
+
+    /// Close a meter reading past the archival period, returning its rent to the submitter
+    pub fn close_reading(ctx: Context<CloseReading>) -> Result<()> {
+        let reading = &ctx.accounts.reading;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now - reading.timestamp >= READING_ARCHIVE_SECS,
+            ErrorCode::ReadingNotArchived
+        );
+
+        emit!(ReadingClosedEvent {
+            meter_id: reading.meter_id.clone(),
+            meter: reading.meter,
+            reading: reading.key(),
+            reading_value: reading.reading_value,
+            reading_type: reading.reading_type,
+            reading_timestamp: reading.timestamp,
+            timestamp: now,
+        });
+
+        msg!("Closed reading for meter {}", reading.meter_id);
+        Ok(())
+    }
+
+    /// Link another wallet to a meter owner, so trades between them can be flagged
+    ///
+    /// Both the meter owner and the wallet sign. A wallet links to one owner at a
+    /// time, and an owner cannot itself be a linked wallet.
+    pub fn link_wallet(ctx: Context<LinkWallet>) -> Result<()> {
+        let owner = ctx.accounts.owner.key();
+        let wallet = ctx.accounts.wallet.key();
+        require!(wallet != owner, ErrorCode::InvalidWalletLink);
+        require!(ctx.accounts.owner_link.data_is_empty(), ErrorCode::InvalidWalletLink);
+
+        let wallet_link = &mut ctx.accounts.wallet_link;
+        wallet_link.owner = owner;
+        wallet_link.wallet = wallet;
+        wallet_link.meter = ctx.accounts.meter.key();
+        wallet_link.linked_at = Clock::get()?.unix_timestamp;
+        wallet_link.bump = *ctx.bumps.get("wallet_link").unwrap();
+
+        emit!(WalletLinkedEvent {
+            owner,
+            wallet,
+            meter: wallet_link.meter,
+            timestamp: wallet_link.linked_at,
+        });
+
+        msg!("Linked wallet {} to {}", wallet, owner);
+        Ok(())
+    }
+
+    /// Remove a wallet link, signed by both the owner and the wallet
+    pub fn unlink_wallet(ctx: Context<UnlinkWallet>) -> Result<()> {
+        let wallet_link = &ctx.accounts.wallet_link;
+
+        emit!(WalletUnlinkedEvent {
+            owner: wallet_link.owner,
+            wallet: wallet_link.wallet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Unlinked wallet {} from {}", wallet_link.wallet, wallet_link.owner);
+        Ok(())
+    }
 }
 
 #[derive(Accounts)]
@@ -191,7 +428,7 @@ pub struct RegisterMeter<'info> {
     )]
     pub meter: Account<'info, EnergyMeter>,
     
-    #[account(mut)]
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
     pub oracle: Account<'info, Oracle>,
     
     #[account(mut)]
@@ -209,12 +446,20 @@ pub struct SubmitReading<'info> {
     )]
     pub reading: Account<'info, MeterReading>,
     
-    #[account(mut)]
+    #[account(mut, has_one = oracle)]
     pub meter: Account<'info, EnergyMeter>,
     
     #[account(mut)]
     pub oracle: Account<'info, Oracle>,
     
+    /// Submitter's gateway authorization
+    #[account(
+        has_one = oracle,
+        seeds = [b"gateway", oracle.key().as_ref(), submitter.key().as_ref()],
+        bump = gateway.bump
+    )]
+    pub gateway: Account<'info, Gateway>,
+    
     #[account(mut)]
     pub submitter: Signer<'info>,
     
@@ -222,10 +467,59 @@ pub struct SubmitReading<'info> {
 }
 
 #[derive(Accounts)]
-pub struct UpdateMeterAuth<'info> {
+#[instruction(gateway: Pubkey)]
+pub struct AddGateway<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Gateway::INIT_SPACE,
+        seeds = [b"gateway", oracle.key().as_ref(), gateway.as_ref()],
+        bump
+    )]
+    pub gateway_account: Account<'info, Gateway>,
+    
+    #[account(has_one = authority @ ErrorCode::Unauthorized)]
+    pub oracle: Account<'info, Oracle>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveGateway<'info> {
+    #[account(
+        mut,
+        close = authority,
+        has_one = oracle,
+        seeds = [b"gateway", oracle.key().as_ref(), gateway_account.gateway.as_ref()],
+        bump = gateway_account.bump
+    )]
+    pub gateway_account: Account<'info, Gateway>,
+    
+    #[account(has_one = authority @ ErrorCode::Unauthorized)]
+    pub oracle: Account<'info, Oracle>,
+    
     #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseReading<'info> {
+    #[account(mut, has_one = submitter, close = submitter)]
+    pub reading: Account<'info, MeterReading>,
+    
+    #[account(mut)]
+    pub submitter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMeterAuth<'info> {
+    #[account(mut, has_one = oracle)]
     pub meter: Account<'info, EnergyMeter>,
     
+    #[account(has_one = authority @ ErrorCode::Unauthorized)]
     pub oracle: Account<'info, Oracle>,
     
     pub authority: Signer<'info>,
@@ -237,17 +531,111 @@ pub struct GetLatestReading<'info> {
 }
 
 #[derive(Accounts)]
-pub struct UpdateOracleSettings<'info> {
+#[instruction(name: String)]
+pub struct CreateReferencePrice<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ReferencePrice::INIT_SPACE,
+        seeds = [b"reference_price", oracle.key().as_ref(), name.as_bytes()],
+        bump
+    )]
+    pub reference_price: Account<'info, ReferencePrice>,
+    
+    #[account(has_one = authority @ ErrorCode::Unauthorized)]
+    pub oracle: Account<'info, Oracle>,
+    
     #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateReferencePrice<'info> {
+    #[account(
+        mut,
+        has_one = oracle,
+        seeds = [b"reference_price", oracle.key().as_ref(), reference_price.name.as_bytes()],
+        bump = reference_price.bump
+    )]
+    pub reference_price: Account<'info, ReferencePrice>,
+    
+    #[account(has_one = authority @ ErrorCode::Unauthorized)]
+    pub oracle: Account<'info, Oracle>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateOracleSettings<'info> {
+    #[account(mut, has_one = authority @ ErrorCode::Unauthorized)]
     pub oracle: Account<'info, Oracle>,
     
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(mut)]
+    pub oracle: Account<'info, Oracle>,
+    
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct LinkWallet<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + WalletLink::INIT_SPACE,
+        seeds = [b"wallet_link", wallet.key().as_ref()],
+        bump
+    )]
+    pub wallet_link: Account<'info, WalletLink>,
+    
+    /// CHECK: the owner's own link address, which must be unused
+    #[account(
+        seeds = [b"wallet_link", owner.key().as_ref()],
+        bump
+    )]
+    pub owner_link: UncheckedAccount<'info>,
+    
+    #[account(has_one = owner)]
+    pub meter: Account<'info, EnergyMeter>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    pub wallet: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnlinkWallet<'info> {
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner,
+        has_one = wallet,
+        seeds = [b"wallet_link", wallet.key().as_ref()],
+        bump = wallet_link.bump
+    )]
+    pub wallet_link: Account<'info, WalletLink>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    pub wallet: Signer<'info>,
+}
+
 #[account]
 #[derive(InitSpace)]
 pub struct Oracle {
     pub authority: Pubkey,
+    /// Authority proposed by `propose_authority`, awaiting acceptance
+    pub pending_authority: Pubkey,
     pub total_meters: u64,
     pub total_readings: u64,
     pub is_active: bool,
@@ -262,10 +650,26 @@ pub struct EnergyMeter {
     #[max_len(128)]
     pub location: String,
     pub owner: Pubkey,
+    /// Oracle the meter was registered with
+    pub oracle: Pubkey,
     pub is_authorized: bool,
     pub registered_at: i64,
     pub last_reading_at: i64,
     pub total_readings: u64,
+    /// Cumulative production reported by the meter
+    pub production_register: u64,
+    /// Cumulative consumption reported by the meter
+    pub consumption_register: u64,
+}
+
+impl EnergyMeter {
+    /// Cumulative register of the given reading type
+    pub fn register(&self, reading_type: ReadingType) -> u64 {
+        match reading_type {
+            ReadingType::Production => self.production_register,
+            ReadingType::Consumption => self.consumption_register,
+        }
+    }
 }
 
 #[account]
@@ -273,15 +677,81 @@ pub struct EnergyMeter {
 pub struct MeterReading {
     #[max_len(64)]
     pub meter_id: String,
+    pub meter: Pubkey,
+    /// Payer of the reading's rent, refunded when it is closed
+    pub submitter: Pubkey,
     pub reading_value: u64,
     pub reading_type: ReadingType,
     pub timestamp: i64,
     #[max_len(128)]
     pub signature: Vec<u8>,
     pub is_verified: bool,
+    /// Time of the meter's previous reading; this reading covers `(period_start, timestamp]`
+    pub period_start: i64,
+    /// The meter's production register after this reading
+    pub production_register: u64,
+    /// The meter's consumption register after this reading
+    pub consumption_register: u64,
+}
+
+impl MeterReading {
+    /// The meter's register of `reading_type` at `time`, if this reading's period covers it
+    ///
+    /// The reading's value is spread evenly across its period.
+    pub fn register_at(&self, reading_type: ReadingType, time: i64) -> Option<u64> {
+        if time <= self.period_start || time > self.timestamp {
+            return None;
+        }
+        let register = match reading_type {
+            ReadingType::Production => self.production_register,
+            ReadingType::Consumption => self.consumption_register,
+        };
+        if reading_type != self.reading_type {
+            return Some(register);
+        }
+
+        let elapsed = (time - self.period_start) as u128;
+        let period = (self.timestamp - self.period_start) as u128;
+        let accrued = (self.reading_value as u128) * elapsed / period;
+        Some(register - self.reading_value + accrued as u64)
+    }
+}
+
+/// A published reference price, such as the utility's grid tariff
+#[account]
+#[derive(InitSpace)]
+pub struct ReferencePrice {
+    pub oracle: Pubkey,
+    #[max_len(32)]
+    pub name: String,
+    pub price_per_kwh: u64,
+    pub updated_at: i64,
+    pub bump: u8,
+}
+
+/// A wallet trading on behalf of a meter owner
+#[account]
+#[derive(InitSpace)]
+pub struct WalletLink {
+    pub owner: Pubkey,
+    pub wallet: Pubkey,
+    /// Meter the owner proved ownership of when linking
+    pub meter: Pubkey,
+    pub linked_at: i64,
+    pub bump: u8,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+/// A gateway the oracle authority trusts to submit meter readings
+#[account]
+#[derive(InitSpace)]
+pub struct Gateway {
+    pub oracle: Pubkey,
+    pub gateway: Pubkey,
+    pub added_at: i64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
 pub enum MeterType {
     Solar,
     Wind,
@@ -290,7 +760,7 @@ pub enum MeterType {
     Consumption,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
 pub enum ReadingType {
     Production,
     Consumption,
@@ -325,6 +795,18 @@ pub struct ReadingSubmittedEvent {
     pub verified: bool,
 }
 
+#[event]
+pub struct ReadingClosedEvent {
+    #[index]
+    pub meter_id: String,
+    pub meter: Pubkey,
+    pub reading: Pubkey,
+    pub reading_value: u64,
+    pub reading_type: ReadingType,
+    pub reading_timestamp: i64,
+    pub timestamp: i64,
+}
+
 #[event]
 pub struct MeterAuthUpdatedEvent {
     #[index]
@@ -333,6 +815,53 @@ pub struct MeterAuthUpdatedEvent {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct ReferencePriceUpdatedEvent {
+    #[index]
+    pub name: String,
+    pub price_per_kwh: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityProposedEvent {
+    pub oracle: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityTransferredEvent {
+    pub oracle: Pubkey,
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GatewayUpdatedEvent {
+    pub oracle: Pubkey,
+    pub gateway: Pubkey,
+    pub is_authorized: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WalletLinkedEvent {
+    pub owner: Pubkey,
+    pub wallet: Pubkey,
+    pub meter: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WalletUnlinkedEvent {
+    pub owner: Pubkey,
+    pub wallet: Pubkey,
+    pub timestamp: i64,
+}
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("Invalid meter ID")]
@@ -351,4 +880,61 @@ pub enum ErrorCode {
     ReadingTooFrequent,
     #[msg("Unauthorized access")]
     Unauthorized,
+    #[msg("Invalid price feed name")]
+    InvalidPriceFeed,
+    #[msg("Invalid price")]
+    InvalidPrice,
+    #[msg("Invalid wallet link")]
+    InvalidWalletLink,
+    #[msg("Reading is still within its archival period")]
+    ReadingNotArchived,
+    #[msg("Arithmetic overflow")]
+    Overflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading() -> MeterReading {
+        MeterReading {
+            meter_id: "meter-1".to_string(),
+            meter: Pubkey::new_unique(),
+            submitter: Pubkey::new_unique(),
+            reading_value: 90,
+            reading_type: ReadingType::Production,
+            timestamp: 1_300,
+            signature: Vec::new(),
+            is_verified: true,
+            period_start: 1_000,
+            production_register: 590,
+            consumption_register: 200,
+        }
+    }
+
+    #[test]
+    fn register_at_spreads_the_reading_across_its_period() {
+        let reading = reading();
+        assert_eq!(reading.register_at(ReadingType::Production, 1_100), Some(530));
+        assert_eq!(reading.register_at(ReadingType::Production, 1_200), Some(560));
+        assert_eq!(reading.register_at(ReadingType::Production, 1_300), Some(590));
+        // Partial kWh accrued so far are rounded down
+        assert_eq!(reading.register_at(ReadingType::Production, 1_001), Some(500));
+    }
+
+    #[test]
+    fn register_at_only_covers_the_readings_period() {
+        let reading = reading();
+        assert_eq!(reading.register_at(ReadingType::Production, 1_000), None);
+        assert_eq!(reading.register_at(ReadingType::Production, 999), None);
+        assert_eq!(reading.register_at(ReadingType::Production, 1_301), None);
+    }
+
+    #[test]
+    fn register_at_holds_the_other_register_steady() {
+        let reading = reading();
+        assert_eq!(reading.register_at(ReadingType::Consumption, 1_001), Some(200));
+        assert_eq!(reading.register_at(ReadingType::Consumption, 1_300), Some(200));
+        assert_eq!(reading.register_at(ReadingType::Consumption, 1_000), None);
+    }
 }
@@ -0,0 +1,330 @@
+//! Account validation of the oracle instructions when an account is substituted
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Once;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::{ProgramResult, SUCCESS};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use anchor_lang::system_program;
+use energy_oracle::{
+    AddGateway, EnergyMeter, Gateway, LinkWallet, MeterReading, MeterType, Oracle,
+    RemoveGateway, SubmitReading, UpdateMeterAuth, WalletLink,
+};
+
+/// Stands in for the runtime pieces account validation uses: the rent sysvar and
+/// the system program's `create_account`
+struct Runtime;
+
+impl SyscallStubs for Runtime {
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        SUCCESS
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        _signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        assert_eq!(instruction.program_id, system_program::ID);
+        let account = |index: usize| {
+            let key = instruction.accounts[index].pubkey;
+            account_infos.iter().find(|info| *info.key == key).unwrap()
+        };
+        let data = &instruction.data;
+        assert_eq!(data[..4], [0, 0, 0, 0], "only create_account is supported");
+        let lamports = u64::from_le_bytes(data[4..12].try_into().unwrap());
+        let space = u64::from_le_bytes(data[12..20].try_into().unwrap());
+        assert_eq!(account(1).data_len() as u64, space);
+        **account(0).try_borrow_mut_lamports()? -= lamports;
+        **account(1).try_borrow_mut_lamports()? += lamports;
+        account(1).assign(&Pubkey::try_from(&data[20..52]).unwrap());
+        Ok(())
+    }
+}
+
+struct TestAccount {
+    key: Pubkey,
+    owner: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+    is_signer: bool,
+    executable: bool,
+}
+
+impl TestAccount {
+    fn new(owner: Pubkey, data: Vec<u8>) -> Self {
+        Self {
+            key: Pubkey::new_unique(),
+            owner,
+            lamports: 1_000_000_000,
+            data,
+            is_signer: false,
+            executable: false,
+        }
+    }
+
+    fn wallet() -> Self {
+        Self::new(system_program::ID, Vec::new())
+    }
+
+    fn signer() -> Self {
+        Self { is_signer: true, ..Self::wallet() }
+    }
+
+    fn program(id: Pubkey) -> Self {
+        Self { key: id, executable: true, ..Self::new(Pubkey::default(), Vec::new()) }
+    }
+
+    /// Unfunded address an instruction initializes
+    fn uninitialized(key: Pubkey, space: usize) -> Self {
+        Self { key, lamports: 0, ..Self::new(system_program::ID, vec![0; space]) }
+    }
+
+    fn anchor<T: AccountSerialize + Owner>(value: &T) -> Self {
+        let mut data = Vec::new();
+        value.try_serialize(&mut data).unwrap();
+        Self::new(T::owner(), data)
+    }
+
+    fn update<T: AccountSerialize + AccountDeserialize>(&mut self, change: impl FnOnce(&mut T)) {
+        let mut value = T::try_deserialize(&mut &self.data[..]).unwrap();
+        change(&mut value);
+        self.data.clear();
+        value.try_serialize(&mut self.data).unwrap();
+    }
+
+    fn info(&mut self) -> AccountInfo<'_> {
+        AccountInfo::new(
+            &self.key,
+            self.is_signer,
+            true,
+            &mut self.lamports,
+            &mut self.data,
+            &self.owner,
+            self.executable,
+            0,
+        )
+    }
+}
+
+fn try_accounts<'info, T: Accounts<'info>>(
+    accounts: &'info mut [TestAccount],
+    ix_data: &[u8],
+) -> Result<()> {
+    static RUNTIME: Once = Once::new();
+    RUNTIME.call_once(|| {
+        set_syscall_stubs(Box::new(Runtime));
+    });
+
+    let infos: Vec<AccountInfo<'info>> = accounts.iter_mut().map(TestAccount::info).collect();
+    T::try_accounts(
+        &energy_oracle::ID,
+        &mut &infos[..],
+        ix_data,
+        &mut BTreeMap::new(),
+        &mut BTreeSet::new(),
+    )
+    .map(|_| ())
+}
+
+fn assert_rejected(result: Result<()>, expected: impl Into<u32>) {
+    match result {
+        Ok(()) => panic!("substituted account was accepted"),
+        Err(err) => assert_eq!(ProgramError::from(err), ProgramError::Custom(expected.into())),
+    }
+}
+
+fn gateway_address(oracle: &Pubkey, gateway: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"gateway", oracle.as_ref(), gateway.as_ref()],
+        &energy_oracle::ID,
+    )
+}
+
+/// Oracle with one meter and one authorised gateway
+struct Network {
+    oracle: TestAccount,
+    authority: TestAccount,
+    meter: TestAccount,
+    meter_owner: TestAccount,
+    gateway: TestAccount,
+    submitter: TestAccount,
+}
+
+impl Network {
+    fn new() -> Self {
+        let authority = TestAccount::signer();
+        let oracle = TestAccount::anchor(&Oracle {
+            authority: authority.key,
+            pending_authority: Pubkey::default(),
+            total_meters: 1,
+            total_readings: 0,
+            is_active: true,
+        });
+        let meter_owner = TestAccount::signer();
+        let meter = TestAccount::anchor(&EnergyMeter {
+            meter_id: "MTR-001".to_string(),
+            meter_type: MeterType::Solar,
+            location: "Feeder 4".to_string(),
+            owner: meter_owner.key,
+            oracle: oracle.key,
+            is_authorized: true,
+            registered_at: 1_700_000_000,
+            last_reading_at: 0,
+            total_readings: 0,
+            production_register: 0,
+            consumption_register: 0,
+        });
+        let submitter = TestAccount::signer();
+        let (gateway_key, bump) = gateway_address(&oracle.key, &submitter.key);
+        let gateway = TestAccount {
+            key: gateway_key,
+            ..TestAccount::anchor(&Gateway {
+                oracle: oracle.key,
+                gateway: submitter.key,
+                added_at: 1_700_000_000,
+                bump,
+            })
+        };
+        Self { oracle, authority, meter, meter_owner, gateway, submitter }
+    }
+
+    fn submit_reading(self) -> Result<()> {
+        let reading = Pubkey::new_unique();
+        let mut accounts = [
+            TestAccount {
+                is_signer: true,
+                ..TestAccount::uninitialized(reading, 8 + MeterReading::INIT_SPACE)
+            },
+            self.meter,
+            self.oracle,
+            self.gateway,
+            self.submitter,
+            TestAccount::program(system_program::ID),
+        ];
+        try_accounts::<SubmitReading>(&mut accounts, &[])
+    }
+
+    fn add_gateway(self, gateway: Pubkey) -> Result<()> {
+        let (gateway_account, _) = gateway_address(&self.oracle.key, &gateway);
+        let mut accounts = [
+            TestAccount::uninitialized(gateway_account, 8 + Gateway::INIT_SPACE),
+            self.oracle,
+            self.authority,
+            TestAccount::program(system_program::ID),
+        ];
+        try_accounts::<AddGateway>(&mut accounts, &gateway.to_bytes())
+    }
+
+    fn remove_gateway(self) -> Result<()> {
+        let mut accounts = [self.gateway, self.oracle, self.authority];
+        try_accounts::<RemoveGateway>(&mut accounts, &[])
+    }
+
+    fn update_meter_authorization(self) -> Result<()> {
+        let mut accounts = [self.meter, self.oracle, self.authority];
+        try_accounts::<UpdateMeterAuth>(&mut accounts, &[])
+    }
+
+    fn link_wallet(self, owner: TestAccount) -> Result<()> {
+        let wallet = TestAccount::signer();
+        let link_address = |wallet: &Pubkey| {
+            Pubkey::find_program_address(&[b"wallet_link", wallet.as_ref()], &energy_oracle::ID).0
+        };
+        let mut accounts = [
+            TestAccount::uninitialized(link_address(&wallet.key), 8 + WalletLink::INIT_SPACE),
+            TestAccount::uninitialized(link_address(&owner.key), 0),
+            self.meter,
+            owner,
+            wallet,
+            TestAccount::program(system_program::ID),
+        ];
+        try_accounts::<LinkWallet>(&mut accounts, &[])
+    }
+}
+
+#[test]
+fn submit_reading_accepts_authorised_gateway() {
+    Network::new().submit_reading().unwrap();
+}
+
+#[test]
+fn submit_reading_rejects_meter_owner_without_gateway() {
+    let network = Network::new();
+    let (gateway_key, _) = gateway_address(&network.oracle.key, &network.meter_owner.key);
+    let network = Network {
+        submitter: network.meter_owner,
+        gateway: TestAccount::uninitialized(gateway_key, 0),
+        meter_owner: TestAccount::signer(),
+        ..network
+    };
+    assert_rejected(network.submit_reading(), ErrorCode::AccountNotInitialized);
+}
+
+#[test]
+fn submit_reading_rejects_gateway_of_another_wallet() {
+    let network = Network { submitter: TestAccount::signer(), ..Network::new() };
+    assert_rejected(network.submit_reading(), ErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn submit_reading_rejects_gateway_of_another_oracle() {
+    let network = Network::new();
+    let mut foreign = Network::new();
+    foreign.gateway.update(|gateway: &mut Gateway| gateway.gateway = network.submitter.key);
+    let network = Network { gateway: foreign.gateway, ..network };
+    assert_rejected(network.submit_reading(), ErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn submit_reading_rejects_meter_of_another_oracle() {
+    let network = Network { meter: Network::new().meter, ..Network::new() };
+    assert_rejected(network.submit_reading(), ErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn add_gateway_accepts_oracle_authority() {
+    Network::new().add_gateway(Pubkey::new_unique()).unwrap();
+}
+
+#[test]
+fn add_gateway_rejects_signer_other_than_authority() {
+    let network = Network { authority: TestAccount::signer(), ..Network::new() };
+    let result = network.add_gateway(Pubkey::new_unique());
+    assert_rejected(result, energy_oracle::ErrorCode::Unauthorized);
+}
+
+#[test]
+fn remove_gateway_rejects_signer_other_than_authority() {
+    let network = Network { authority: TestAccount::signer(), ..Network::new() };
+    assert_rejected(network.remove_gateway(), energy_oracle::ErrorCode::Unauthorized);
+}
+
+#[test]
+fn remove_gateway_rejects_gateway_of_another_oracle() {
+    let network = Network { gateway: Network::new().gateway, ..Network::new() };
+    assert_rejected(network.remove_gateway(), ErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn update_meter_authorization_rejects_meter_of_another_oracle() {
+    let network = Network { meter: Network::new().meter, ..Network::new() };
+    assert_rejected(network.update_meter_authorization(), ErrorCode::ConstraintHasOne);
+}
+
+#[test]
+fn link_wallet_accepts_meter_owner() {
+    let network = Network::new();
+    let owner = TestAccount { key: network.meter_owner.key, ..TestAccount::signer() };
+    network.link_wallet(owner).unwrap();
+}
+
+#[test]
+fn link_wallet_rejects_owner_of_another_meter() {
+    let network = Network::new();
+    assert_rejected(network.link_wallet(TestAccount::signer()), ErrorCode::ConstraintHasOne);
+}